@@ -0,0 +1,184 @@
+use syn::{Attribute, LitStr};
+
+/// How a method is exposed by `com_object!`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Member {
+    /// A property read with `#[com(get)]`.
+    Get,
+    /// A property written with `#[com(set)]`.
+    Set,
+    /// A method called with `#[com(method)]`.
+    Method,
+}
+
+/// Whether calling a member of a `com_shim!` class changes anything.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Effect {
+    /// A member marked `#[com(idempotent)]`.
+    Idempotent,
+    /// A member marked `#[com(mutating)]`.
+    Mutating,
+}
+
+/// How an enum deriving `ComVariant` is stored.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Repr {
+    /// As an `i32`, with `#[com(repr = "i32")]`.
+    I32,
+    /// As the name of each variant, with `#[com(repr = "string")]`.
+    String,
+}
+
+/// Every option of `#[com(...)]` attributes.
+const OPTIONS: &[&str] = &[
+    "rename",
+    "name",
+    "optional",
+    "nested",
+    "get",
+    "set",
+    "method",
+    "remote",
+    "no_retry",
+    "idempotent",
+    "mutating",
+    "repr",
+];
+
+/// Where `#[com(...)]` attributes are written, which decides the options they accept.
+#[derive(Clone, Copy)]
+pub(crate) enum Context {
+    /// A class declared with `com_shim!`.
+    Class,
+    /// A function or property of a `com_shim!` class.
+    ClassMember,
+    /// A method in a `com_object!` block.
+    ObjectMethod,
+    /// A struct deriving `ComVariant` or `FromDispatch`.
+    Struct,
+    /// An enum deriving `ComVariant`.
+    Enum,
+    /// A variant of an enum deriving `ComVariant`.
+    EnumVariant,
+    /// A field of a struct deriving `FromDispatch`.
+    Field,
+}
+
+impl Context {
+    /// The options accepted here.
+    fn options(self) -> &'static [&'static str] {
+        match self {
+            Self::Class => &["remote"],
+            Self::ClassMember => &["no_retry", "idempotent", "mutating"],
+            Self::ObjectMethod => &["rename", "name", "get", "set", "method"],
+            Self::Struct => &[],
+            Self::Enum => &["repr"],
+            Self::EnumVariant => &["rename", "name"],
+            Self::Field => &["rename", "name", "optional", "nested"],
+        }
+    }
+
+    /// What is written here, to name in errors.
+    fn description(self) -> &'static str {
+        match self {
+            Self::Class => "`com_shim!` classes",
+            Self::ClassMember => "`com_shim!` members",
+            Self::ObjectMethod => "`com_object!` methods",
+            Self::Struct => "structs",
+            Self::Enum => "enums",
+            Self::EnumVariant => "enum variants",
+            Self::Field => "`FromDispatch` fields",
+        }
+    }
+}
+
+/// The options given in `#[com(...)]` attributes.
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)] // independent flags, as they are written
+pub(crate) struct ComAttributes {
+    /// The COM name, if it differs from the Rust name, given by `rename` or `name`.
+    pub(crate) rename: Option<LitStr>,
+    /// Whether a missing property is read as the default value.
+    pub(crate) optional: bool,
+    /// Whether a property is an object to read with `FromDispatch`.
+    pub(crate) nested: bool,
+    /// How a method is exposed, if it is.
+    pub(crate) member: Option<Member>,
+    /// Whether a class can be called through a handle to it on a `ComThread`.
+    pub(crate) remote: bool,
+    /// Whether calls to a member are never retried.
+    pub(crate) no_retry: bool,
+    /// Whether calling a member changes anything, if it is given.
+    pub(crate) effect: Option<Effect>,
+    /// How an enum is stored, if it is given.
+    pub(crate) repr: Option<Repr>,
+}
+
+impl ComAttributes {
+    /// Parse the `#[com(...)]` attributes written in `context`, rejecting options that do not
+    /// apply there.
+    pub(crate) fn parse(attributes: &[Attribute], context: Context) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attribute in attributes.iter().filter(|a| a.path().is_ident("com")) {
+            attribute.parse_nested_meta(|meta| {
+                let name = meta.path.get_ident().map(ToString::to_string);
+                if let Some(name) = name.filter(|name| OPTIONS.contains(&name.as_str()))
+                    && !context.options().contains(&name.as_str())
+                {
+                    return Err(meta.error(format!(
+                        "`{name}` cannot be used on {}",
+                        context.description()
+                    )));
+                }
+                if meta.path.is_ident("rename") || meta.path.is_ident("name") {
+                    options.rename = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("optional") {
+                    options.optional = true;
+                } else if meta.path.is_ident("nested") {
+                    options.nested = true;
+                } else if meta.path.is_ident("remote") {
+                    options.remote = true;
+                } else if meta.path.is_ident("no_retry") {
+                    options.no_retry = true;
+                } else if meta.path.is_ident("repr") {
+                    let repr: LitStr = meta.value()?.parse()?;
+                    options.repr = Some(match repr.value().as_str() {
+                        "i32" => Repr::I32,
+                        "string" => Repr::String,
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                repr,
+                                "expected `\"i32\"` or `\"string\"`",
+                            ));
+                        }
+                    });
+                } else if let Some(effect) = [
+                    ("idempotent", Effect::Idempotent),
+                    ("mutating", Effect::Mutating),
+                ]
+                .into_iter()
+                .find_map(|(name, effect)| meta.path.is_ident(name).then_some(effect))
+                {
+                    if options.effect.replace(effect).is_some() {
+                        return Err(meta.error("expected only one of `idempotent` or `mutating`"));
+                    }
+                } else if let Some(member) = [
+                    ("get", Member::Get),
+                    ("set", Member::Set),
+                    ("method", Member::Method),
+                ]
+                .into_iter()
+                .find_map(|(name, member)| meta.path.is_ident(name).then_some(member))
+                {
+                    if options.member.replace(member).is_some() {
+                        return Err(meta.error("expected only one of `get`, `set` or `method`"));
+                    }
+                } else {
+                    return Err(meta.error("unknown `com` attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
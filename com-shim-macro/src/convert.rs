@@ -0,0 +1,221 @@
+use proc_macro2::{Literal, TokenStream};
+use quote::{ToTokens, quote};
+use syn::{Data, DataEnum, DeriveInput, Expr, Fields, Generics, Type, parse_quote};
+
+use crate::attributes::{ComAttributes, Context, Repr};
+
+/// The single field of a newtype.
+struct Newtype<'a> {
+    /// The field, as a member of `self`.
+    member: TokenStream,
+    /// A constructor taking the value of the field from `value`.
+    constructor: TokenStream,
+    ty: &'a Type,
+}
+
+impl<'a> Newtype<'a> {
+    /// Parse a newtype, failing with `message` if the input is not a struct with a single field.
+    fn parse(input: &'a DeriveInput, message: &str) -> syn::Result<Self> {
+        let error = || syn::Error::new(input.ident.span(), message);
+        let Data::Struct(data) = &input.data else {
+            return Err(error());
+        };
+        match &data.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Ok(Self {
+                member: quote!(0),
+                constructor: quote!(Self(value)),
+                ty: &fields.unnamed[0].ty,
+            }),
+            Fields::Named(fields) if fields.named.len() == 1 => {
+                let ident = fields.named[0].ident.as_ref().ok_or_else(error)?;
+                Ok(Self {
+                    member: ident.to_token_stream(),
+                    constructor: quote!(Self { #ident: value }),
+                    ty: &fields.named[0].ty,
+                })
+            }
+            _ => Err(error()),
+        }
+    }
+
+    fn impl_from_variant(&self, input: &DeriveInput) -> TokenStream {
+        let Newtype {
+            constructor, ty, ..
+        } = self;
+        let ident = &input.ident;
+        let generics = bounded(&input.generics, ty, &quote!(::com_shim::FromVariant));
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics ::com_shim::FromVariant for #ident #ty_generics #where_clause {
+                fn from_variant(variant: &::com_shim::VARIANT) -> ::com_shim::Result<Self> {
+                    let value = <#ty as ::com_shim::FromVariant>::from_variant(variant)?;
+                    ::std::result::Result::Ok(#constructor)
+                }
+            }
+        }
+    }
+
+    fn impl_to_variant(&self, input: &DeriveInput) -> TokenStream {
+        let Newtype { member, ty, .. } = self;
+        let ident = &input.ident;
+        let generics = bounded(&input.generics, ty, &quote!(::com_shim::ToVariant));
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics ::com_shim::ToVariant for #ident #ty_generics #where_clause {
+                fn to_variant(&self) -> ::com_shim::Variant {
+                    <#ty as ::com_shim::ToVariant>::to_variant(&self.#member)
+                }
+            }
+        }
+    }
+}
+
+/// Add a bound on the field type to the generics of the newtype.
+fn bounded(generics: &Generics, ty: &Type, bound: &TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote!(#ty: #bound));
+    generics
+}
+
+pub(crate) fn from_variant(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let newtype = Newtype::parse(
+        input,
+        "`FromVariant` can only be derived for structs with a single field",
+    )?;
+    Ok(newtype.impl_from_variant(input))
+}
+
+pub(crate) fn to_variant(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let newtype = Newtype::parse(
+        input,
+        "`ToVariant` can only be derived for structs with a single field",
+    )?;
+    Ok(newtype.impl_to_variant(input))
+}
+
+pub(crate) fn com_variant(input: &DeriveInput) -> syn::Result<TokenStream> {
+    if let Data::Enum(data) = &input.data {
+        return enumeration(input, data);
+    }
+    let newtype = Newtype::parse(
+        input,
+        "`ComVariant` can only be derived for structs with a single field, or enums of unit variants",
+    )?;
+    ComAttributes::parse(&input.attrs, Context::Struct)?;
+    let mut tokens = newtype.impl_from_variant(input);
+    tokens.extend(newtype.impl_to_variant(input));
+    Ok(tokens)
+}
+
+/// How an enum is stored, which must be chosen with `#[com(repr = "...")]`.
+fn repr(input: &DeriveInput) -> syn::Result<Repr> {
+    ComAttributes::parse(&input.attrs, Context::Enum)?
+        .repr
+        .ok_or_else(|| {
+            syn::Error::new(
+                input.ident.span(),
+                "`ComVariant` enums need `#[com(repr = \"i32\")]` or `#[com(repr = \"string\")]`",
+            )
+        })
+}
+
+/// Enums are stored as chosen with `repr`, either as an `i32` numbered as Rust numbers them, or as
+/// the name of each variant unless renamed.
+fn enumeration(input: &DeriveInput, data: &DataEnum) -> syn::Result<TokenStream> {
+    let integer = repr(input)? == Repr::I32;
+
+    let mut idents = vec![];
+    let mut values = vec![];
+    let mut last: Option<(&Expr, usize)> = None;
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                &variant.fields,
+                "`ComVariant` can only be derived for enums of unit variants",
+            ));
+        }
+        let ComAttributes { rename, .. } =
+            ComAttributes::parse(&variant.attrs, Context::EnumVariant)?;
+        let value = if integer {
+            if let Some(rename) = rename {
+                return Err(syn::Error::new_spanned(
+                    rename,
+                    "variants of integer-valued enums cannot be renamed",
+                ));
+            }
+            // Without a discriminant, a variant follows on from the one before
+            last = match (&variant.discriminant, last) {
+                (Some((_, expr)), _) => Some((expr, 0)),
+                (None, Some((expr, offset))) => Some((expr, offset + 1)),
+                (None, None) => None,
+            };
+            match last {
+                Some((expr, 0)) => quote!((#expr)),
+                Some((expr, offset)) => {
+                    let offset = Literal::usize_unsuffixed(offset);
+                    quote!((#expr) + #offset)
+                }
+                None => quote!(0),
+            }
+        } else {
+            if let Some((_, discriminant)) = &variant.discriminant {
+                return Err(syn::Error::new_spanned(
+                    discriminant,
+                    "variants of string-valued enums cannot have a discriminant",
+                ));
+            }
+            let name = rename.map_or_else(|| variant.ident.to_string(), |r| r.value());
+            quote!(#name)
+        };
+        idents.push(&variant.ident);
+        values.push(value);
+    }
+
+    let (ty, read) = if integer {
+        (
+            quote!(i32),
+            quote! {
+                let value = <i32 as ::com_shim::FromVariant>::from_variant(variant)?;
+                #(
+                    if value == #values {
+                        return ::std::result::Result::Ok(Self::#idents);
+                    }
+                )*
+            },
+        )
+    } else {
+        (
+            quote!(&str),
+            quote! {
+                let value = <::std::string::String as ::com_shim::FromVariant>::from_variant(variant)?;
+                match value.as_str() {
+                    #(#values => return ::std::result::Result::Ok(Self::#idents),)*
+                    _ => (),
+                }
+            },
+        )
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::com_shim::FromVariant for #ident #ty_generics #where_clause {
+            fn from_variant(variant: &::com_shim::VARIANT) -> ::com_shim::Result<Self> {
+                #read
+                ::std::result::Result::Err(::com_shim::__private::unknown_value())
+            }
+        }
+
+        impl #impl_generics ::com_shim::ToVariant for #ident #ty_generics #where_clause {
+            fn to_variant(&self) -> ::com_shim::Variant {
+                let value: #ty = match self {
+                    #(Self::#idents => #values,)*
+                };
+                ::com_shim::ToVariant::to_variant(&value)
+            }
+        }
+    })
+}
@@ -0,0 +1,88 @@
+use heck::ToUpperCamelCase;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields};
+
+use crate::attributes::{ComAttributes, Context};
+
+pub(crate) fn from_dispatch(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "`FromDispatch` can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "`FromDispatch` can only be derived for structs with named fields",
+            ));
+        }
+    };
+
+    ComAttributes::parse(&input.attrs, Context::Struct)?;
+
+    let mut idents = vec![];
+    let mut values = vec![];
+    for field in fields {
+        let ident = field
+            .ident
+            .as_ref()
+            .expect("named fields have an identifier");
+        let ty = &field.ty;
+        let ComAttributes {
+            rename,
+            optional,
+            nested,
+            ..
+        } = ComAttributes::parse(&field.attrs, Context::Field)?;
+        let name = rename.map_or_else(|| ident.to_string().to_upper_camel_case(), |r| r.value());
+
+        let convert = if nested {
+            quote! {
+                let object = <::com_shim::IDispatch as ::com_shim::FromVariant>::from_variant(&value)?;
+                <#ty as ::com_shim::FromDispatch>::from_dispatch(&object)?
+            }
+        } else {
+            quote!(<#ty as ::com_shim::FromVariant>::from_variant(&value)?)
+        };
+        let value = if optional {
+            quote! {
+                match object.get(#name) {
+                    ::std::result::Result::Ok(value) => { #convert }
+                    ::std::result::Result::Err(e) if ::com_shim::__private::is_missing_member(&e) => {
+                        ::std::default::Default::default()
+                    }
+                    ::std::result::Result::Err(e) => return ::std::result::Result::Err(e),
+                }
+            }
+        } else {
+            quote! {{
+                let value = object.get(#name)?;
+                #convert
+            }}
+        };
+        idents.push(ident);
+        values.push(value);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::com_shim::FromDispatch for #ident #ty_generics #where_clause {
+            fn from_dispatch(object: &impl ::com_shim::HasIDispatch) -> ::com_shim::Result<Self> {
+                use ::com_shim::IDispatchExt;
+                ::com_shim::__private::object_scope(object.conversion(), || {
+                    let object = object.get_idispatch();
+                    ::std::result::Result::Ok(Self {
+                        #(#idents: #values,)*
+                    })
+                })
+            }
+        }
+    })
+}
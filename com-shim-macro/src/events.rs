@@ -0,0 +1,244 @@
+use heck::ToSnakeCase;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    Attribute, Ident, LitInt, LitStr, Token, Type,
+    ext::IdentExt,
+    parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+};
+
+/// An outgoing interface, declared as a trait of events.
+pub(crate) struct Events {
+    attributes: Vec<Attribute>,
+    ident: Ident,
+    iid: LitStr,
+    members: Punctuated<Event, Token![,]>,
+}
+
+impl Parse for Events {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attributes = Attribute::parse_outer(input)?;
+        let _: Token![trait] = input.parse()?;
+        let ident: Ident = input.parse()?;
+        let iid;
+        parenthesized!(iid in input);
+        let iid = iid.parse()?;
+        let content;
+        syn::braced!(content in input);
+        let members = content.parse_terminated(Event::parse, Token![,])?;
+
+        Ok(Self {
+            attributes,
+            ident,
+            iid,
+            members,
+        })
+    }
+}
+
+/// An event, with the types of its arguments and, optionally, its `DISPID`.
+struct Event {
+    attributes: Vec<Attribute>,
+    ident: Ident,
+    parameters: Punctuated<Type, Token![,]>,
+    dispid: Option<(bool, LitInt)>,
+}
+
+impl Parse for Event {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attributes = Attribute::parse_outer(input)?;
+        let _: Token![fn] = input.parse()?;
+        let ident: Ident = input.parse()?;
+        let parameters;
+        parenthesized!(parameters in input);
+        let parameters = parameters.parse_terminated(Type::parse, Token![,])?;
+        let dispid = if input.peek(Token![=]) {
+            let _: Token![=] = input.parse()?;
+            let negative = input.parse::<Option<Token![-]>>()?.is_some();
+            Some((negative, input.parse()?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            attributes,
+            ident,
+            parameters,
+            dispid,
+        })
+    }
+}
+
+impl Event {
+    /// Generate the handler method, enum variant, method sending the variant to a stream, and match
+    /// arm raising the event, for this event.
+    fn expand(&self, dispid: i32, event_ident: &Ident) -> [TokenStream; 4] {
+        let Event {
+            attributes,
+            ident,
+            parameters,
+            ..
+        } = self;
+        let method = Ident::new(&ident.unraw().to_string().to_snake_case(), ident.span());
+        let variant = Ident::new(&ident.unraw().to_string(), ident.span());
+        let arguments = (0..parameters.len())
+            .map(|idx| Ident::new(&format!("p{idx}"), Span::call_site()))
+            .collect::<Vec<_>>();
+        let indices = 0..parameters.len();
+        let (fields, values) = if parameters.is_empty() {
+            (quote!(), quote!())
+        } else {
+            (quote!((#parameters)), quote!((#(#arguments),*)))
+        };
+        let parameters = parameters.iter().collect::<Vec<_>>();
+        [
+            quote! {
+                #(#attributes)*
+                fn #method(&self, #(#arguments: #parameters),*) -> ::com_shim::Result<()> {
+                    let _ = (#(#arguments,)*);
+                    ::std::result::Result::Ok(())
+                }
+            },
+            quote! {
+                #(#attributes)*
+                #variant #fields,
+            },
+            quote! {
+                fn #method(&self, #(#arguments: #parameters),*) -> ::com_shim::Result<()> {
+                    self.send(#event_ident::#variant #values);
+                    ::std::result::Result::Ok(())
+                }
+            },
+            quote! {
+                #dispid => handler.#method(#(::com_shim::__private::argument(params, #indices)?),*),
+            },
+        ]
+    }
+}
+
+/// Parse a GUID in the registry format, with or without braces.
+fn parse_guid(iid: &LitStr) -> syn::Result<u128> {
+    let value = iid.value();
+    let trimmed = value.trim_start_matches('{').trim_end_matches('}');
+    let groups = trimmed.split('-').map(str::len).collect::<Vec<_>>();
+    let hex = trimmed.replace('-', "");
+    if groups != [8, 4, 4, 4, 12] || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(syn::Error::new(
+            iid.span(),
+            "expected an IID such as \"{00020400-0000-0000-C000-000000000046}\"",
+        ));
+    }
+    u128::from_str_radix(&hex, 16).map_err(|e| syn::Error::new(iid.span(), e))
+}
+
+pub(crate) fn com_events(events: Events) -> syn::Result<TokenStream> {
+    let Events {
+        attributes,
+        ident,
+        iid,
+        members,
+    } = events;
+    let iid = parse_guid(&iid)?;
+
+    // `SessionEvents` raises a `SessionEvent`
+    let name = ident.to_string();
+    let event_ident = Ident::new(
+        &format!("{}Event", name.strip_suffix("Events").unwrap_or(&name)),
+        ident.span(),
+    );
+    let mut methods = vec![];
+    let mut variants = vec![];
+    let mut sends = vec![];
+    let mut arms = vec![];
+    let mut names = vec![];
+    let mut dispids = vec![];
+    let mut next_dispid = 1_i32;
+    for event in &members {
+        let dispid = match &event.dispid {
+            Some((negative, literal)) => {
+                let value = literal.base10_parse::<i32>()?;
+                if *negative { -value } else { value }
+            }
+            None => next_dispid,
+        };
+        if dispids.contains(&dispid) {
+            return Err(syn::Error::new(
+                event.ident.span(),
+                format!("DISPID {dispid} is already used by another event"),
+            ));
+        }
+        next_dispid = dispid.wrapping_add(1);
+
+        let [method, variant, send, arm] = event.expand(dispid, &event_ident);
+        methods.push(method);
+        variants.push(variant);
+        sends.push(send);
+        arms.push(arm);
+        names.push(event.ident.unraw().to_string());
+        dispids.push(dispid);
+    }
+
+    Ok(quote! {
+        #(#attributes)*
+        pub trait #ident {
+            #(#methods)*
+
+            /// Connect this handler to the events of an object, until the returned connection is
+            /// dropped.
+            ///
+            /// # Errors
+            ///
+            /// Fails if the object does not raise these events.
+            fn advise(
+                self,
+                source: &impl ::com_shim::HasIDispatch,
+            ) -> ::com_shim::Result<::com_shim::EventConnection>
+            where
+                Self: ::std::marker::Sized + 'static,
+            {
+                ::com_shim::EventConnection::advise::<dyn #ident, Self>(source, self)
+            }
+        }
+
+        #[doc = concat!("An event raised through [`", stringify!(#ident), "`].")]
+        pub enum #event_ident {
+            #(#variants)*
+        }
+
+        impl #event_ident {
+            /// Receive the events an object raises as a stream, buffering up to `capacity` of them.
+            ///
+            /// # Errors
+            ///
+            /// Fails if the object does not raise these events.
+            pub fn stream(
+                source: &impl ::com_shim::HasIDispatch,
+                capacity: usize,
+            ) -> ::com_shim::Result<::com_shim::EventStream<Self>> {
+                ::com_shim::EventStream::advise::<dyn #ident>(source, capacity)
+            }
+        }
+
+        impl #ident for ::com_shim::EventSender<#event_ident> {
+            #(#sends)*
+        }
+
+        impl<H: #ident> ::com_shim::EventInterface<H> for dyn #ident {
+            const IID: ::com_shim::GUID = ::com_shim::GUID::from_u128(#iid);
+            const EVENTS: &'static [(&'static str, i32)] = &[#((#names, #dispids)),*];
+
+            fn raise(
+                handler: &H,
+                dispid: i32,
+                params: &::com_shim::__private::DISPPARAMS,
+            ) -> ::com_shim::Result<()> {
+                match dispid {
+                    #(#arms)*
+                    _ => ::std::result::Result::Err(::com_shim::__private::member_not_found()),
+                }
+            }
+        }
+    })
+}
@@ -3,13 +3,23 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+use attributes::{Context, Effect};
 use heck::ToSnakeCase;
 use proc_macro::TokenStream;
+use proc_macro2::Span;
 use quote::{ToTokens, TokenStreamExt, quote};
 use syn::{
-    braced, ext::IdentExt, parenthesized, parse::Parse, parse_macro_input, punctuated::Punctuated, Attribute, Ident, Token
+    Attribute, DeriveInput, Ident, Token, Type, braced, ext::IdentExt, parenthesized, parse::Parse,
+    parse_macro_input, punctuated::Punctuated, spanned::Spanned,
 };
 
+mod attributes;
+mod convert;
+mod dispatch;
+mod events;
+mod object;
+mod remote;
+
 struct Class {
     attributes: Vec<Attribute>,
     ident: Ident,
@@ -54,6 +64,33 @@ enum FunctionOrVariable {
     Variable(Variable),
 }
 
+impl FunctionOrVariable {
+    /// Describe the members generated for this, as listed in `ComClass::MEMBERS`.
+    fn members(&self) -> Vec<proc_macro2::TokenStream> {
+        match self {
+            Self::Function(Function { ident, effect, .. }) => vec![member(
+                &ident.unraw().to_string(),
+                "Method",
+                effect.unwrap_or(Effect::Mutating),
+            )],
+            Self::Variable(Variable {
+                ident,
+                effect,
+                mutable,
+                ..
+            }) => {
+                let name = ident.unraw().to_string();
+                let mut members = vec![member(&name, "Get", effect.unwrap_or(Effect::Idempotent))];
+                // The attributes only describe reading a property, which writing always changes
+                if *mutable {
+                    members.push(member(&name, "Set", Effect::Mutating));
+                }
+                members
+            }
+        }
+    }
+}
+
 impl ToTokens for FunctionOrVariable {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         match self {
@@ -66,21 +103,30 @@ impl ToTokens for FunctionOrVariable {
 impl Parse for FunctionOrVariable {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let attributes = Attribute::parse_outer(input)?;
+        let options = attributes::ComAttributes::parse(&attributes, Context::ClassMember)?;
+        let no_retry = options.no_retry;
+        let effect = options.effect;
+        let attributes = attributes
+            .into_iter()
+            .filter(|a| !a.path().is_ident("com"))
+            .collect();
         if input.peek(Token![fn]) {
             // Parse function next
             let _: Token![fn] = input.parse()?;
             let ident: Ident = input.parse()?;
             let parameters_raw;
             parenthesized!(parameters_raw in input);
-            let parameters = parameters_raw.parse_terminated(Ident::parse, Token![,])?;
+            let parameters = parameters_raw.parse_terminated(Type::parse, Token![,])?;
             let returns = if input.peek(Token![->]) {
                 let _: Token![->] = input.parse()?;
-                Some(input.parse::<Ident>()?)
+                Some(input.parse::<Type>()?)
             } else {
                 None
             };
             Ok(FunctionOrVariable::Function(Function {
                 attributes,
+                no_retry,
+                effect,
                 ident,
                 parameters,
                 returns,
@@ -90,9 +136,11 @@ impl Parse for FunctionOrVariable {
             let _: Token![mut] = input.parse()?;
             let ident: Ident = input.parse()?;
             let _: Token![:] = input.parse()?;
-            let type_: Ident = input.parse()?;
+            let type_: Type = input.parse()?;
             Ok(FunctionOrVariable::Variable(Variable {
                 attributes,
+                no_retry,
+                effect,
                 mutable: true,
                 ident,
                 type_,
@@ -101,9 +149,11 @@ impl Parse for FunctionOrVariable {
             // Parse read-only variable
             let ident: Ident = input.parse()?;
             let _: Token![:] = input.parse()?;
-            let type_: Ident = input.parse()?;
+            let type_: Type = input.parse()?;
             Ok(FunctionOrVariable::Variable(Variable {
                 attributes,
+                no_retry,
+                effect,
                 mutable: false,
                 ident,
                 type_,
@@ -114,15 +164,21 @@ impl Parse for FunctionOrVariable {
 
 struct Variable {
     attributes: Vec<Attribute>,
+    /// Whether calls are never retried, from `#[com(no_retry)]`.
+    no_retry: bool,
+    /// Whether calls change anything, from `#[com(idempotent)]` or `#[com(mutating)]`.
+    effect: Option<attributes::Effect>,
     mutable: bool,
     ident: Ident,
-    type_: Ident,
+    type_: Type,
 }
 
 impl ToTokens for Variable {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let Variable {
             attributes,
+            no_retry,
+            effect,
             mutable,
             ident,
             type_,
@@ -131,22 +187,39 @@ impl ToTokens for Variable {
         let ident_unraw_str = ident.unraw().to_string();
 
         let read_ident = Ident::new(&ident_str.to_snake_case(), ident.span());
+        let get = member_call(
+            *no_retry,
+            &member(
+                &ident_unraw_str,
+                "Get",
+                effect.unwrap_or(Effect::Idempotent),
+            ),
+            &quote!(self.get_idispatch().get(#ident_unraw_str)),
+        );
         tokens.append_all(quote! {
             #(#attributes)*
             fn #read_ident(&self) -> ::com_shim::Result<#type_> {
-                use ::com_shim::{IDispatchExt, VariantTypeExt};
-                ::std::result::Result::Ok(self.get_idispatch().get(#ident_unraw_str)?.variant_into()?)
+                use ::com_shim::IDispatchExt;
+                let value = #get?;
+                ::com_shim::__private::object_scope(self.conversion(), || {
+                    <#type_ as ::com_shim::FromVariant>::from_variant(&value)
+                })
             }
         });
 
         if *mutable {
             let write_ident =
                 Ident::new(&format!("set_{}", ident_str.to_snake_case()), ident.span());
+            let set = member_call(
+                *no_retry,
+                &member(&ident_unraw_str, "Set", Effect::Mutating),
+                &quote!(self.get_idispatch().set(#ident_unraw_str, ::com_shim::ToVariant::to_variant(&value))),
+            );
             tokens.append_all(quote! {
                 #(#attributes)*
                 fn #write_ident(&self, value: #type_) -> ::com_shim::Result<()> {
-                    use ::com_shim::{IDispatchExt, VariantTypeExt};
-                    let _ = self.get_idispatch().set(#ident_unraw_str, ::com_shim::VARIANT::variant_from(value))?;
+                    use ::com_shim::IDispatchExt;
+                    let _ = #set?;
                     ::std::result::Result::Ok(())
                 }
             });
@@ -156,15 +229,21 @@ impl ToTokens for Variable {
 
 struct Function {
     attributes: Vec<Attribute>,
+    /// Whether calls are never retried, from `#[com(no_retry)]`.
+    no_retry: bool,
+    /// Whether calls change anything, from `#[com(idempotent)]` or `#[com(mutating)]`.
+    effect: Option<attributes::Effect>,
     ident: Ident,
-    parameters: Punctuated<Ident, Token![,]>,
-    returns: Option<Ident>,
+    parameters: Punctuated<Type, Token![,]>,
+    returns: Option<Type>,
 }
 
 impl ToTokens for Function {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let Function {
             attributes,
+            no_retry,
+            effect,
             ident,
             parameters,
             returns,
@@ -178,27 +257,78 @@ impl ToTokens for Function {
         });
         let parameters = parameters.iter().enumerate().map(|(idx, p)| {
             let ident = Ident::new(&format!("p{idx}"), p.span());
-            quote!(::com_shim::VARIANT::variant_from(#ident))
+            quote!(::com_shim::ToVariant::to_variant(&#ident))
         });
+        let call = member_call(
+            *no_retry,
+            &member(
+                &ident_unraw_str,
+                "Method",
+                effect.unwrap_or(Effect::Mutating),
+            ),
+            &quote!(self.get_idispatch().call(#ident_unraw_str, vec![#(#parameters),*])),
+        );
         let (returns_type, return_statement) = if let Some(returns) = returns {
-            (quote!(#returns), quote!(r.variant_into()?))
+            (
+                quote!(#returns),
+                quote! {
+                    ::com_shim::__private::object_scope(self.conversion(), || {
+                        <#returns as ::com_shim::FromVariant>::from_variant(&r)
+                    })
+                },
+            )
         } else {
-            (quote!(()), quote!(()))
+            (quote!(()), quote!(::std::result::Result::Ok(())))
         };
         tokens.append_all(quote! {
             #(#attributes)*
             fn #fn_ident(&self, #(#fn_parameters),*) -> ::com_shim::Result<#returns_type> {
-                use ::com_shim::{IDispatchExt, VariantTypeExt};
-                let r = self.get_idispatch().call(#ident_unraw_str, vec![
-                    #(#parameters),*
-                ])?;
-                ::std::result::Result::Ok(#return_statement)
+                use ::com_shim::IDispatchExt;
+                let r = #call?;
+                #return_statement
             }
         });
     }
 }
 
+/// Describe a member of a class, as listed in `ComClass::MEMBERS`.
+fn member(name: &str, kind: &str, effect: Effect) -> proc_macro2::TokenStream {
+    let kind = Ident::new(kind, Span::call_site());
+    let effect = match effect {
+        Effect::Idempotent => quote!(Idempotent),
+        Effect::Mutating => quote!(Mutating),
+    };
+    quote!(::com_shim::Member::new(#name, ::com_shim::MemberKind::#kind, ::com_shim::Effect::#effect))
+}
+
+/// Make a call to a member, without retrying it if it is marked `#[com(no_retry)]`.
+fn member_call(
+    no_retry: bool,
+    member: &proc_macro2::TokenStream,
+    call: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let call = quote!(::com_shim::__private::call_member(#member, self.dry_run(), self.deadline(), || #call));
+    if no_retry {
+        quote!(::com_shim::__private::without_retry(|| #call))
+    } else {
+        call
+    }
+}
+
 /// Generate a COM-compatible class structure.
+///
+/// A class marked `#[com(remote)]` also has a trait named after it, such as `SessionRemote`,
+/// implementing its members for a `Remote` handle to it on a `ComThread`, with an `_async`
+/// variant of each. Every argument and result must implement `Marshal`.
+///
+/// Calls rejected by a busy server are retried by the current `RetryPolicy`, except to members
+/// marked `#[com(no_retry)]`.
+///
+/// Reading a property is taken to change nothing, and writing one or calling a method to change
+/// something, unless the member is marked `#[com(idempotent)]` or `#[com(mutating)]`. On a
+/// property, these describe reading it, and writing it is always taken to change something. This
+/// is listed in `ComClass::MEMBERS`, and in a `DryRun` mutating members are recorded instead of
+/// called, while in a `CallCache` the results of idempotent members are reused.
 #[proc_macro]
 pub fn com_shim(stream: TokenStream) -> TokenStream {
     let Class {
@@ -208,20 +338,45 @@ pub fn com_shim(stream: TokenStream) -> TokenStream {
         functions_and_variables,
     } = parse_macro_input!(stream as Class);
 
-    let functions_and_variables = functions_and_variables.into_iter();
+    let options = match attributes::ComAttributes::parse(&attributes, Context::Class) {
+        Ok(options) => options,
+        Err(error) => return error.into_compile_error().into(),
+    };
+    let attributes = attributes.iter().filter(|a| !a.path().is_ident("com"));
+    let functions_and_variables = functions_and_variables.into_iter().collect::<Vec<_>>();
+    let members = functions_and_variables
+        .iter()
+        .flat_map(FunctionOrVariable::members);
     let self_impl = Ident::new(&format!("{ident}Ext"), ident.span());
-    let inherited_casts = inherited.iter().map(|i| quote! {
-        impl ::com_shim::IsA<#i> for #ident {
-            fn upcast(&self) -> #i {
-                #i::from(self.inner.clone())
+    let remote_trait = options
+        .remote
+        .then(|| remote::remote_trait(&ident, &self_impl, &functions_and_variables));
+    let inherited_casts = inherited.iter().map(|i| {
+        quote! {
+            impl ::com_shim::IsA<#i> for #ident {
+                fn upcast(&self) -> #i {
+                    #i::from(self.inner.clone())
+                }
+
+                fn upcast_ref(&self) -> &#i {
+                    <#i as ::com_shim::ComClass>::from_idispatch_ref(&self.inner)
+                }
+            }
+
+            impl ::std::convert::AsRef<#i> for #ident {
+                fn as_ref(&self) -> &#i {
+                    <Self as ::com_shim::IsA<#i>>::upcast_ref(self)
+                }
             }
         }
     });
     let inherited_impls = inherited
         .iter()
-        .map(|i| Ident::new(&format!("{i}Ext"), i.span()));
+        .map(|i| Ident::new(&format!("{i}Ext"), i.span()))
+        .collect::<Vec<_>>();
     quote! {
         #(#attributes)*
+        #[repr(transparent)]
         pub struct #ident {
             inner: ::com_shim::IDispatch,
         }
@@ -232,6 +387,18 @@ pub fn com_shim(stream: TokenStream) -> TokenStream {
             }
         }
 
+        // SAFETY: this class is `#[repr(transparent)]` over `IDispatch`.
+        #[allow(unsafe_code)]
+        unsafe impl ::com_shim::ComClass for #ident {
+            const MEMBERS: &'static [::com_shim::Member] = &[#(#members),*];
+        }
+
+        impl ::std::convert::AsRef<::com_shim::IDispatch> for #ident {
+            fn as_ref(&self) -> &::com_shim::IDispatch {
+                &self.inner
+            }
+        }
+
         pub trait #self_impl<T: ::com_shim::HasIDispatch = Self>: ::com_shim::HasIDispatch<T> {
             #(#functions_and_variables)*
         }
@@ -240,6 +407,18 @@ pub fn com_shim(stream: TokenStream) -> TokenStream {
 
         #(impl #inherited_impls for #ident {})*
 
+        impl #self_impl for ::com_shim::WithConversion<#ident> {}
+
+        #(impl #inherited_impls for ::com_shim::WithConversion<#ident> {})*
+
+        impl #self_impl for ::com_shim::WithDryRun<#ident> {}
+
+        #(impl #inherited_impls for ::com_shim::WithDryRun<#ident> {})*
+
+        impl #self_impl for ::com_shim::WithDeadline<#ident> {}
+
+        #(impl #inherited_impls for ::com_shim::WithDeadline<#ident> {})*
+
         #(#inherited_casts)*
 
         impl ::std::convert::From<::com_shim::IDispatch> for #ident {
@@ -248,17 +427,108 @@ pub fn com_shim(stream: TokenStream) -> TokenStream {
             }
         }
 
-        impl ::com_shim::VariantTypeExt<'_, #ident> for ::com_shim::VARIANT {
-            fn variant_from(value: #ident) -> ::com_shim::VARIANT {
-                let idisp = &value.inner;
-                ::com_shim::VARIANT::variant_from(idisp)
+        impl ::com_shim::FromVariant for #ident {
+            fn from_variant(variant: &::com_shim::VARIANT) -> ::com_shim::Result<Self> {
+                <::com_shim::IDispatch as ::com_shim::FromVariant>::from_variant(variant).map(Self::from)
+            }
+        }
+
+        impl ::com_shim::ToVariant for #ident {
+            fn to_variant(&self) -> ::com_shim::Variant {
+                ::com_shim::ToVariant::to_variant(&self.inner)
             }
+        }
 
-            fn variant_into(&'_ self) -> ::com_shim::Result<#ident> {
-                let idisp = <::com_shim::VARIANT as ::com_shim::VariantTypeExt<'_, &::com_shim::IDispatch>>
-                    ::variant_into(self)?.clone();
-                ::std::result::Result::Ok(#ident::from(idisp))
+        impl ::com_shim::Marshal for #ident {
+            type Remote = ::com_shim::Remote<Self>;
+
+            fn marshal(self) -> ::com_shim::Result<::com_shim::Remote<Self>> {
+                ::com_shim::Remote::new(self)
+            }
+
+            fn unmarshal(remote: ::com_shim::Remote<Self>) -> ::com_shim::Result<Self> {
+                remote.with_local(|object| Self::from(object.inner.clone()))
             }
         }
+
+        #remote_trait
     }.into()
 }
+
+/// Declare an outgoing interface, through which an object raises events, as a trait to implement.
+///
+/// Each event is given as `fn Name(Type, ...) = dispid`. If the `DISPID` is omitted, it follows
+/// the previous event, starting from one. The trait has a method for each event, named in
+/// `snake_case`, which does nothing unless implemented, and `advise` to connect a handler to an
+/// object.
+///
+/// An enum with a variant for each event is also generated, named after the trait with a trailing
+/// `Events` replaced by `Event`, whose `stream` function receives events as an `EventStream`.
+#[proc_macro]
+pub fn com_events(stream: TokenStream) -> TokenStream {
+    let events = parse_macro_input!(stream as events::Events);
+    events::com_events(events)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implement `ComObject` for the methods of an `impl` block, so that it can be called through
+/// `IDispatch`.
+///
+/// Methods marked `#[com(get)]` or `#[com(set)]` read or write a property, and those marked
+/// `#[com(method)]` are called as methods, each named in `UpperCamelCase` after the method, without
+/// a `set_` prefix for setters, unless renamed with `#[com(name = "...")]`. Exposed methods must
+/// take `&self`, and other methods are left as they are.
+#[proc_macro]
+pub fn com_object(stream: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(stream as syn::ItemImpl);
+    object::com_object(item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `FromVariant` for a struct with a single field, by reading the value of that field.
+#[proc_macro_derive(FromVariant)]
+pub fn derive_from_variant(stream: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(stream as DeriveInput);
+    convert::from_variant(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `FromVariant` and `ToVariant` for a struct with a single field, or for an enum of unit
+/// variants.
+///
+/// Enums marked `#[com(repr = "string")]` are stored as strings, named after each variant unless
+/// renamed with `#[com(rename = "...")]`. Enums marked `#[com(repr = "i32")]` are instead stored
+/// as `i32`s, numbered as Rust numbers them.
+#[proc_macro_derive(ComVariant, attributes(com))]
+pub fn derive_com_variant(stream: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(stream as DeriveInput);
+    convert::com_variant(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `FromDispatch` for a struct with named fields, reading each field from a property.
+///
+/// Properties are named after each field in `UpperCamelCase`, unless renamed with
+/// `#[com(name = "...")]`. Fields marked `#[com(optional)]` are read as their default value if
+/// the property does not exist, and fields marked `#[com(nested)]` are read from an object with
+/// `FromDispatch`.
+#[proc_macro_derive(FromDispatch, attributes(com))]
+pub fn derive_from_dispatch(stream: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(stream as DeriveInput);
+    dispatch::from_dispatch(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `ToVariant` for a struct with a single field, by writing the value of that field.
+#[proc_macro_derive(ToVariant)]
+pub fn derive_to_variant(stream: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(stream as DeriveInput);
+    convert::to_variant(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,128 @@
+use heck::ToUpperCamelCase;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{FnArg, Ident, ImplItem, ItemImpl, LitInt, Signature, ext::IdentExt, spanned::Spanned};
+
+use crate::attributes::{ComAttributes, Context, Member};
+
+pub(crate) fn com_object(mut item: ItemImpl) -> syn::Result<TokenStream> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new(
+            path.span(),
+            "expected an inherent `impl` block",
+        ));
+    }
+
+    let mut names: Vec<(String, i32)> = vec![];
+    let mut exposed: Vec<(String, bool)> = vec![];
+    let mut arms = vec![];
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(function) = impl_item else {
+            continue;
+        };
+        let ComAttributes { rename, member, .. } =
+            ComAttributes::parse(&function.attrs, Context::ObjectMethod)?;
+        function.attrs.retain(|a| !a.path().is_ident("com"));
+        let Some(member) = member else {
+            continue;
+        };
+
+        let ident = &function.sig.ident;
+        let count = check_signature(&function.sig, member)?;
+        let name = rename.map_or_else(|| com_name(ident, member), |r| r.value());
+        let put = member == Member::Set;
+        let key = (name.to_lowercase(), put);
+        if exposed.contains(&key) {
+            return Err(syn::Error::new(
+                ident.span(),
+                format!("`{name}` is already exposed by another method"),
+            ));
+        }
+        let dispid = if let Some((_, dispid)) = names
+            .iter()
+            .find(|(other, _)| other.to_lowercase() == key.0)
+        {
+            *dispid
+        } else {
+            let dispid = i32::try_from(names.len() + 1).expect("too many members");
+            names.push((name, dispid));
+            dispid
+        };
+        exposed.push(key);
+
+        let dispid = LitInt::new(&dispid.to_string(), ident.span());
+        let indices = 0..count;
+        arms.push(quote! {
+            (#dispid, #put) => {
+                ::com_shim::__private::expect_arguments(params, #count)?;
+                ::com_shim::__private::ReturnValue::into_result(
+                    self.#ident(#(::com_shim::__private::argument(params, #indices)?),*),
+                )
+            }
+        });
+    }
+
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    let self_ty = &item.self_ty;
+    let (names, dispids): (Vec<_>, Vec<_>) = names.into_iter().unzip();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::com_shim::ComObject for #self_ty #where_clause {
+            fn dispid(&self, name: &str) -> ::std::option::Option<i32> {
+                ::com_shim::__private::find_dispid(&[#((#names, #dispids)),*], name)
+            }
+
+            fn invoke(
+                &self,
+                dispid: i32,
+                flags: ::com_shim::__private::DISPATCH_FLAGS,
+                params: &::com_shim::__private::DISPPARAMS,
+            ) -> ::com_shim::Result<::com_shim::Variant> {
+                let _ = params;
+                match (dispid, ::com_shim::__private::is_put(flags)) {
+                    #(#arms)*
+                    _ => ::std::result::Result::Err(::com_shim::__private::member_not_found()),
+                }
+            }
+        }
+    })
+}
+
+/// Check that an exposed method can be called through `IDispatch`, returning how many arguments it
+/// takes.
+fn check_signature(signature: &Signature, member: Member) -> syn::Result<usize> {
+    match signature.receiver() {
+        Some(receiver) if receiver.reference.is_some() && receiver.mutability.is_none() => (),
+        _ => {
+            return Err(syn::Error::new(
+                signature.span(),
+                "exposed methods must take `&self`, as COM may call them re-entrantly",
+            ));
+        }
+    }
+    let count = signature
+        .inputs
+        .iter()
+        .filter(|input| matches!(input, FnArg::Typed(_)))
+        .count();
+    if member == Member::Set && count == 0 {
+        return Err(syn::Error::new(
+            signature.span(),
+            "setters must take the value to set",
+        ));
+    }
+    Ok(count)
+}
+
+/// The COM name of a method, such that `set_count` writes `Count`.
+fn com_name(ident: &Ident, member: Member) -> String {
+    let name = ident.unraw().to_string();
+    match member {
+        Member::Set => name
+            .strip_prefix("set_")
+            .unwrap_or(&name)
+            .to_upper_camel_case(),
+        Member::Get | Member::Method => name.to_upper_camel_case(),
+    }
+}
@@ -0,0 +1,143 @@
+use heck::ToSnakeCase;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{Attribute, Ident, Type, ext::IdentExt, parse_quote};
+
+use crate::{Function, FunctionOrVariable, Variable};
+
+/// A member of a class, as called through a `Remote` handle.
+struct Member<'a> {
+    attributes: &'a [Attribute],
+    /// The COM name of the member, which is given in errors.
+    name: String,
+    ident: Ident,
+    parameters: Vec<Type>,
+    returns: Type,
+}
+
+impl<'a> Member<'a> {
+    /// The methods generated for a member in `com_shim!`, which are called the same way remotely.
+    fn of(member: &'a FunctionOrVariable) -> Vec<Self> {
+        match member {
+            FunctionOrVariable::Function(Function {
+                attributes,
+                ident,
+                parameters,
+                returns,
+                ..
+            }) => vec![Self {
+                attributes,
+                name: ident.unraw().to_string(),
+                ident: Ident::new(&ident.to_string().to_snake_case(), ident.span()),
+                parameters: parameters.iter().cloned().collect(),
+                returns: returns.clone().unwrap_or_else(|| parse_quote!(())),
+            }],
+            FunctionOrVariable::Variable(Variable {
+                attributes,
+                mutable,
+                ident,
+                type_,
+                ..
+            }) => {
+                let snake = ident.to_string().to_snake_case();
+                let mut members = vec![Self {
+                    attributes,
+                    name: ident.unraw().to_string(),
+                    ident: Ident::new(&snake, ident.span()),
+                    parameters: vec![],
+                    returns: type_.clone(),
+                }];
+                if *mutable {
+                    members.push(Self {
+                        attributes,
+                        name: ident.unraw().to_string(),
+                        ident: Ident::new(&format!("set_{snake}"), ident.span()),
+                        parameters: vec![type_.clone()],
+                        returns: parse_quote!(()),
+                    });
+                }
+                members
+            }
+        }
+    }
+
+    /// Generate the declarations of the blocking and `_async` methods, and their implementations.
+    fn expand(&self, self_impl: &Ident) -> (TokenStream, TokenStream) {
+        let Member {
+            attributes,
+            name,
+            ident,
+            parameters,
+            returns,
+        } = self;
+        let async_ident = Ident::new(&format!("{ident}_async"), ident.span());
+        let arguments = (0..parameters.len())
+            .map(|idx| Ident::new(&format!("p{idx}"), Span::call_site()))
+            .collect::<Vec<_>>();
+        let blocking_signature = quote! {
+            fn #ident(
+                &self,
+                #(#arguments: <#parameters as ::com_shim::Marshal>::Remote),*
+            ) -> ::com_shim::Result<<#returns as ::com_shim::Marshal>::Remote>
+        };
+        let async_signature = quote! {
+            fn #async_ident(
+                &self,
+                #(#arguments: <#parameters as ::com_shim::Marshal>::Remote),*
+            ) -> ::com_shim::Pending<<#returns as ::com_shim::Marshal>::Remote>
+        };
+        let call = quote! {
+            move |object| {
+                #(let #arguments = ::com_shim::Marshal::unmarshal(#arguments)?;)*
+                ::com_shim::Marshal::marshal(<T as #self_impl>::#ident(object, #(#arguments),*)?)
+            }
+        };
+        let async_doc = format!("Call [`{ident}`](Self::{ident}) without waiting for its result.");
+        (
+            quote! {
+                #(#attributes)*
+                #blocking_signature;
+
+                #[doc = #async_doc]
+                #async_signature;
+            },
+            quote! {
+                #blocking_signature {
+                    self.call_member(#name, #call)
+                }
+
+                #async_signature {
+                    self.call_member_async(#name, #call)
+                }
+            },
+        )
+    }
+}
+
+/// Generate the trait implementing the members of a class for a `Remote` handle to it.
+pub(crate) fn remote_trait(
+    ident: &Ident,
+    self_impl: &Ident,
+    members: &[FunctionOrVariable],
+) -> TokenStream {
+    let remote_ident = Ident::new(&format!("{ident}Remote"), ident.span());
+    let (declarations, implementations): (Vec<_>, Vec<_>) = members
+        .iter()
+        .flat_map(Member::of)
+        .map(|member| member.expand(self_impl))
+        .unzip();
+    let doc = format!(
+        "The members of [`{ident}`], called on its thread through a \
+         [`Remote`](::com_shim::Remote) handle."
+    );
+    quote! {
+        #[doc = #doc]
+        pub trait #remote_ident {
+            #(#declarations)*
+        }
+
+        impl<T: #self_impl + 'static> #remote_ident for ::com_shim::Remote<T> {
+            #(#implementations)*
+        }
+    }
+}
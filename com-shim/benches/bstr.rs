@@ -0,0 +1,65 @@
+//! Compare reading and writing strings through `BStr` with copying them through a `BSTR`, as
+//! com-shim did before.
+//!
+//! Run with `cargo bench --bench bstr`.
+
+// Off Windows, the Win32 functions that com-shim calls are faked
+extern crate com_shim_fake_win32;
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use com_shim::{BStr, FromVariant, ToVariant, Variant};
+use windows::core::BSTR;
+
+/// Run `f` repeatedly for about half a second, and report the mean time taken.
+fn bench(name: &str, mut f: impl FnMut()) {
+    let mut iterations = 0_u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(500) {
+        for _ in 0..100 {
+            f();
+        }
+        iterations += 100;
+    }
+    let mean = start.elapsed() / iterations;
+    println!("{name:<40} {mean:>10.2?}");
+}
+
+/// Read a string as before: copy the variant, then decode the copy.
+fn read_copied(variant: &Variant) -> String {
+    let copy = Variant::copy(variant).unwrap();
+    // SAFETY: the variant holds a `VT_BSTR`.
+    unsafe { copy.Anonymous.Anonymous.Anonymous.bstrVal.to_string() }
+}
+
+fn main() {
+    for (size, text) in [
+        ("short", "wnd[0]/usr/txtName".to_owned()),
+        ("long", "Grüße, ".repeat(512)),
+    ] {
+        let variant = text.to_variant();
+
+        bench(&format!("write {size}: BSTR::from"), || {
+            black_box(BSTR::from(black_box(text.as_str())));
+        });
+        bench(&format!("write {size}: ToVariant"), || {
+            black_box(black_box(text.as_str()).to_variant());
+        });
+
+        bench(&format!("read {size}: copy and decode"), || {
+            black_box(read_copied(black_box(&variant)));
+        });
+        bench(&format!("read {size}: String::from_variant"), || {
+            black_box(String::from_variant(black_box(&variant)).unwrap());
+        });
+        bench(&format!("read {size}: BStr::from_variant"), || {
+            black_box(BStr::from_variant(black_box(&variant)).unwrap());
+        });
+        bench(&format!("read {size}: BStr::borrow"), || {
+            black_box(BStr::borrow(black_box(&variant)).unwrap());
+        });
+    }
+}
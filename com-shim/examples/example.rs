@@ -1,4 +1,15 @@
-use com_shim::com_shim;
+use com_shim::{Array2, ComVariant, com_shim};
+
+/// The mode a transaction is opened in
+#[derive(ComVariant)]
+#[com(repr = "string")]
+enum Mode {
+    /// View without changing
+    Display,
+    /// Make changes
+    #[com(rename = "Change")]
+    Edit,
+}
 
 com_shim! {
     /// A generic GUI component
@@ -27,11 +38,25 @@ com_shim! {
         DisplayedText: String,
         /// Whether the field is highlighted
         mut Highlighted: bool,
+        /// The mode the field is in
+        mut Mode: Mode,
 
         /// Get a property from the component
         fn GetListProperty(String) -> GuiComponent,
         /// Get a property value from the component
         fn GetListPropertyValue(String, String) -> GuiComponent,
+        /// Find a child component, if it exists
+        fn FindById(String) -> Option<GuiComponent>,
+    }
+}
+
+com_shim! {
+    /// A grid of cells
+    struct GuiGridView: GuiVComponent + GuiComponent {
+        /// Select the given rows
+        fn SelectRows(Vec<i32>),
+        /// Get the values of the visible cells
+        fn GetVisibleCells() -> Array2<String>,
     }
 }
 
@@ -0,0 +1,507 @@
+use std::{
+    ops::{Index, IndexMut},
+    ptr,
+};
+
+use windows::{
+    Win32::{
+        Foundation::{DISP_E_BADVARTYPE, DISP_E_TYPEMISMATCH, E_OUTOFMEMORY},
+        System::{
+            Com::{SAFEARRAY, SAFEARRAYBOUND},
+            Ole::{
+                SafeArrayAccessData, SafeArrayCreate, SafeArrayDestroy, SafeArrayGetDim,
+                SafeArrayGetLBound, SafeArrayGetUBound, SafeArrayGetVartype, SafeArrayUnaccessData,
+            },
+            Variant::{
+                VARENUM, VARIANT, VT_ARRAY, VT_BOOL, VT_BSTR, VT_CY, VT_DATE, VT_DECIMAL,
+                VT_DISPATCH, VT_ERROR, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_R4, VT_R8, VT_UI1,
+                VT_UI2, VT_UI4, VT_UI8, VT_UINT, VT_UNKNOWN, VT_VARIANT,
+            },
+        },
+    },
+    core::Result,
+};
+
+use crate::Variant;
+
+/// A two-dimensional array, as stored in a two-dimensional `SAFEARRAY`.
+///
+/// Elements are indexed by `(row, column)` from zero, regardless of the lower bounds of the
+/// array they were read from. The lower bounds are kept so that the array can be written back
+/// with the same shape.
+///
+/// Like `SAFEARRAY`s, elements are stored in column-major order, with the row varying fastest:
+///
+/// ```rust
+/// use com_shim::Array2;
+///
+/// let array = Array2::from_column_major(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+/// assert_eq!(array[(0, 0)], 1);
+/// assert_eq!(array[(1, 0)], 2);
+/// assert_eq!(array[(0, 1)], 3);
+/// assert_eq!(array[(1, 2)], 6);
+/// assert_eq!(array.get(2, 0), None);
+///
+/// let array = Array2::from_row_major(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap();
+/// assert_eq!(array.as_column_major(), &[1, 4, 2, 5, 3, 6]);
+/// assert_eq!(array.row(1).copied().collect::<Vec<_>>(), vec![4, 5, 6]);
+///
+/// let array = Array2::from_fn(3, 2, |row, column| (row, column)).with_lower_bounds(1, -1);
+/// assert_eq!(array.lower_bounds(), (1, -1));
+/// assert_eq!(array[(2, 1)], (2, 1));
+///
+/// assert!(Array2::from_column_major(2, 2, vec![1, 2, 3]).is_none());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Array2<T> {
+    data: Vec<T>,
+    rows: usize,
+    columns: usize,
+    lower_bounds: (i32, i32),
+}
+
+impl<T> Array2<T> {
+    /// Create an array by calling `f` with the `(row, column)` of each element.
+    pub fn from_fn<F>(rows: usize, columns: usize, mut f: F) -> Self
+    where
+        F: FnMut(usize, usize) -> T,
+    {
+        let mut data = Vec::with_capacity(rows * columns);
+        for column in 0..columns {
+            for row in 0..rows {
+                data.push(f(row, column));
+            }
+        }
+        Self {
+            data,
+            rows,
+            columns,
+            lower_bounds: (0, 0),
+        }
+    }
+
+    /// Create an array from elements in column-major order.
+    ///
+    /// Returns [`None`] if there are not exactly `rows * columns` elements.
+    #[must_use]
+    pub fn from_column_major(rows: usize, columns: usize, data: Vec<T>) -> Option<Self> {
+        (rows.checked_mul(columns) == Some(data.len())).then_some(Self {
+            data,
+            rows,
+            columns,
+            lower_bounds: (0, 0),
+        })
+    }
+
+    /// Create an array from elements in row-major order.
+    ///
+    /// Returns [`None`] if there are not exactly `rows * columns` elements.
+    #[must_use]
+    pub fn from_row_major(rows: usize, columns: usize, data: Vec<T>) -> Option<Self> {
+        if rows.checked_mul(columns) != Some(data.len()) {
+            return None;
+        }
+        // Sort the elements by their column-major offset
+        let mut data: Vec<(usize, T)> = data
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| ((i % columns) * rows + i / columns, value))
+            .collect();
+        data.sort_unstable_by_key(|(offset, _)| *offset);
+        Some(Self {
+            data: data.into_iter().map(|(_, value)| value).collect(),
+            rows,
+            columns,
+            lower_bounds: (0, 0),
+        })
+    }
+
+    /// Set the lower bounds of the rows and columns, used when this array is written to a
+    /// `SAFEARRAY`.
+    #[must_use]
+    pub fn with_lower_bounds(mut self, rows: i32, columns: i32) -> Self {
+        self.lower_bounds = (rows, columns);
+        self
+    }
+
+    /// The lower bounds of the rows and columns.
+    #[must_use]
+    pub fn lower_bounds(&self) -> (i32, i32) {
+        self.lower_bounds
+    }
+
+    /// The number of rows.
+    #[must_use]
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The number of columns.
+    #[must_use]
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Get the element at a zero-based `row` and `column`.
+    #[must_use]
+    pub fn get(&self, row: usize, column: usize) -> Option<&T> {
+        self.offset(row, column).map(|i| &self.data[i])
+    }
+
+    /// Get a mutable reference to the element at a zero-based `row` and `column`.
+    #[must_use]
+    pub fn get_mut(&mut self, row: usize, column: usize) -> Option<&mut T> {
+        self.offset(row, column).map(|i| &mut self.data[i])
+    }
+
+    /// Iterate over the elements of a row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `row` is out of bounds.
+    pub fn row(&self, row: usize) -> impl Iterator<Item = &T> {
+        assert!(row < self.rows, "row out of bounds");
+        self.data.iter().skip(row).step_by(self.rows.max(1))
+    }
+
+    /// The elements in column-major order.
+    #[must_use]
+    pub fn as_column_major(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Take the elements in column-major order.
+    #[must_use]
+    pub fn into_column_major(self) -> Vec<T> {
+        self.data
+    }
+
+    fn offset(&self, row: usize, column: usize) -> Option<usize> {
+        (row < self.rows && column < self.columns).then_some(row + column * self.rows)
+    }
+}
+
+impl<T> Index<(usize, usize)> for Array2<T> {
+    type Output = T;
+
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        self.get(row, column).expect("index out of bounds")
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Array2<T> {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        self.get_mut(row, column).expect("index out of bounds")
+    }
+}
+
+/// The lower bound and length of a dimension of a `SAFEARRAY`.
+pub(crate) type Bound = (i32, usize);
+
+/// Read every element of a `SAFEARRAY` with the expected number of dimensions, in storage order,
+/// passing each to `f` as a borrowed [`VARIANT`].
+///
+/// Returns the converted elements, and the lower bound and length of each dimension.
+///
+/// # Safety
+///
+/// `psa` must point to a valid `SAFEARRAY`. The [`VARIANT`] passed to `f` is only borrowed, so
+/// must not be cleared.
+pub(crate) unsafe fn read_safearray<R, F>(
+    psa: *const SAFEARRAY,
+    dims: u32,
+    mut f: F,
+) -> Result<(Vec<R>, Vec<Bound>)>
+where
+    F: FnMut(&VARIANT) -> Result<R>,
+{
+    unsafe {
+        if psa.is_null() || SafeArrayGetDim(psa) != dims {
+            return Err(DISP_E_TYPEMISMATCH.into());
+        }
+        let vt = SafeArrayGetVartype(psa)?;
+        if !is_supported_element(vt) {
+            return Err(DISP_E_BADVARTYPE.into());
+        }
+
+        let mut bounds = Vec::with_capacity(dims as usize);
+        for dim in 1..=dims {
+            let lower = SafeArrayGetLBound(psa, dim)?;
+            let upper = SafeArrayGetUBound(psa, dim)?;
+            let len = usize::try_from(i64::from(upper) - i64::from(lower) + 1).unwrap_or(0);
+            bounds.push((lower, len));
+        }
+        let count = bounds.iter().map(|(_, len)| len).product();
+
+        let size = (*psa).cbElements as usize;
+        let mut data = ptr::null_mut();
+        SafeArrayAccessData(psa, &raw mut data)?;
+        let mut results = Vec::with_capacity(count);
+        for i in 0..count {
+            let result = if vt == VT_VARIANT {
+                f(&*data.cast::<VARIANT>().add(i))
+            } else {
+                f(&element_view(vt, data.cast::<u8>().add(i * size), size))
+            };
+            match result {
+                Ok(r) => results.push(r),
+                Err(e) => {
+                    SafeArrayUnaccessData(psa)?;
+                    return Err(e);
+                }
+            }
+        }
+        SafeArrayUnaccessData(psa)?;
+
+        Ok((results, bounds))
+    }
+}
+
+/// Create a `SAFEARRAY` of `VT_VARIANT` with the given lower bound and length of each dimension,
+/// filled with `elements` in storage order.
+///
+/// # Errors
+///
+/// Fails if a dimension is too long to be stored in a `SAFEARRAY`, or it cannot be allocated.
+pub(crate) fn create_safearray<I>(bounds: &[Bound], elements: I) -> Result<*mut SAFEARRAY>
+where
+    I: IntoIterator<Item = Variant>,
+{
+    let psa = allocate(VT_VARIANT, bounds)?;
+    let count: usize = bounds.iter().map(|(_, len)| len).product();
+    unsafe {
+        let mut data = ptr::null_mut();
+        if let Err(e) = SafeArrayAccessData(psa, &raw mut data) {
+            let _ = SafeArrayDestroy(psa);
+            return Err(e);
+        }
+        let data = data.cast::<VARIANT>();
+        for (i, element) in elements.into_iter().take(count).enumerate() {
+            // Elements are initialised to `VT_EMPTY`, so there is nothing to drop
+            data.add(i).write(element.into_raw());
+        }
+        SafeArrayUnaccessData(psa)?;
+        Ok(psa)
+    }
+}
+
+/// Allocate a `SAFEARRAY` of `vt` with the given lower bound and length of each dimension.
+fn allocate(vt: VARENUM, bounds: &[Bound]) -> Result<*mut SAFEARRAY> {
+    let rgsabound = bounds
+        .iter()
+        .map(|&(lower, len)| {
+            Ok(SAFEARRAYBOUND {
+                cElements: u32::try_from(len).map_err(|_| E_OUTOFMEMORY)?,
+                lLbound: lower,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let dims = u32::try_from(rgsabound.len()).map_err(|_| E_OUTOFMEMORY)?;
+    // SAFETY: `rgsabound` holds a bound for each dimension.
+    let psa = unsafe { SafeArrayCreate(vt, dims, rgsabound.as_ptr()) };
+    if psa.is_null() {
+        return Err(E_OUTOFMEMORY.into());
+    }
+    Ok(psa)
+}
+
+/// Create a `SAFEARRAY` of `vt` with the given lower bound and length of each dimension, filled
+/// with `elements` in storage order, each converted to `vt`.
+///
+/// # Errors
+///
+/// Fails if the element type is not supported, an element cannot be converted, or the
+/// `SAFEARRAY` cannot be allocated.
+#[cfg(any(feature = "serde", not(windows)))]
+pub(crate) fn create_typed_safearray(
+    vt: VARENUM,
+    bounds: &[Bound],
+    elements: Vec<Variant>,
+) -> Result<*mut SAFEARRAY> {
+    use crate::variant;
+
+    if vt == VT_VARIANT {
+        return create_safearray(bounds, elements);
+    }
+    if !is_supported_element(vt) {
+        return Err(DISP_E_BADVARTYPE.into());
+    }
+    let psa = allocate(vt, bounds)?;
+    let count: usize = bounds.iter().map(|(_, len)| len).product();
+    unsafe {
+        let size = (*psa).cbElements as usize;
+        let mut data = ptr::null_mut();
+        if let Err(e) = SafeArrayAccessData(psa, &raw mut data) {
+            let _ = SafeArrayDestroy(psa);
+            return Err(e);
+        }
+        let data = data.cast::<u8>();
+        for (i, element) in elements.into_iter().take(count).enumerate() {
+            let value = match variant::change_type(&element, vt) {
+                Ok(value) => value.into_raw(),
+                Err(e) => {
+                    SafeArrayUnaccessData(psa)?;
+                    SafeArrayDestroy(psa)?;
+                    return Err(e);
+                }
+            };
+            // The array takes ownership of any string or object the value references
+            let element = data.add(i * size);
+            if vt == VT_DECIMAL {
+                // A `DECIMAL` overlays the whole `VARIANT`, so its `vt` is cleared from it
+                let mut decimal = value.Anonymous.decVal;
+                decimal.wReserved = 0;
+                ptr::copy_nonoverlapping((&raw const decimal).cast::<u8>(), element, size);
+            } else {
+                let source = &raw const value.Anonymous.Anonymous.Anonymous;
+                ptr::copy_nonoverlapping(source.cast::<u8>(), element, size);
+            }
+        }
+        SafeArrayUnaccessData(psa)?;
+        Ok(psa)
+    }
+}
+
+/// The `vt` of a [`VARIANT`] holding a `SAFEARRAY`.
+pub(crate) const VT_ARRAY_VARIANT: VARENUM = VARENUM(VT_ARRAY.0 | VT_VARIANT.0);
+/// The `vt` of a [`VARIANT`] holding a `SAFEARRAY` of bytes.
+pub(crate) const VT_ARRAY_UI1: VARENUM = VARENUM(VT_ARRAY.0 | VT_UI1.0);
+
+fn is_supported_element(vt: VARENUM) -> bool {
+    [
+        VT_VARIANT,
+        VT_I1,
+        VT_I2,
+        VT_I4,
+        VT_I8,
+        VT_INT,
+        VT_UI1,
+        VT_UI2,
+        VT_UI4,
+        VT_UI8,
+        VT_UINT,
+        VT_R4,
+        VT_R8,
+        VT_CY,
+        VT_DATE,
+        VT_DECIMAL,
+        VT_BSTR,
+        VT_BOOL,
+        VT_ERROR,
+        VT_DISPATCH,
+        VT_UNKNOWN,
+    ]
+    .contains(&vt)
+}
+
+/// Build a borrowed [`VARIANT`] over a typed `SAFEARRAY` element.
+///
+/// # Safety
+///
+/// `element` must point to `size` bytes holding a value of type `vt`.
+unsafe fn element_view(vt: VARENUM, element: *const u8, size: usize) -> VARIANT {
+    unsafe {
+        let mut view = VARIANT::default();
+        if vt == VT_DECIMAL {
+            // A `DECIMAL` overlays the whole `VARIANT`, with `vt` in its reserved field
+            ptr::copy_nonoverlapping(element, (&raw mut view.Anonymous.decVal).cast::<u8>(), size);
+            view.Anonymous.decVal.wReserved = vt.0;
+        } else {
+            let v00 = &mut *view.Anonymous.Anonymous;
+            v00.vt = vt;
+            ptr::copy_nonoverlapping(element, (&raw mut v00.Anonymous).cast::<u8>(), size);
+        }
+        view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Foundation::E_OUTOFMEMORY;
+
+    use super::{Array2, SafeArrayDestroy, create_safearray, read_safearray};
+    use crate::{FromVariant, ToVariant, Variant};
+
+    /// A 2 by 3 array whose elements are their `(row, column)`.
+    fn grid() -> Array2<(usize, usize)> {
+        Array2::from_fn(2, 3, |row, column| (row, column))
+    }
+
+    #[test]
+    fn stores_elements_in_column_major_order() {
+        let grid = grid();
+        assert_eq!(
+            grid.as_column_major(),
+            [(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2)]
+        );
+        for row in 0..2 {
+            for column in 0..3 {
+                assert_eq!(grid.offset(row, column), Some(row + column * 2));
+                assert_eq!(grid.get(row, column), Some(&(row, column)));
+            }
+        }
+        assert_eq!(grid.offset(2, 0), None);
+        assert_eq!(grid.offset(0, 3), None);
+        assert_eq!(grid.get(2, 0), None);
+    }
+
+    #[test]
+    fn reads_elements_in_either_order() {
+        let grid = grid();
+        let by_column = Array2::from_column_major(2, 3, grid.as_column_major().to_vec()).unwrap();
+        assert_eq!(by_column, grid);
+
+        let by_row = [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)];
+        assert_eq!(Array2::from_row_major(2, 3, by_row.to_vec()).unwrap(), grid);
+        assert_eq!(grid.row(1).copied().collect::<Vec<_>>(), by_row[3..]);
+
+        assert!(Array2::from_row_major(2, 3, by_row[1..].to_vec()).is_none());
+        assert!(Array2::from_column_major(3, 2, by_row[1..].to_vec()).is_none());
+    }
+
+    #[test]
+    fn handles_empty_arrays() {
+        let empty = Array2::from_fn(0, 3, |_, _| 0);
+        assert!(empty.as_column_major().is_empty());
+        assert_eq!(empty.get(0, 0), None);
+        assert_eq!(
+            Array2::<i32>::from_row_major(3, 0, vec![])
+                .unwrap()
+                .columns(),
+            0
+        );
+    }
+
+    #[test]
+    fn keeps_lower_bounds() {
+        let grid = Array2::from_fn(2, 3, |row, column| {
+            i32::try_from(row * 10 + column).unwrap()
+        })
+        .with_lower_bounds(1, -1);
+        let read = Array2::<i32>::from_variant(&grid.to_variant()).unwrap();
+        assert_eq!(read.lower_bounds(), (1, -1));
+        assert_eq!(read, grid);
+        // Elements are still indexed from zero
+        assert_eq!(read[(1, 2)], 12);
+    }
+
+    #[test]
+    fn writes_safearrays_in_storage_order() {
+        let elements = (0..6).map(|i: i32| i.to_variant());
+        let psa = create_safearray(&[(1, 2), (-1, 3)], elements).unwrap();
+        // SAFETY: the array was just created.
+        let (read, bounds) = unsafe { read_safearray(psa, 2, i32::from_variant) }.unwrap();
+        assert_eq!(read, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(bounds, [(1, 2), (-1, 3)]);
+        // SAFETY: the array is not used again.
+        unsafe { SafeArrayDestroy(psa) }.unwrap();
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn rejects_dimensions_too_long_for_a_safearray() {
+        let len = usize::try_from(u64::from(u32::MAX) + 1).unwrap();
+        let error = create_safearray(&[(0, len)], std::iter::empty::<Variant>()).unwrap_err();
+        assert_eq!(error.code(), E_OUTOFMEMORY);
+    }
+}
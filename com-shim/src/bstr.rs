@@ -0,0 +1,253 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    slice,
+};
+
+use windows::{
+    Win32::{
+        Foundation::{DISP_E_TYPEMISMATCH, SysAllocStringByteLen, SysStringByteLen},
+        System::Variant::{VARIANT, VARIANT_0_0, VT_BSTR, VT_BYREF},
+    },
+    core::{BSTR, Result},
+};
+
+use crate::{FromVariant, ToVariant, Variant, conversion};
+
+/// A `BSTR`, either borrowed from a [`VARIANT`] without copying it, or owned.
+///
+/// Unlike a [`String`], this keeps every byte of the string, so embedded NULs and byte strings of
+/// an odd length, as used by some servers for binary data, survive being read. Borrow the string
+/// held by a variant with [`BStr::borrow`], or read an owned `BStr<'static>` from any value,
+/// including as the type of a property in [`com_shim!`](crate::com_shim):
+///
+/// ```rust
+/// use com_shim::{BStr, FromVariant, Result, ToVariant};
+///
+/// # fn main() -> Result<()> {
+/// let text = "one\0two".to_variant();
+/// let view = BStr::borrow(&text)?;
+/// assert_eq!(view, "one\0two");
+/// assert_eq!(view.as_wide().len(), 7);
+///
+/// let bytes = BStr::from_bytes(&[1, 2, 3]).to_variant();
+/// assert_eq!(BStr::borrow(&bytes)?.as_bytes(), [1, 2, 3]);
+/// assert_eq!(Vec::<u8>::from_variant(&bytes)?, [1, 2, 3]);
+///
+/// let owned = BStr::from_variant(&42.to_variant())?;
+/// assert_eq!(owned.to_string_lossy(), "42");
+/// # Ok(())
+/// # }
+/// ```
+pub struct BStr<'a> {
+    bstr: ManuallyDrop<BSTR>,
+    owned: bool,
+    borrow: PhantomData<&'a BSTR>,
+}
+
+impl<'a> BStr<'a> {
+    /// Borrow the string held by a `VT_BSTR` variant, or a reference to one.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `DISP_E_TYPEMISMATCH` if the variant does not hold a string. Use
+    /// [`BStr::from_variant`] to convert other values.
+    pub fn borrow(variant: &'a VARIANT) -> Result<Self> {
+        // SAFETY: `vt` is checked before reading the string, which is copied bitwise but never
+        // freed, and lives as long as the variant.
+        unsafe {
+            let v00 = &variant.Anonymous.Anonymous;
+            let bstr = match v00.vt {
+                VT_BSTR => mem::transmute_copy(&*v00.Anonymous.bstrVal),
+                vt if vt.0 == VT_BSTR.0 | VT_BYREF.0 && !v00.Anonymous.pbstrVal.is_null() => {
+                    mem::transmute_copy(&*v00.Anonymous.pbstrVal)
+                }
+                _ => return Err(DISP_E_TYPEMISMATCH.into()),
+            };
+            Ok(Self {
+                bstr: ManuallyDrop::new(bstr),
+                owned: false,
+                borrow: PhantomData,
+            })
+        }
+    }
+
+    /// The string as UTF-16 code units, which may include NULs and unpaired surrogates.
+    #[must_use]
+    pub fn as_wide(&self) -> &[u16] {
+        self.bstr.as_wide()
+    }
+
+    /// The raw bytes of the string, including the last byte of a string with an odd length.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = self.byte_len();
+        if len == 0 {
+            return &[];
+        }
+        // SAFETY: a `BSTR` is prefixed with the length of its data in bytes.
+        unsafe { slice::from_raw_parts(self.as_ptr().cast(), len) }
+    }
+
+    /// Decode the string, replacing invalid UTF-16 with `U+FFFD`.
+    #[must_use]
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf16_lossy(self.as_wide())
+    }
+
+    /// Take ownership of the string, copying it if it is borrowed.
+    #[must_use]
+    pub fn into_owned(self) -> BStr<'static> {
+        if self.owned {
+            let this = ManuallyDrop::new(self);
+            // SAFETY: `this` is never dropped, so the string is moved out exactly once.
+            let bstr = unsafe { std::ptr::read(&raw const this.bstr) };
+            BStr {
+                bstr,
+                owned: true,
+                borrow: PhantomData,
+            }
+        } else {
+            BStr::from_bytes(self.as_bytes())
+        }
+    }
+
+    fn as_ptr(&self) -> *const u16 {
+        // SAFETY: a `BSTR` is a pointer to its data.
+        unsafe { mem::transmute_copy(&*self.bstr) }
+    }
+
+    fn byte_len(&self) -> usize {
+        if self.as_ptr().is_null() {
+            return 0;
+        }
+        // SAFETY: the string is valid.
+        unsafe { SysStringByteLen(&*self.bstr) as usize }
+    }
+}
+
+impl BStr<'static> {
+    /// Create a byte string, which may have an odd length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the string cannot be allocated.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        // SAFETY: the bytes are copied into a new string.
+        let string = Self::from(unsafe { SysAllocStringByteLen(Some(bytes)) });
+        assert!(!string.as_ptr().is_null(), "failed to allocate a BSTR");
+        string
+    }
+
+    /// Release the owned string.
+    fn into_bstr(self) -> BSTR {
+        let this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so the string is moved out exactly once.
+        ManuallyDrop::into_inner(unsafe { std::ptr::read(&raw const this.bstr) })
+    }
+}
+
+impl From<BSTR> for BStr<'static> {
+    fn from(bstr: BSTR) -> Self {
+        Self {
+            bstr: ManuallyDrop::new(bstr),
+            owned: true,
+            borrow: PhantomData,
+        }
+    }
+}
+
+impl Drop for BStr<'_> {
+    fn drop(&mut self) {
+        if self.owned {
+            // SAFETY: an owned string is only dropped here.
+            unsafe { ManuallyDrop::drop(&mut self.bstr) };
+        }
+    }
+}
+
+impl Clone for BStr<'_> {
+    fn clone(&self) -> Self {
+        BStr::from_bytes(self.as_bytes())
+    }
+}
+
+impl PartialEq for BStr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for BStr<'_> {}
+
+impl PartialEq<str> for BStr<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_wide().iter().copied().eq(other.encode_utf16())
+    }
+}
+
+impl PartialEq<&str> for BStr<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl fmt::Display for BStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.bstr, f)
+    }
+}
+
+impl fmt::Debug for BStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string_lossy(), f)
+    }
+}
+
+/// Any value is converted to a string, which is then held without decoding it.
+impl FromVariant for BStr<'static> {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        let mut new = conversion::convert(variant, VT_BSTR)?;
+        // SAFETY: the variant has just been converted to this type, and is left holding an empty
+        // string.
+        let bstr = unsafe {
+            let v00 = &mut *new.as_raw_mut().Anonymous.Anonymous;
+            ManuallyDrop::into_inner(mem::take(&mut v00.Anonymous.bstrVal))
+        };
+        Ok(BStr::from(bstr))
+    }
+}
+
+/// The string is copied, keeping all of its bytes.
+impl ToVariant for BStr<'_> {
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_BSTR,
+            ..Default::default()
+        };
+        v00.Anonymous.bstrVal = ManuallyDrop::new(BStr::from_bytes(self.as_bytes()).into_bstr());
+        Variant::from_v00(v00)
+    }
+}
+
+/// Encode a string directly into a new `BSTR`, without allocating for short strings.
+pub(crate) fn encode(text: &str) -> BSTR {
+    /// Strings of up to this many bytes are encoded on the stack.
+    const STACK: usize = 256;
+
+    // A string never has more UTF-16 code units than UTF-8 bytes
+    let result = if text.len() <= STACK {
+        let mut buffer = [0_u16; STACK];
+        let mut len = 0;
+        for (slot, unit) in buffer.iter_mut().zip(text.encode_utf16()) {
+            *slot = unit;
+            len += 1;
+        }
+        BSTR::from_wide(&buffer[..len])
+    } else {
+        BSTR::from_wide(&text.encode_utf16().collect::<Vec<_>>())
+    };
+    result.expect("failed to allocate a BSTR")
+}
@@ -0,0 +1,314 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use windows::{
+    Win32::System::Variant::{
+        VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF, VT_CY, VT_DATE, VT_DECIMAL, VT_DISPATCH, VT_EMPTY,
+        VT_ERROR, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_NULL, VT_R4, VT_R8, VT_RECORD, VT_UI1,
+        VT_UI2, VT_UI4, VT_UI8, VT_UINT, VT_UNKNOWN,
+    },
+    core::Interface,
+};
+
+use crate::{BStr, FromVariant, IDispatch, Member, Result, Variant, utils};
+
+/// Remembers the results of calls to idempotent members, so that repeating one does not call the
+/// object again.
+///
+/// While a cache applies, a call to an [idempotent](crate::Effect::Idempotent) member of a class
+/// declared with [`com_shim!`](crate::com_shim) returns the result of an earlier call to the same
+/// member of the same object with the same arguments, if there was one. Any other call may change
+/// what those members return, so it empties the cache. Failed calls are not remembered.
+///
+/// A cache applies to every call made on this thread with [`CallCache::scope`]. Calls made
+/// directly through [`IDispatchExt`](crate::IDispatchExt) are not cached, and empty it. Calls
+/// through a [`Remote`](crate::Remote) handle are made on another thread, so they are not cached.
+///
+/// ```rust
+/// use std::cell::Cell;
+///
+/// use com_shim::{CallCache, ComObject, Result, com_object, com_shim};
+///
+/// #[derive(Default)]
+/// struct Grid {
+///     rows: Cell<i32>,
+/// }
+///
+/// com_object! {
+///     impl Grid {
+///         #[com(get)]
+///         fn row_count(&self) -> i32 {
+///             self.rows.get()
+///         }
+///
+///         #[com(method)]
+///         fn add_row(&self) {
+///             self.rows.set(self.rows.get() + 1);
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     struct GridClass {
+///         RowCount: i32,
+///         fn AddRow(),
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let grid = GridClass::from(Grid::default().into_idispatch());
+///
+/// let cache = CallCache::new();
+/// cache.scope(|| -> Result<()> {
+///     assert_eq!(grid.row_count()?, 0);
+///     assert_eq!(grid.row_count()?, 0);
+///     assert_eq!(cache.len(), 1);
+///
+///     // Adding a row may change the row count, so it is read again
+///     grid.add_row()?;
+///     assert!(cache.is_empty());
+///     assert_eq!(grid.row_count()?, 1);
+///     Ok(())
+/// })?;
+/// assert_eq!(cache.len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct CallCache(Rc<RefCell<Vec<Entry>>>);
+
+/// The result of a call.
+struct Entry {
+    object: IDispatch,
+    member: Member,
+    arguments: Vec<Variant>,
+    result: Variant,
+}
+
+impl Entry {
+    fn matches(&self, object: &IDispatch, member: Member, arguments: &[Variant]) -> bool {
+        self.object == *object
+            && self.member == member
+            && self.arguments.len() == arguments.len()
+            && self
+                .arguments
+                .iter()
+                .zip(arguments)
+                .all(|(left, right)| same_argument(left, right))
+    }
+}
+
+/// Whether two arguments are the same value of the same type.
+///
+/// Values are compared as they are stored rather than as `VarCmp` compares them, so `1` and `1.0`,
+/// or strings differing only in case, are different arguments. Strings are compared by their
+/// contents, and anything else by the bits of the field its type is stored in, so arrays,
+/// references and objects are only the same if they refer to the same value. Values of types a
+/// variant cannot hold are never the same.
+fn same_argument(left: &Variant, right: &Variant) -> bool {
+    let vt = left.vt();
+    if vt != right.vt() {
+        return false;
+    }
+    if vt == VT_BSTR {
+        return match (BStr::from_variant(left), BStr::from_variant(right)) {
+            (Ok(left), Ok(right)) => left.as_bytes() == right.as_bytes(),
+            _ => false,
+        };
+    }
+    // SAFETY: each field read is the one `vt` says the value is stored in, and the unused bytes of
+    // the union, which may hold anything, are not read.
+    unsafe {
+        if vt == VT_DECIMAL {
+            let (left, right) = (&left.Anonymous.decVal, &right.Anonymous.decVal);
+            return left.Anonymous1.signscale == right.Anonymous1.signscale
+                && left.Hi32 == right.Hi32
+                && left.Anonymous2.Lo64 == right.Anonymous2.Lo64;
+        }
+        let (left, right) = (
+            &left.Anonymous.Anonymous.Anonymous,
+            &right.Anonymous.Anonymous.Anonymous,
+        );
+        if vt.0 & (VT_BYREF.0 | VT_ARRAY.0) != 0 {
+            return left.byref == right.byref;
+        }
+        match vt {
+            VT_EMPTY | VT_NULL => true,
+            VT_I1 => left.cVal == right.cVal,
+            VT_UI1 => left.bVal == right.bVal,
+            VT_I2 => left.iVal == right.iVal,
+            VT_UI2 => left.uiVal == right.uiVal,
+            VT_BOOL => left.boolVal == right.boolVal,
+            VT_I4 => left.lVal == right.lVal,
+            VT_UI4 => left.ulVal == right.ulVal,
+            VT_INT => left.intVal == right.intVal,
+            VT_UINT => left.uintVal == right.uintVal,
+            VT_ERROR => left.scode == right.scode,
+            VT_I8 => left.llVal == right.llVal,
+            VT_UI8 => left.ullVal == right.ullVal,
+            VT_CY => left.cyVal.int64 == right.cyVal.int64,
+            VT_R4 => left.fltVal.to_bits() == right.fltVal.to_bits(),
+            VT_R8 => left.dblVal.to_bits() == right.dblVal.to_bits(),
+            VT_DATE => left.date.to_bits() == right.date.to_bits(),
+            VT_DISPATCH => {
+                left.pdispVal.as_ref().map(Interface::as_raw)
+                    == right.pdispVal.as_ref().map(Interface::as_raw)
+            }
+            VT_UNKNOWN => {
+                left.punkVal.as_ref().map(Interface::as_raw)
+                    == right.punkVal.as_ref().map(Interface::as_raw)
+            }
+            VT_RECORD => {
+                left.Anonymous.pvRecord == right.Anonymous.pvRecord
+                    && left.Anonymous.pRecInfo.as_ref().map(Interface::as_raw)
+                        == right.Anonymous.pRecInfo.as_ref().map(Interface::as_raw)
+            }
+            _ => false,
+        }
+    }
+}
+
+thread_local! {
+    /// The cache chosen for calls on this thread.
+    static SESSION: RefCell<Option<CallCache>> = const { RefCell::new(None) };
+}
+
+impl CallCache {
+    /// Start an empty cache.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` with this cache, reusing the results of its calls to idempotent members.
+    pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        utils::scoped(&SESSION, Some(self.clone()), f)
+    }
+
+    /// How many results are remembered.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    /// Whether no results are remembered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    /// Forget every result.
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl fmt::Debug for CallCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallCache")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Run `f` without a cache.
+pub(crate) fn detached<R>(f: impl FnOnce() -> R) -> R {
+    utils::scoped(&SESSION, None, f)
+}
+
+/// Make a call to the current member of `object` with `f`, reusing an earlier result if it is
+/// idempotent and a cache applies.
+pub(crate) fn call(
+    object: &IDispatch,
+    arguments: Vec<Variant>,
+    f: impl FnOnce(Vec<Variant>) -> Result<Variant>,
+) -> Result<Variant> {
+    let Some(cache) = SESSION.with_borrow(Clone::clone) else {
+        return f(arguments);
+    };
+    let Some(member) = Member::current().filter(Member::is_idempotent) else {
+        cache.clear();
+        return f(arguments);
+    };
+    let cached = cache
+        .0
+        .borrow()
+        .iter()
+        .find(|entry| entry.matches(object, member, &arguments))
+        .map(|entry| entry.result.clone());
+    if let Some(result) = cached {
+        tracing::debug!("Reusing the result of a call to {}", member.name());
+        return Ok(result);
+    }
+    let result = f(arguments.clone())?;
+    cache.0.borrow_mut().push(Entry {
+        object: object.clone(),
+        member,
+        arguments,
+        result: result.clone(),
+    });
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::System::Variant::VT_I4;
+
+    use super::same_argument;
+    use crate::{ComObject, Decimal, ToVariant, VARIANT, Variant, com_object};
+
+    /// An object with no members.
+    struct Fake;
+
+    com_object! {
+        impl Fake {}
+    }
+
+    #[test]
+    fn only_the_bytes_of_the_type_are_compared() {
+        // A value narrower than the union may leave garbage in the bytes it does not use
+        let mut garbage = VARIANT::default();
+        // SAFETY: the variant holds an `i32`, which has no resources to free.
+        let garbage = unsafe {
+            let v00 = &mut *garbage.Anonymous.Anonymous;
+            v00.Anonymous.llVal = 0x5EAD_BEEF_0000_0000 | 7;
+            v00.vt = VT_I4;
+            Variant::from_raw(garbage)
+        };
+        assert!(same_argument(&garbage, &7.to_variant()));
+        assert!(!same_argument(&garbage, &8.to_variant()));
+        assert!(!same_argument(&garbage, &7_i64.to_variant()));
+
+        let object = Fake.into_idispatch();
+        assert!(same_argument(
+            &object.to_variant(),
+            &object.clone().to_variant()
+        ));
+        assert!(!same_argument(
+            &object.to_variant(),
+            &Fake.into_idispatch().to_variant()
+        ));
+        assert!(same_argument(
+            &f64::NAN.to_variant(),
+            &f64::NAN.to_variant()
+        ));
+        assert!(!same_argument(&0.0.to_variant(), &(-0.0).to_variant()));
+    }
+
+    #[test]
+    fn arguments_are_compared_by_type_and_value() {
+        assert!(same_argument(&1.to_variant(), &1.to_variant()));
+        assert!(!same_argument(&1.to_variant(), &1.0.to_variant()));
+        assert!(!same_argument(&1.to_variant(), &2.to_variant()));
+
+        assert!(same_argument(&"a".to_variant(), &"a".to_variant()));
+        assert!(!same_argument(&"a".to_variant(), &"A".to_variant()));
+
+        let one = Decimal::new(1, 0).unwrap();
+        let one_point_zero = Decimal::new(10, 1).unwrap();
+        assert!(same_argument(&one.to_variant(), &one.to_variant()));
+        assert!(!same_argument(
+            &one.to_variant(),
+            &one_point_zero.to_variant()
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+use std::marker::PhantomData;
+
+use windows::{
+    Win32::System::Com::{DISPATCH_FLAGS, DISPATCH_METHOD, DISPPARAMS, IDispatch},
+    core::Result,
+};
+
+use crate::{__private, FromVariant, HasIDispatch, ToVariant, VARIANT, Variant, server, utils};
+
+/// The `DISPID` of the default member, which `windows` declares as a `u32`.
+const DISPID_VALUE: i32 = 0;
+
+/// An object that is called through its default member, such as a handler passed to an
+/// automation API, or a function object from a script.
+///
+/// Make one from a closure with [`callback`], taking its arguments as a tuple. It can then be
+/// passed to any method declared to take a `Callback` in [`com_shim!`](crate::com_shim), and
+/// callbacks received from COM can be called with [`Callback::call`].
+///
+/// ```rust
+/// use std::cell::RefCell;
+///
+/// use com_shim::{
+///     Callback, ComObject, FromVariant, Result, ToVariant, Variant, callback, com_object, com_shim,
+/// };
+/// use windows::Win32::Foundation::{DISP_E_BADPARAMCOUNT, E_FAIL};
+///
+/// /// A server that notifies a handler.
+/// #[derive(Default)]
+/// struct Notifier {
+///     handler: RefCell<Option<Callback>>,
+/// }
+///
+/// com_object! {
+///     impl Notifier {
+///         #[com(method)]
+///         fn subscribe(&self, handler: Callback) {
+///             *self.handler.borrow_mut() = Some(handler);
+///         }
+///
+///         #[com(method)]
+///         fn notify(&self, name: String, count: i32) -> Result<Variant> {
+///             let handler = self.handler.borrow().clone().ok_or(E_FAIL)?;
+///             handler.call(vec![name.to_variant(), count.to_variant()])
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     struct NotifierClass {
+///         fn Subscribe(Callback),
+///         fn Notify(String, i32) -> bool,
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let notifier = NotifierClass::from(Notifier::default().into_idispatch());
+/// notifier.subscribe(callback(|(name, count): (String, i32)| {
+///     Ok(i32::try_from(name.len()) == Ok(count))
+/// }))?;
+/// assert!(notifier.notify("three".to_string(), 5)?);
+/// assert!(!notifier.notify("four".to_string(), 5)?);
+///
+/// let greet = callback(|(name,): (String,)| Ok(format!("Hello, {name}!")));
+/// let greeting = greet.call(vec!["world".to_variant()])?;
+/// assert_eq!(String::from_variant(&greeting)?, "Hello, world!");
+/// assert_eq!(greet.call(vec![1.to_variant(), 2.to_variant()]).unwrap_err().code(), DISP_E_BADPARAMCOUNT);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Callback(IDispatch);
+
+impl Callback {
+    /// Call the object through its default member.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the object cannot be called with these arguments, or the call fails.
+    pub fn call(&self, mut args: Vec<Variant>) -> Result<Variant> {
+        utils::invoke(
+            &self.0,
+            DISPID_VALUE,
+            DISPATCH_METHOD,
+            &utils::assemble_dispparams_get(&mut args),
+        )
+    }
+}
+
+/// Make a [`Callback`] that calls a closure, converting the arguments it is called with into a
+/// tuple.
+pub fn callback<A, R, F>(f: F) -> Callback
+where
+    A: CallbackArguments + 'static,
+    R: ToVariant + 'static,
+    F: Fn(A) -> Result<R> + 'static,
+{
+    Callback(server::into_idispatch(Closure {
+        f,
+        signature: PhantomData,
+    }))
+}
+
+impl From<IDispatch> for Callback {
+    fn from(value: IDispatch) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Callback> for IDispatch {
+    fn from(value: Callback) -> Self {
+        value.0
+    }
+}
+
+impl HasIDispatch for Callback {
+    fn get_idispatch(&self) -> &IDispatch {
+        &self.0
+    }
+}
+
+impl FromVariant for Callback {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        IDispatch::from_variant(variant).map(Self)
+    }
+}
+
+impl ToVariant for Callback {
+    fn to_variant(&self) -> Variant {
+        self.0.to_variant()
+    }
+}
+
+/// The arguments a [`callback`] is called with, as a tuple of values to convert each argument
+/// into.
+pub trait CallbackArguments: Sized {
+    /// How many arguments are taken.
+    const COUNT: usize;
+
+    /// Convert the arguments of a call, which are given in reverse order.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any argument cannot be converted.
+    fn from_params(params: &DISPPARAMS) -> Result<Self>;
+}
+
+macro_rules! callback_arguments {
+    ($($name:ident: $index:tt),*) => {
+        impl<$($name: FromVariant),*> CallbackArguments for ($($name,)*) {
+            const COUNT: usize = <[usize]>::len(&[$($index),*]);
+
+            fn from_params(params: &DISPPARAMS) -> Result<Self> {
+                let _ = params;
+                Ok(($(__private::argument::<$name>(params, $index)?,)*))
+            }
+        }
+    };
+}
+
+callback_arguments!();
+callback_arguments!(A: 0);
+callback_arguments!(A: 0, B: 1);
+callback_arguments!(A: 0, B: 1, C: 2);
+callback_arguments!(A: 0, B: 1, C: 2, D: 3);
+callback_arguments!(A: 0, B: 1, C: 2, D: 3, E: 4);
+callback_arguments!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+callback_arguments!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+callback_arguments!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+/// A closure called through `DISPID_VALUE`.
+struct Closure<A, R, F> {
+    f: F,
+    signature: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R, F> server::Dispatch for Closure<A, R, F>
+where
+    A: CallbackArguments + 'static,
+    R: ToVariant + 'static,
+    F: Fn(A) -> Result<R> + 'static,
+{
+    fn dispid(&self, _name: &str) -> Option<i32> {
+        None
+    }
+
+    fn invoke(&self, dispid: i32, flags: DISPATCH_FLAGS, params: &DISPPARAMS) -> Result<Variant> {
+        if dispid != DISPID_VALUE || __private::is_put(flags) {
+            return Err(__private::member_not_found());
+        }
+        __private::expect_arguments(params, A::COUNT)?;
+        (self.f)(A::from_params(params)?).map(|value| value.to_variant())
+    }
+}
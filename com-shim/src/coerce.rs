@@ -0,0 +1,904 @@
+//! A pure-Rust implementation of the OLE Automation coercion rules followed by
+//! `VariantChangeType`, which converts variants off Windows, where there is no `oleaut32`.
+//! Windows uses `VariantChangeType` itself, and only compiles this module for its tests.
+//!
+//! Conversions follow the documented behaviour of `VariantChangeType` in the US English locale:
+//!
+//! - Floating point, currency and decimal values are rounded to integers half to even, and
+//!   values out of the range of the target type fail with `DISP_E_OVERFLOW`.
+//! - `True` is `-1`, which wraps to the maximum of unsigned types, and is written as `"-1"`
+//!   unless `VARIANT_ALPHABOOL` is set.
+//! - Strings are parsed as numbers allowing surrounding white space, a leading or trailing sign,
+//!   parentheses for negative numbers, a `$` symbol, `,` thousands separators, an exponent, and
+//!   `&H` and `&O` prefixes. Anything else fails with `DISP_E_TYPEMISMATCH`.
+//! - Strings are read as booleans if they are `True` or `False`, ignoring case, or as numbers.
+//! - Strings are read as dates in the `M/D/YYYY h:mm:ss AM` and `YYYY-MM-DD hh:mm:ss` formats,
+//!   and dates are written in the former.
+//! - `Empty` converts to zero, `False` or an empty string, and `Null` to nothing but itself.
+//! - Objects are converted through their default (`DISPID_VALUE`) property, unless
+//!   `VARIANT_NOVALUEPROP` is set.
+//!
+//! The tests at the end of this module check these rules with a table of conversions per type,
+//! and on Windows check that `VariantChangeType` gives the same results.
+
+use std::mem::ManuallyDrop;
+
+use windows::{
+    Win32::{
+        Foundation::{
+            DISP_E_BADVARTYPE, DISP_E_OVERFLOW, DISP_E_TYPEMISMATCH, E_INVALIDARG, E_OUTOFMEMORY,
+            SysAllocStringByteLen, SysStringByteLen,
+        },
+        System::{
+            Com::{DISPATCH_PROPERTYGET, DISPPARAMS, IDispatch},
+            Ole::DISPID_VALUE,
+            Variant::{
+                VAR_CHANGE_FLAGS, VARENUM, VARIANT, VARIANT_0_0, VARIANT_ALPHABOOL,
+                VARIANT_LOCALBOOL, VARIANT_NOVALUEPROP, VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF,
+                VT_CY, VT_DATE, VT_DECIMAL, VT_DISPATCH, VT_EMPTY, VT_ERROR, VT_I1, VT_I2, VT_I4,
+                VT_I8, VT_INT, VT_NULL, VT_R4, VT_R8, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT,
+                VT_UNKNOWN, VT_VARIANT,
+            },
+        },
+    },
+    core::{BSTR, ComInterface, GUID, IUnknown, Result},
+};
+
+use crate::{
+    Currency, Decimal, OleDate, ToVariant, Variant,
+    array::{self, VT_ARRAY_UI1},
+    decimal::rescale,
+};
+
+/// The date of day zero, to which times without a date belong.
+const DAY_ZERO: (i32, u32, u32) = (1899, 12, 30);
+
+/// A numeric value read from a variant, before it is converted to the target type.
+#[derive(Clone, Copy)]
+enum Number {
+    /// A boolean, which is `-1` or all bits set when true.
+    Boolean(bool),
+    /// An integer, exactly.
+    Integer(i128),
+    /// A floating point value or date.
+    Float(f64),
+    /// A currency, decimal or parsed value of `mantissa / 10^scale`, exactly.
+    Scaled(i128, u32),
+}
+
+/// Convert a [`VARIANT`] to a new variant of type `vt`.
+pub(crate) fn change_type(
+    source: &VARIANT,
+    vt: VARENUM,
+    flags: VAR_CHANGE_FLAGS,
+) -> Result<Variant> {
+    let from = vt_of(source);
+    if from.0 & VT_BYREF.0 != 0 {
+        let value = dereference(source)?;
+        return change_type(&value, vt, flags);
+    }
+    if from == vt {
+        return Variant::copy(source);
+    }
+    // SAFETY: each field is only read for its type.
+    let value = unsafe { &source.Anonymous.Anonymous.Anonymous };
+    match vt {
+        VT_EMPTY | VT_NULL => {
+            // Any scalar other than an error, and `Null` itself, can be cleared
+            if from.0 > VT_UINT.0 || from == VT_ERROR || (vt == VT_EMPTY && from == VT_NULL) {
+                return Err(DISP_E_TYPEMISMATCH.into());
+            }
+            Ok(if vt == VT_NULL {
+                Variant::null()
+            } else {
+                Variant::empty()
+            })
+        }
+        VT_DISPATCH if from == VT_UNKNOWN => {
+            let object = match unsafe { value.punkVal.as_ref() } {
+                Some(unknown) => Some(unknown.cast::<IDispatch>()?),
+                None => None,
+            };
+            Ok(raw(VT_DISPATCH, |v00| {
+                v00.Anonymous.pdispVal = ManuallyDrop::new(object);
+            }))
+        }
+        VT_UNKNOWN if from == VT_DISPATCH => {
+            let object = unsafe { value.pdispVal.as_ref() }.map(IDispatch::cast::<IUnknown>);
+            let object = object.transpose()?;
+            Ok(raw(VT_UNKNOWN, |v00| {
+                v00.Anonymous.punkVal = ManuallyDrop::new(object);
+            }))
+        }
+        VT_DISPATCH | VT_UNKNOWN | VT_ERROR => Err(DISP_E_TYPEMISMATCH.into()),
+        _ if from == VT_DISPATCH => {
+            if flags.0 & VARIANT_NOVALUEPROP.0 != 0 {
+                return Err(DISP_E_TYPEMISMATCH.into());
+            }
+            let object = unsafe { value.pdispVal.as_ref() }.ok_or(DISP_E_TYPEMISMATCH)?;
+            let value = default_value(object)?;
+            change_type(&value, vt, flags)
+        }
+        VT_BSTR => to_string(source, from, flags),
+        _ if vt == VT_ARRAY_UI1 && from == VT_BSTR => {
+            let bytes = unsafe { bstr_bytes(&value.bstrVal) };
+            let elements = bytes.iter().map(ToVariant::to_variant).collect();
+            let psa = array::create_typed_safearray(VT_UI1, &[(0, bytes.len())], elements)?;
+            Ok(raw(vt, |v00| v00.Anonymous.parray = psa))
+        }
+        _ if vt.0 & (VT_ARRAY.0 | VT_BYREF.0) != 0 => Err(DISP_E_TYPEMISMATCH.into()),
+        VT_BOOL if from == VT_BSTR => {
+            let text = unsafe { value.bstrVal.to_string() };
+            let text = text.trim();
+            if text.eq_ignore_ascii_case("true") {
+                Ok(true.to_variant())
+            } else if text.eq_ignore_ascii_case("false") {
+                Ok(false.to_variant())
+            } else {
+                from_number(parse_number(text)?, vt)
+            }
+        }
+        VT_DATE if from == VT_BSTR => {
+            let text = unsafe { value.bstrVal.to_string() };
+            let date = parse_date(&text).ok_or(DISP_E_TYPEMISMATCH)?;
+            Ok(raw(VT_DATE, |v00| v00.Anonymous.date = f64::from(date)))
+        }
+        _ => from_number(number(source, from)?, vt),
+    }
+}
+
+fn vt_of(variant: &VARIANT) -> VARENUM {
+    // SAFETY: `vt` is valid for every variant.
+    unsafe { variant.Anonymous.Anonymous.vt }
+}
+
+/// Create a variant of type `vt`, setting its value with `set`. The value must be owned by the
+/// variant.
+fn raw(vt: VARENUM, set: impl FnOnce(&mut VARIANT_0_0)) -> Variant {
+    let mut v00 = VARIANT_0_0 {
+        vt,
+        ..Default::default()
+    };
+    set(&mut v00);
+    Variant::from_v00(v00)
+}
+
+/// Copy the value referenced by a `VT_BYREF` variant.
+fn dereference(source: &VARIANT) -> Result<Variant> {
+    let vt = VARENUM(vt_of(source).0 & !VT_BYREF.0);
+    // SAFETY: a `VT_BYREF` variant points to a value of its type.
+    unsafe {
+        let value = &source.Anonymous.Anonymous.Anonymous;
+        if value.byref.is_null() {
+            return Err(E_INVALIDARG.into());
+        }
+        let mut view = VARIANT::default();
+        if vt == VT_VARIANT {
+            return Variant::copy(&*value.pvarVal);
+        } else if vt == VT_DECIMAL {
+            view.Anonymous.decVal = *value.pdecVal;
+            view.Anonymous.decVal.wReserved = VT_DECIMAL.0;
+        } else {
+            let size = match vt {
+                VT_I1 | VT_UI1 => 1,
+                VT_I2 | VT_UI2 | VT_BOOL => 2,
+                VT_I4 | VT_UI4 | VT_INT | VT_UINT | VT_R4 | VT_ERROR => 4,
+                VT_I8 | VT_UI8 | VT_R8 | VT_DATE | VT_CY => 8,
+                VT_BSTR | VT_DISPATCH | VT_UNKNOWN => size_of::<usize>(),
+                _ if vt.0 & VT_ARRAY.0 != 0 => size_of::<usize>(),
+                _ => return Err(DISP_E_BADVARTYPE.into()),
+            };
+            let v00 = &mut *view.Anonymous.Anonymous;
+            v00.vt = vt;
+            std::ptr::copy_nonoverlapping(
+                value.byref.cast::<u8>(),
+                (&raw mut v00.Anonymous).cast::<u8>(),
+                size,
+            );
+        }
+        // The view borrows the referenced value, so it is copied rather than owned
+        Variant::copy(&view)
+    }
+}
+
+/// Read the default property of an object.
+fn default_value(object: &IDispatch) -> Result<Variant> {
+    let mut result = Variant::null();
+    unsafe {
+        object.Invoke(
+            DISPID_VALUE.cast_signed(),
+            &GUID::zeroed(),
+            0,
+            DISPATCH_PROPERTYGET,
+            &DISPPARAMS::default(),
+            Some(result.as_raw_mut()),
+            None,
+            None,
+        )?;
+    }
+    Ok(result)
+}
+
+/// The bytes of a `BSTR`, which may be an odd number.
+///
+/// # Safety
+///
+/// `bstr` must be a valid `BSTR`.
+unsafe fn bstr_bytes(bstr: &BSTR) -> Vec<u8> {
+    unsafe {
+        let pointer: *const u16 = std::mem::transmute_copy(bstr);
+        if pointer.is_null() {
+            return vec![];
+        }
+        let len = SysStringByteLen(bstr) as usize;
+        std::slice::from_raw_parts(pointer.cast::<u8>(), len).to_vec()
+    }
+}
+
+/// Read a numeric value from a scalar variant.
+fn number(source: &VARIANT, from: VARENUM) -> Result<Number> {
+    // SAFETY: each field is only read for its type.
+    unsafe {
+        let value = &source.Anonymous.Anonymous.Anonymous;
+        Ok(match from {
+            VT_EMPTY => Number::Integer(0),
+            VT_I1 => Number::Integer(value.cVal.cast_signed().into()),
+            VT_I2 => Number::Integer(value.iVal.into()),
+            VT_I4 => Number::Integer(value.lVal.into()),
+            VT_I8 => Number::Integer(value.llVal.into()),
+            VT_UI1 => Number::Integer(value.bVal.into()),
+            VT_UI2 => Number::Integer(value.uiVal.into()),
+            VT_UI4 => Number::Integer(value.ulVal.into()),
+            VT_UI8 => Number::Integer(value.ullVal.into()),
+            VT_INT => Number::Integer(value.intVal.into()),
+            VT_UINT => Number::Integer(value.uintVal.into()),
+            VT_BOOL => Number::Boolean(value.boolVal.as_bool()),
+            VT_R4 => Number::Float(value.fltVal.into()),
+            VT_R8 => Number::Float(value.dblVal),
+            VT_DATE => Number::Float(value.date),
+            VT_CY => Number::Scaled(Currency::from(value.cyVal).scaled().into(), 4),
+            VT_DECIMAL => {
+                let decimal = Decimal::try_from(source.Anonymous.decVal)?;
+                Number::Scaled(decimal.mantissa(), decimal.scale().into())
+            }
+            VT_BSTR => parse_number(&value.bstrVal.to_string())?,
+            _ => return Err(DISP_E_TYPEMISMATCH.into()),
+        })
+    }
+}
+
+/// Convert a numeric value to a scalar variant of type `vt`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // range checked before casting
+fn from_number(number: Number, vt: VARENUM) -> Result<Variant> {
+    Ok(match vt {
+        VT_I1 => (integer(number, i8::MIN.into(), i8::MAX.into())? as i8).to_variant(),
+        VT_I2 => (integer(number, i16::MIN.into(), i16::MAX.into())? as i16).to_variant(),
+        VT_I4 => (integer(number, i32::MIN.into(), i32::MAX.into())? as i32).to_variant(),
+        VT_I8 => (integer(number, i64::MIN.into(), i64::MAX.into())? as i64).to_variant(),
+        VT_UI1 => (integer(number, 0, u8::MAX.into())? as u8).to_variant(),
+        VT_UI2 => (integer(number, 0, u16::MAX.into())? as u16).to_variant(),
+        VT_UI4 => (integer(number, 0, u32::MAX.into())? as u32).to_variant(),
+        VT_UI8 => (integer(number, 0, u64::MAX.into())? as u64).to_variant(),
+        VT_INT => {
+            let value = integer(number, i32::MIN.into(), i32::MAX.into())? as i32;
+            raw(VT_INT, |v00| v00.Anonymous.intVal = value)
+        }
+        VT_UINT => {
+            let value = integer(number, 0, u32::MAX.into())? as u32;
+            raw(VT_UINT, |v00| v00.Anonymous.uintVal = value)
+        }
+        VT_R4 => {
+            let value = float(number);
+            if value.is_finite() && value.abs() > f64::from(f32::MAX) {
+                return Err(DISP_E_OVERFLOW.into());
+            }
+            (value as f32).to_variant()
+        }
+        VT_R8 => float(number).to_variant(),
+        VT_DATE => {
+            let value = float(number);
+            OleDate::try_from(value)?;
+            raw(VT_DATE, |v00| v00.Anonymous.date = value)
+        }
+        VT_BOOL => match number {
+            Number::Boolean(value) => value,
+            Number::Integer(value) | Number::Scaled(value, _) => value != 0,
+            Number::Float(value) => value != 0.0,
+        }
+        .to_variant(),
+        VT_CY => {
+            let scaled = i64::try_from(scaled(number, 4)?).map_err(|_| DISP_E_OVERFLOW)?;
+            Currency::from_scaled(scaled).to_variant()
+        }
+        VT_DECIMAL => decimal(number)?.to_variant(),
+        _ => return Err(DISP_E_BADVARTYPE.into()),
+    })
+}
+
+/// Round a value to an integer, half to even, failing if it is not within `min..=max`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)] // range checked
+fn integer(number: Number, min: i128, max: i128) -> Result<i128> {
+    let value = match number {
+        // `True` has every bit set, so is the maximum of unsigned types
+        Number::Boolean(value) => {
+            return Ok(if !value {
+                0
+            } else if min == 0 {
+                max
+            } else {
+                -1
+            });
+        }
+        Number::Integer(value) => value,
+        Number::Float(value) => {
+            if !(value >= min as f64 - 0.5 && value < max as f64 + 0.5) {
+                return Err(DISP_E_OVERFLOW.into());
+            }
+            value.round_ties_even() as i128
+        }
+        Number::Scaled(mantissa, scale) => rescale(mantissa, scale, 0).ok_or(DISP_E_OVERFLOW)?,
+    };
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(DISP_E_OVERFLOW.into())
+    }
+}
+
+#[allow(clippy::cast_precision_loss)] // as precise as a `f64` can be
+fn float(number: Number) -> f64 {
+    match number {
+        Number::Boolean(value) => -f64::from(u8::from(value)),
+        Number::Integer(value) => value as f64,
+        Number::Float(value) => value,
+        Number::Scaled(mantissa, scale) => format!("{mantissa}e-{scale}")
+            .parse()
+            .expect("a scaled integer is a valid float"),
+    }
+}
+
+/// Scale a value by `10^scale` and round it to an integer, half to even.
+#[allow(clippy::cast_possible_truncation)] // range checked
+fn scaled(number: Number, scale: u32) -> Result<i128> {
+    let value = match number {
+        Number::Boolean(value) => rescale(-i128::from(value), 0, scale),
+        Number::Integer(value) => rescale(value, 0, scale),
+        Number::Float(value) => {
+            let value = value * 10_f64.powi(scale.cast_signed());
+            (value.is_finite() && value.abs() < 1e38).then(|| value.round_ties_even() as i128)
+        }
+        Number::Scaled(mantissa, from) => rescale(mantissa, from, scale),
+    };
+    value.ok_or_else(|| DISP_E_OVERFLOW.into())
+}
+
+/// Convert a value to a decimal, rounding it to fewer decimal places if it has too many digits.
+fn decimal(number: Number) -> Result<Decimal> {
+    let (mut mantissa, mut scale) = match number {
+        Number::Boolean(_) | Number::Integer(_) => (integer(number, i128::MIN, i128::MAX)?, 0),
+        // Floating point values are only precise to 15 significant digits
+        Number::Float(value) if value.is_finite() => {
+            match parse_number(&format!("{value:.14e}"))? {
+                Number::Integer(mantissa) => (mantissa, 0),
+                Number::Scaled(mantissa, scale) => (mantissa, scale),
+                _ => unreachable!("a formatted float parses exactly"),
+            }
+        }
+        Number::Float(_) => return Err(DISP_E_OVERFLOW.into()),
+        Number::Scaled(mantissa, scale) => (mantissa, scale),
+    };
+    loop {
+        if let Some(decimal) = u8::try_from(scale)
+            .ok()
+            .and_then(|scale| Decimal::new(mantissa, scale))
+        {
+            // Floating point values have no scale of their own
+            return Ok(if matches!(number, Number::Float(_)) {
+                decimal.normalize()
+            } else {
+                decimal
+            });
+        }
+        if scale == 0 {
+            return Err(DISP_E_OVERFLOW.into());
+        }
+        mantissa = rescale(mantissa, scale, scale - 1).ok_or(DISP_E_OVERFLOW)?;
+        scale -= 1;
+    }
+}
+
+/// Parse a number as `VarParseNumFromStr` does with `NUMPRS_STD` in the US English locale.
+fn parse_number(text: &str) -> Result<Number> {
+    parse_number_inner(text.trim()).unwrap_or_else(|| Err(DISP_E_TYPEMISMATCH.into()))
+}
+
+fn parse_number_inner(text: &str) -> Option<Result<Number>> {
+    let mut negative = false;
+    let mut text = text;
+    if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        negative = true;
+        text = inner.trim();
+    }
+    for sign in ['-', '+'] {
+        if let Some(rest) = text.strip_prefix(sign) {
+            negative ^= sign == '-';
+            text = rest.trim_start();
+            break;
+        } else if let Some(rest) = text.strip_suffix(sign) {
+            negative ^= sign == '-';
+            text = rest.trim_end();
+            break;
+        }
+    }
+    if let Some(rest) = text.strip_prefix('$') {
+        text = rest.trim_start();
+        if let Some(rest) = text.strip_prefix('-') {
+            negative = !negative;
+            text = rest;
+        }
+    }
+
+    // Hexadecimal and octal numbers are the bits of the smallest integer that holds them
+    let radix = match text.get(..2).map(str::to_ascii_uppercase).as_deref() {
+        Some("&H") => Some(16),
+        Some("&O") => Some(8),
+        _ => None,
+    };
+    if let Some(radix) = radix {
+        let bits = u64::from_str_radix(&text[2..], radix).ok()?;
+        let value = if let Ok(bits) = u16::try_from(bits) {
+            i128::from(bits.cast_signed())
+        } else if let Ok(bits) = u32::try_from(bits) {
+            i128::from(bits.cast_signed())
+        } else {
+            i128::from(bits.cast_signed())
+        };
+        return Some(Ok(Number::Integer(if negative { -value } else { value })));
+    }
+    parse_decimal(text, negative)
+}
+
+/// Parse an unsigned decimal number, with optional thousands separators and exponent.
+fn parse_decimal(text: &str, negative: bool) -> Option<Result<Number>> {
+    let (text, exponent) = match text.find(['e', 'E']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let exponent: i32 = match exponent {
+        Some(exponent) => {
+            let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            // Exponents too large for an `i32` overflow any type
+            let Ok(exponent) = exponent.parse() else {
+                return Some(Err(DISP_E_OVERFLOW.into()));
+            };
+            exponent
+        }
+        None => 0,
+    };
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if whole.starts_with(',')
+        || !whole.bytes().all(|b| b.is_ascii_digit() || b == b',')
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let digits: String = whole
+        .chars()
+        .filter(|&c| c != ',')
+        .chain(fraction.chars())
+        .collect();
+    if digits.is_empty() {
+        return None;
+    }
+    let digits = digits.trim_start_matches('0');
+    let exponent = i64::from(exponent) - i64::try_from(fraction.len()).ok()?;
+    let sign = if negative { "-" } else { "" };
+
+    let exact = if digits.is_empty() {
+        Some(Number::Integer(0))
+    } else if digits.len() <= 38 {
+        let mantissa: i128 = format!("{sign}{digits}").parse().ok()?;
+        match u32::try_from(-exponent) {
+            Ok(scale) => Some(Number::Scaled(mantissa, scale)),
+            Err(_) => u32::try_from(exponent)
+                .ok()
+                .and_then(|exponent| rescale(mantissa, 0, exponent))
+                .map(Number::Integer),
+        }
+    } else {
+        None
+    };
+    if let Some(number) = exact {
+        return Some(Ok(number));
+    }
+    // Values too large to be exact are as precise as a `f64`
+    let value: f64 = format!("{sign}{digits}e{exponent}").parse().ok()?;
+    Some(if value.is_finite() {
+        Ok(Number::Float(value))
+    } else {
+        Err(DISP_E_OVERFLOW.into())
+    })
+}
+
+/// Parse a date in the `M/D/YYYY h:mm:ss AM` or `YYYY-MM-DD hh:mm:ss` formats, either of which
+/// may have only a date or a time.
+fn parse_date(text: &str) -> Option<OleDate> {
+    let text = text.trim().to_ascii_uppercase();
+    let (text, meridiem) = if let Some(text) = text.strip_suffix("AM") {
+        (text, Some(0))
+    } else if let Some(text) = text.strip_suffix("PM") {
+        (text, Some(12))
+    } else {
+        (text.as_str(), None)
+    };
+
+    let mut date = None;
+    let mut time = None;
+    for part in text.split([' ', 'T']).filter(|part| !part.is_empty()) {
+        let slot = if part.contains(':') {
+            &mut time
+        } else {
+            &mut date
+        };
+        if slot.replace(part).is_some() {
+            return None;
+        }
+    }
+
+    let (year, month, day) = match date {
+        Some(date) => {
+            let parts: Vec<&str> = date.split(['/', '-']).collect();
+            let [first, second, third] = parts[..] else {
+                return None;
+            };
+            let number = |part: &str| part.parse::<u32>().ok();
+            let year = |part: &str| match (part.len(), part.parse::<i32>().ok()?) {
+                (1 | 2, year) if year < 30 => Some(year + 2000),
+                (1 | 2, year) => Some(year + 1900),
+                (_, year) => Some(year),
+            };
+            if first.len() == 4 {
+                (year(first)?, number(second)?, number(third)?)
+            } else {
+                (year(third)?, number(first)?, number(second)?)
+            }
+        }
+        None if time.is_some() => DAY_ZERO,
+        None => return None,
+    };
+
+    let (mut hour, minute, second, millisecond) = match time {
+        Some(time) => {
+            let parts: Vec<&str> = time.split(':').collect();
+            let (second, millisecond) = match parts.get(2) {
+                Some(second) => match second.split_once('.') {
+                    Some((second, millis)) if millis.len() == 3 => (second, millis.parse().ok()?),
+                    Some(_) => return None,
+                    None => (*second, 0),
+                },
+                None => ("0", 0),
+            };
+            if !(2..=3).contains(&parts.len()) {
+                return None;
+            }
+            let number = |part: &str| part.parse::<u32>().ok();
+            (
+                number(parts[0])?,
+                number(parts[1])?,
+                number(second)?,
+                millisecond,
+            )
+        }
+        None => (0, 0, 0, 0),
+    };
+    if let Some(offset) = meridiem {
+        if !(1..=12).contains(&hour) {
+            return None;
+        }
+        hour = hour % 12 + offset;
+    }
+    OleDate::from_ymd_hms_milli(year, month, day, hour, minute, second, millisecond)
+}
+
+/// Convert a scalar variant to a string.
+fn to_string(source: &VARIANT, from: VARENUM, flags: VAR_CHANGE_FLAGS) -> Result<Variant> {
+    // SAFETY: each field is only read for its type.
+    let value = unsafe { &source.Anonymous.Anonymous.Anonymous };
+    let text = match from {
+        VT_EMPTY => String::new(),
+        VT_BOOL if flags.0 & (VARIANT_ALPHABOOL.0 | VARIANT_LOCALBOOL.0) != 0 => {
+            if unsafe { value.boolVal }.as_bool() {
+                "True".to_string()
+            } else {
+                "False".to_string()
+            }
+        }
+        VT_R4 => format_float(unsafe { value.fltVal }.into(), 7),
+        VT_R8 => format_float(unsafe { value.dblVal }, 15),
+        VT_DATE => format_date(OleDate::try_from(unsafe { value.date })?),
+        VT_CY => Currency::from(unsafe { value.cyVal }).to_string(),
+        VT_DECIMAL => Decimal::try_from(unsafe { source.Anonymous.decVal })?
+            .normalize()
+            .to_string(),
+        _ if from == VT_ARRAY_UI1 => {
+            let psa = unsafe { value.parray };
+            // SAFETY: a `VT_ARRAY` variant holds a valid `SAFEARRAY`.
+            let (bytes, _) = unsafe {
+                array::read_safearray(psa, 1, |element| {
+                    Ok(element.Anonymous.Anonymous.Anonymous.bVal)
+                })
+            }?;
+            // SAFETY: the string is allocated with exactly the bytes of the array.
+            let bstr = unsafe { SysAllocStringByteLen(Some(&bytes)) };
+            if bstr.is_empty() && !bytes.is_empty() {
+                return Err(E_OUTOFMEMORY.into());
+            }
+            return Ok(raw(VT_BSTR, |v00| {
+                v00.Anonymous.bstrVal = ManuallyDrop::new(bstr);
+            }));
+        }
+        _ => match number(source, from)? {
+            Number::Boolean(value) => (-i32::from(value)).to_string(),
+            Number::Integer(value) => value.to_string(),
+            _ => return Err(DISP_E_TYPEMISMATCH.into()),
+        },
+    };
+    Ok(text.to_variant())
+}
+
+/// Format a floating point value to a number of significant digits, as `%G` does.
+fn format_float(value: f64, precision: usize) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    } else if !value.is_finite() {
+        return value.to_string();
+    }
+    let formatted = format!("{value:.*e}", precision - 1);
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("formatted with an exponent");
+    let exponent: i32 = exponent.parse().expect("the exponent is an integer");
+    let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+    if exponent < -4 || exponent >= i32::try_from(precision).unwrap_or(i32::MAX) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{mantissa}E{sign}{:02}", exponent.unsigned_abs());
+    }
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let point = exponent + 1;
+    match usize::try_from(point) {
+        Err(_) | Ok(0) => format!(
+            "{sign}0.{}{digits}",
+            "0".repeat(point.unsigned_abs() as usize)
+        ),
+        Ok(point) if point >= digits.len() => {
+            format!("{sign}{digits}{}", "0".repeat(point - digits.len()))
+        }
+        Ok(point) => format!("{sign}{}.{}", &digits[..point], &digits[point..]),
+    }
+}
+
+/// Format a date as `M/D/YYYY h:mm:ss AM`, leaving out the date on day zero and the time at
+/// midnight.
+fn format_date(date: OleDate) -> String {
+    // Dates are written to the nearest second
+    let millis = date.millis_since_epoch();
+    let date =
+        OleDate::from_millis_since_epoch((millis + 500).div_euclid(1000) * 1000).unwrap_or(date);
+    let (year, month, day) = date.ymd();
+    let (hour, minute, second, _) = date.hms_milli();
+    let meridiem = if hour < 12 { "AM" } else { "PM" };
+    let time = format!(
+        "{}:{minute:02}:{second:02} {meridiem}",
+        if hour % 12 == 0 { 12 } else { hour % 12 }
+    );
+    if (year, month, day) == DAY_ZERO {
+        time
+    } else if (hour, minute, second) == (0, 0, 0) {
+        format!("{month}/{day}/{year}")
+    } else {
+        format!("{month}/{day}/{year} {time}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{any::type_name, fmt::Debug};
+
+    use windows::{
+        Win32::{
+            Foundation::{DISP_E_OVERFLOW, DISP_E_TYPEMISMATCH},
+            System::Variant::{
+                VAR_CHANGE_FLAGS, VARENUM, VT_BOOL, VT_BSTR, VT_CY, VT_DATE, VT_DECIMAL, VT_I2,
+                VT_R8, VT_UI1,
+            },
+        },
+        core::HRESULT,
+    };
+
+    use super::change_type;
+    use crate::{Currency, Decimal, FromVariant, OleDate, ToVariant, Variant};
+
+    fn date(year: i32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> OleDate {
+        OleDate::from_ymd_hms_milli(year, month, day, hour, min, sec, 0).unwrap()
+    }
+
+    /// Convert each value to `vt` and read it as `T`, naming the row whose result is not the
+    /// expected one.
+    ///
+    /// Each value is converted both by these rules and by [`FromVariant`], which uses
+    /// `VariantChangeType` on Windows, so there the table also checks that the two agree.
+    #[track_caller]
+    fn check<T>(vt: VARENUM, table: &[(Variant, Result<T, HRESULT>)])
+    where
+        T: FromVariant + PartialEq + Debug,
+    {
+        for (row, (value, expected)) in table.iter().enumerate() {
+            let coerced = change_type(value, vt, VAR_CHANGE_FLAGS(0))
+                .and_then(|new| T::from_variant(&new))
+                .map_err(|e| e.code());
+            let native = T::from_variant(value).map_err(|e| e.code());
+            for actual in [coerced, native] {
+                assert_eq!(
+                    &actual,
+                    expected,
+                    "row {row}: {value:?} to {}",
+                    type_name::<T>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn to_i16() {
+        let to_i16 = [
+            ("42".to_variant(), Ok(42)),
+            (" -7 ".to_variant(), Ok(-7)),
+            ("7-".to_variant(), Ok(-7)),
+            ("(12)".to_variant(), Ok(-12)),
+            ("$1,234".to_variant(), Ok(1234)),
+            ("1.5E2".to_variant(), Ok(150)),
+            ("2.5".to_variant(), Ok(2)),
+            ("3.5".to_variant(), Ok(4)),
+            ("&HFF".to_variant(), Ok(255)),
+            ("&HFFFF".to_variant(), Ok(-1)),
+            ("&O17".to_variant(), Ok(15)),
+            ("".to_variant(), Err(DISP_E_TYPEMISMATCH)),
+            ("True".to_variant(), Err(DISP_E_TYPEMISMATCH)),
+            ("12abc".to_variant(), Err(DISP_E_TYPEMISMATCH)),
+            ("40000".to_variant(), Err(DISP_E_OVERFLOW)),
+            (2.5.to_variant(), Ok(2)),
+            ((-2.5).to_variant(), Ok(-2)),
+            (32767.49.to_variant(), Ok(32767)),
+            (32767.5.to_variant(), Err(DISP_E_OVERFLOW)),
+            (true.to_variant(), Ok(-1)),
+            (Currency::from_scaled(15_000).to_variant(), Ok(2)),
+            (Decimal::new(-255, 1).unwrap().to_variant(), Ok(-26)),
+            (Variant::empty(), Ok(0)),
+            (Variant::null(), Err(DISP_E_TYPEMISMATCH)),
+        ];
+        check::<i16>(VT_I2, &to_i16);
+    }
+
+    #[test]
+    fn to_u8() {
+        let to_u8 = [
+            (true.to_variant(), Ok(255)),
+            ((-1).to_variant(), Err(DISP_E_OVERFLOW)),
+            (255.4.to_variant(), Ok(255)),
+            ("256".to_variant(), Err(DISP_E_OVERFLOW)),
+        ];
+        check::<u8>(VT_UI1, &to_u8);
+    }
+
+    #[test]
+    fn to_bool() {
+        let to_bool = [
+            ("True".to_variant(), Ok(true)),
+            (" false ".to_variant(), Ok(false)),
+            ("-1".to_variant(), Ok(true)),
+            ("0.0".to_variant(), Ok(false)),
+            ("Yes".to_variant(), Err(DISP_E_TYPEMISMATCH)),
+            ("".to_variant(), Err(DISP_E_TYPEMISMATCH)),
+            (2.to_variant(), Ok(true)),
+            (0.0.to_variant(), Ok(false)),
+            (Variant::empty(), Ok(false)),
+        ];
+        check::<bool>(VT_BOOL, &to_bool);
+    }
+
+    #[test]
+    fn to_f64() {
+        let to_f64 = [
+            ("1,234.5".to_variant(), Ok(1234.5)),
+            ("-1e-3".to_variant(), Ok(-0.001)),
+            ("1e400".to_variant(), Err(DISP_E_OVERFLOW)),
+            (true.to_variant(), Ok(-1.0)),
+            (Currency::from_scaled(12_345).to_variant(), Ok(1.2345)),
+            (date(1900, 1, 1, 12, 0, 0).to_variant(), Ok(2.5)),
+        ];
+        check::<f64>(VT_R8, &to_f64);
+    }
+
+    #[test]
+    fn to_string() {
+        let to_string = [
+            (42.to_variant(), Ok("42")),
+            ((-1.5).to_variant(), Ok("-1.5")),
+            ((1.0 / 3.0).to_variant(), Ok("0.333333333333333")),
+            (0.1_f32.to_variant(), Ok("0.1")),
+            (1e20.to_variant(), Ok("1E+20")),
+            (0.00001.to_variant(), Ok("1E-05")),
+            (true.to_variant(), Ok("-1")),
+            (false.to_variant(), Ok("0")),
+            (Currency::from_scaled(15_000).to_variant(), Ok("1.5")),
+            (Decimal::new(1050, 2).unwrap().to_variant(), Ok("10.5")),
+            (
+                date(2024, 2, 29, 13, 5, 9).to_variant(),
+                Ok("2/29/2024 1:05:09 PM"),
+            ),
+            (date(2024, 2, 29, 0, 0, 0).to_variant(), Ok("2/29/2024")),
+            (date(1899, 12, 30, 9, 30, 0).to_variant(), Ok("9:30:00 AM")),
+            (date(1899, 12, 30, 0, 0, 0).to_variant(), Ok("12:00:00 AM")),
+            (Variant::empty(), Ok("")),
+            (Variant::null(), Err(DISP_E_TYPEMISMATCH)),
+        ];
+        check::<String>(
+            VT_BSTR,
+            &to_string.map(|(value, expected)| (value, expected.map(str::to_string))),
+        );
+    }
+
+    #[test]
+    fn to_date() {
+        let to_date = [
+            (
+                "2/29/2024 1:05:09 PM".to_variant(),
+                Ok(date(2024, 2, 29, 13, 5, 9)),
+            ),
+            (
+                "2024-02-29 13:05".to_variant(),
+                Ok(date(2024, 2, 29, 13, 5, 0)),
+            ),
+            (
+                "2024-02-29T13:05:09".to_variant(),
+                Ok(date(2024, 2, 29, 13, 5, 9)),
+            ),
+            ("12/31/99".to_variant(), Ok(date(1999, 12, 31, 0, 0, 0))),
+            ("12:30 am".to_variant(), Ok(date(1899, 12, 30, 0, 30, 0))),
+            ("2/30/2024".to_variant(), Err(DISP_E_TYPEMISMATCH)),
+            ("tomorrow".to_variant(), Err(DISP_E_TYPEMISMATCH)),
+            (2.5.to_variant(), Ok(date(1900, 1, 1, 12, 0, 0))),
+            (3e6.to_variant(), Err(DISP_E_OVERFLOW)),
+        ];
+        check::<OleDate>(VT_DATE, &to_date);
+    }
+
+    #[test]
+    fn to_currency() {
+        let to_currency = [
+            ("1.23456".to_variant(), Ok(Currency::from_scaled(12_346))),
+            ("0.00005".to_variant(), Ok(Currency::from_scaled(0))),
+            (1.98767.to_variant(), Ok(Currency::from_scaled(19_877))),
+            (1e15.to_variant(), Err(DISP_E_OVERFLOW)),
+        ];
+        check::<Currency>(VT_CY, &to_currency);
+    }
+
+    #[test]
+    fn to_decimal() {
+        let to_decimal = [
+            ("-12.50".to_variant(), Ok(Decimal::new(-1250, 2).unwrap())),
+            (0.1.to_variant(), Ok(Decimal::new(1, 1).unwrap())),
+            (7.to_variant(), Ok(Decimal::new(7, 0).unwrap())),
+            (1e30.to_variant(), Err(DISP_E_OVERFLOW)),
+        ];
+        check::<Decimal>(VT_DECIMAL, &to_decimal);
+    }
+}
@@ -0,0 +1,512 @@
+use std::{
+    cell::Cell,
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+use windows::{
+    Win32::{
+        Foundation::DISP_E_TYPEMISMATCH,
+        System::Variant::{
+            VARENUM, VARIANT, VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF, VT_CY, VT_DATE, VT_DECIMAL,
+            VT_DISPATCH, VT_EMPTY, VT_ERROR, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_NULL, VT_R4,
+            VT_R8, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT, VT_UNKNOWN, VT_VARIANT,
+        },
+    },
+    core::{Error, Result},
+};
+
+use crate::{FromVariant, HasIDispatch, IDispatch, Variant, utils, variant};
+
+/// How far values may be converted from the type they are held as when they are read from a
+/// [`VARIANT`].
+///
+/// The conversion can be chosen for a single read or call with [`Conversion::scope`], for an
+/// object with [`WithConversion`], or for the whole program with [`Conversion::set_global`].
+/// One chosen for a call applies over one chosen for the object it is made on, which applies over
+/// the global conversion, and values are coerced by OLE Automation rules if none are chosen.
+///
+/// Values that are not allowed fail with a [`ConversionError`], returned as `DISP_E_TYPEMISMATCH`
+/// and a message naming both types, such as "strict conversion cannot read `VT_BSTR` as `VT_I2`".
+///
+/// ```rust
+/// use com_shim::{Conversion, FromVariant, ToVariant};
+/// use windows::Win32::Foundation::DISP_E_TYPEMISMATCH;
+///
+/// let empty = "".to_variant();
+/// assert!(Conversion::Ole.read::<i16>(&"42".to_variant()).is_ok());
+///
+/// let error = Conversion::Strict.read::<i16>(&empty).unwrap_err();
+/// assert_eq!(error.code(), DISP_E_TYPEMISMATCH);
+///
+/// assert_eq!(Conversion::Widening.read::<i64>(&7_i16.to_variant()), Ok(7));
+/// assert!(Conversion::Widening.read::<i16>(&7_i64.to_variant()).is_err());
+///
+/// let result = Conversion::Strict.scope(|| f64::from_variant(&1.5_f32.to_variant()));
+/// assert!(result.is_err());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Conversion {
+    /// Values must already be of the type being read.
+    Strict,
+    /// Numbers may also be read as a type that holds every value of their own, such as a
+    /// `VT_I2` as an `i64` or a `VT_R4` as an `f64`.
+    Widening,
+    /// Values are coerced by OLE Automation rules, as with `VariantChangeType`, so a `VT_BSTR`
+    /// holding `"42"` can be read as an `i32`.
+    #[default]
+    Ole,
+}
+
+/// The global conversion, as the index of a variant of [`Conversion`].
+static GLOBAL: AtomicU8 = AtomicU8::new(Conversion::Ole as u8);
+
+thread_local! {
+    /// The conversion chosen for the current call.
+    static CALL: Cell<Option<Conversion>> = const { Cell::new(None) };
+    /// The conversion chosen for the object the current call is made on.
+    static OBJECT: Cell<Option<Conversion>> = const { Cell::new(None) };
+}
+
+impl Conversion {
+    /// The conversion used when none is chosen for a call or object.
+    #[must_use]
+    pub fn global() -> Self {
+        match GLOBAL.load(Ordering::Relaxed) {
+            0 => Conversion::Strict,
+            1 => Conversion::Widening,
+            _ => Conversion::Ole,
+        }
+    }
+
+    /// Set the conversion used when none is chosen for a call or object.
+    pub fn set_global(self) {
+        GLOBAL.store(self as u8, Ordering::Relaxed);
+    }
+
+    /// The conversion that applies on this thread now.
+    #[must_use]
+    pub fn current() -> Self {
+        CALL.get()
+            .or_else(|| OBJECT.get())
+            .unwrap_or_else(Self::global)
+    }
+
+    /// Run `f` with this conversion, whatever the conversion of any object it uses.
+    ///
+    /// This also applies to calls `f` makes through a [`Remote`](crate::Remote) handle.
+    pub fn scope<R>(self, f: impl FnOnce() -> R) -> R {
+        utils::scoped(&CALL, Some(self), f)
+    }
+
+    /// The conversion chosen with [`Conversion::scope`] on this thread, if one is.
+    pub(crate) fn chosen() -> Option<Self> {
+        CALL.get()
+    }
+
+    /// Read a value from a [`VARIANT`] with this conversion.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the value cannot be converted, or this conversion does not allow it.
+    pub fn read<T: FromVariant>(self, variant: &VARIANT) -> Result<T> {
+        self.scope(|| T::from_variant(variant))
+    }
+
+    /// Whether this conversion allows a value of type `from` to be read as `to`.
+    #[must_use]
+    pub fn allows(self, from: VARENUM, to: VARENUM) -> bool {
+        // A reference is read as the value it refers to
+        let from = VARENUM(from.0 & !VT_BYREF.0);
+        match self {
+            Conversion::Strict => same(from, to),
+            Conversion::Widening => same(from, to) || widens(from, to),
+            Conversion::Ole => true,
+        }
+    }
+}
+
+impl fmt::Display for Conversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conversion::Strict => write!(f, "strict"),
+            Conversion::Widening => write!(f, "widening"),
+            Conversion::Ole => write!(f, "OLE"),
+        }
+    }
+}
+
+/// Run `f` with the conversion chosen for the object a call is made on, if there is one.
+pub(crate) fn object_scope<R>(conversion: Option<Conversion>, f: impl FnOnce() -> R) -> R {
+    match conversion {
+        Some(conversion) => utils::scoped(&OBJECT, Some(conversion), f),
+        None => f(),
+    }
+}
+
+/// Run `f` with the global conversion, whatever was chosen for the current call or object.
+pub(crate) fn detached<R>(f: impl FnOnce() -> R) -> R {
+    utils::scoped(&CALL, None, || utils::scoped(&OBJECT, None, f))
+}
+
+/// `VT_INT` and `VT_UINT` are the same as `VT_I4` and `VT_UI4`.
+fn same(from: VARENUM, to: VARENUM) -> bool {
+    let canonical = |vt| match vt {
+        VT_INT => VT_I4,
+        VT_UINT => VT_UI4,
+        vt => vt,
+    };
+    canonical(from) == canonical(to)
+}
+
+/// Whether every value of numeric type `from` can be held exactly by `to`.
+fn widens(from: VARENUM, to: VARENUM) -> bool {
+    match from {
+        VT_I1 => matches!(
+            to,
+            VT_I2 | VT_I4 | VT_INT | VT_I8 | VT_R4 | VT_R8 | VT_CY | VT_DECIMAL
+        ),
+        VT_UI1 => matches!(
+            to,
+            VT_I2
+                | VT_I4
+                | VT_INT
+                | VT_I8
+                | VT_UI2
+                | VT_UI4
+                | VT_UINT
+                | VT_UI8
+                | VT_R4
+                | VT_R8
+                | VT_CY
+                | VT_DECIMAL
+        ),
+        VT_I2 => matches!(
+            to,
+            VT_I4 | VT_INT | VT_I8 | VT_R4 | VT_R8 | VT_CY | VT_DECIMAL
+        ),
+        VT_UI2 => matches!(
+            to,
+            VT_I4 | VT_INT | VT_I8 | VT_UI4 | VT_UINT | VT_UI8 | VT_R4 | VT_R8 | VT_CY | VT_DECIMAL
+        ),
+        VT_I4 | VT_INT => matches!(to, VT_I8 | VT_R8 | VT_CY | VT_DECIMAL),
+        VT_UI4 | VT_UINT => matches!(to, VT_I8 | VT_UI8 | VT_R8 | VT_CY | VT_DECIMAL),
+        VT_I8 | VT_UI8 | VT_CY => to == VT_DECIMAL,
+        VT_R4 => to == VT_R8,
+        _ => false,
+    }
+}
+
+/// Convert a [`VARIANT`] into a new variant of type `vt`, if the current conversion allows it.
+pub(crate) fn convert(source: &VARIANT, vt: VARENUM) -> Result<Variant> {
+    // SAFETY: `vt` is valid for every variant.
+    let from = unsafe { source.Anonymous.Anonymous.vt };
+    let conversion = Conversion::current();
+    if !conversion.allows(from, vt) {
+        let error = ConversionError {
+            conversion,
+            source_vt: from,
+            target_vt: vt,
+        };
+        tracing::debug!("{error}");
+        return Err(error.into());
+    }
+    variant::change_type(source, vt)
+}
+
+/// A value was not read because the [`Conversion`] that applied does not allow its type to be
+/// read as the type asked for.
+///
+/// Reads return this as a `DISP_E_TYPEMISMATCH` [`Error`], whose message names the conversion
+/// and both types. It can be recovered from that error with [`ConversionError::from_error`]:
+///
+/// ```rust
+/// use com_shim::{Conversion, ConversionError, ToVariant};
+/// use windows::Win32::System::Variant::{VT_BSTR, VT_I2};
+///
+/// let error = Conversion::Strict.read::<i16>(&"42".to_variant()).unwrap_err();
+/// let rejected = ConversionError::from_error(&error).unwrap();
+/// assert_eq!(rejected.conversion(), Conversion::Strict);
+/// assert_eq!((rejected.source_vt(), rejected.target_vt()), (VT_BSTR, VT_I2));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConversionError {
+    conversion: Conversion,
+    source_vt: VARENUM,
+    target_vt: VARENUM,
+}
+
+impl ConversionError {
+    /// The conversion that did not allow the value to be read.
+    #[must_use]
+    pub fn conversion(&self) -> Conversion {
+        self.conversion
+    }
+
+    /// The type the value was held as, which may be a `VT_BYREF` reference.
+    #[must_use]
+    pub fn source_vt(&self) -> VARENUM {
+        self.source_vt
+    }
+
+    /// The type the value was to be read as.
+    #[must_use]
+    pub fn target_vt(&self) -> VARENUM {
+        self.target_vt
+    }
+
+    /// Recover the conversion error an [`Error`] was returned for.
+    ///
+    /// Returns [`None`] if the error was not returned for a value a conversion did not allow,
+    /// or has lost its message, such as by being converted to an `HRESULT` and back.
+    #[must_use]
+    pub fn from_error(error: &Error) -> Option<Self> {
+        if error.code() != DISP_E_TYPEMISMATCH {
+            return None;
+        }
+        let message = error.message().to_string();
+        let (conversion, types) = message.split_once(" conversion cannot read ")?;
+        let (from, to) = types.split_once(" as ")?;
+        Some(Self {
+            conversion: match conversion {
+                "strict" => Conversion::Strict,
+                "widening" => Conversion::Widening,
+                "OLE" => Conversion::Ole,
+                _ => return None,
+            },
+            source_vt: VtName::parse(from)?,
+            target_vt: VtName::parse(to)?,
+        })
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} conversion cannot read {} as {}",
+            self.conversion,
+            VtName(self.source_vt),
+            VtName(self.target_vt)
+        )
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl From<ConversionError> for Error {
+    fn from(error: ConversionError) -> Self {
+        Error::new(DISP_E_TYPEMISMATCH, error.to_string().into())
+    }
+}
+
+/// The names of the base types, without `VT_BYREF` or `VT_ARRAY`.
+const VT_NAMES: [(VARENUM, &str); 23] = [
+    (VT_EMPTY, "VT_EMPTY"),
+    (VT_NULL, "VT_NULL"),
+    (VT_I1, "VT_I1"),
+    (VT_I2, "VT_I2"),
+    (VT_I4, "VT_I4"),
+    (VT_I8, "VT_I8"),
+    (VT_UI1, "VT_UI1"),
+    (VT_UI2, "VT_UI2"),
+    (VT_UI4, "VT_UI4"),
+    (VT_UI8, "VT_UI8"),
+    (VT_INT, "VT_INT"),
+    (VT_UINT, "VT_UINT"),
+    (VT_R4, "VT_R4"),
+    (VT_R8, "VT_R8"),
+    (VT_CY, "VT_CY"),
+    (VT_DATE, "VT_DATE"),
+    (VT_BSTR, "VT_BSTR"),
+    (VT_DISPATCH, "VT_DISPATCH"),
+    (VT_ERROR, "VT_ERROR"),
+    (VT_BOOL, "VT_BOOL"),
+    (VT_VARIANT, "VT_VARIANT"),
+    (VT_UNKNOWN, "VT_UNKNOWN"),
+    (VT_DECIMAL, "VT_DECIMAL"),
+];
+
+/// Writes the name of a type, such as `VT_ARRAY | VT_I4`.
+pub(crate) struct VtName(pub(crate) VARENUM);
+
+impl VtName {
+    /// Read back the type whose name was written.
+    fn parse(name: &str) -> Option<VARENUM> {
+        let (name, byref) = match name.strip_prefix("VT_BYREF | ") {
+            Some(name) => (name, VT_BYREF.0),
+            None => (name, 0),
+        };
+        let (name, array) = match name.strip_prefix("VT_ARRAY | ") {
+            Some(name) => (name, VT_ARRAY.0),
+            None => (name, 0),
+        };
+        let base = match name.strip_prefix("VT ") {
+            Some(number) => number.parse().ok()?,
+            None => VT_NAMES.iter().find(|(_, known)| *known == name)?.0.0,
+        };
+        Some(VARENUM(byref | array | base))
+    }
+}
+
+impl fmt::Display for VtName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vt = self.0;
+        if vt.0 & VT_BYREF.0 != 0 {
+            write!(f, "VT_BYREF | ")?;
+        }
+        if vt.0 & VT_ARRAY.0 != 0 {
+            write!(f, "VT_ARRAY | ")?;
+        }
+        let base = VARENUM(vt.0 & 0xFFF);
+        match VT_NAMES.iter().find(|(known, _)| *known == base) {
+            Some((_, name)) => write!(f, "{name}"),
+            None => write!(f, "VT {}", base.0),
+        }
+    }
+}
+
+/// An object whose values are read with a chosen [`Conversion`].
+///
+/// This has every method of the class it wraps, and can be read with [`FromDispatch`](crate::FromDispatch):
+///
+/// ```rust
+/// use com_shim::{ComObject, Conversion, WithConversion, com_object, com_shim};
+/// use windows::Win32::Foundation::DISP_E_TYPEMISMATCH;
+///
+/// /// An object whose `Text` is "42".
+/// struct Fake;
+///
+/// com_object! {
+///     impl Fake {
+///         #[com(get)]
+///         fn text(&self) -> String {
+///             "42".to_string()
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     struct Field {
+///         Text: i32,
+///     }
+/// }
+///
+/// let field = Field::from(Fake.into_idispatch());
+/// assert_eq!(field.text(), Ok(42));
+///
+/// let field = WithConversion::new(field, Conversion::Strict);
+/// assert_eq!(field.text().unwrap_err().code(), DISP_E_TYPEMISMATCH);
+/// ```
+#[derive(Clone, Debug)]
+pub struct WithConversion<T> {
+    object: T,
+    conversion: Conversion,
+}
+
+impl<T> WithConversion<T> {
+    /// Read the values of `object` with `conversion`.
+    pub fn new(object: T, conversion: Conversion) -> Self {
+        Self { object, conversion }
+    }
+
+    /// The conversion values are read with.
+    pub fn conversion(&self) -> Conversion {
+        self.conversion
+    }
+
+    /// Get back the object.
+    pub fn into_inner(self) -> T {
+        self.object
+    }
+}
+
+impl<T: HasIDispatch> HasIDispatch for WithConversion<T> {
+    fn get_idispatch(&self) -> &IDispatch {
+        self.object.get_idispatch()
+    }
+
+    fn conversion(&self) -> Option<Conversion> {
+        Some(self.conversion)
+    }
+
+    fn dry_run(&self) -> Option<&crate::DryRun> {
+        self.object.dry_run()
+    }
+
+    fn deadline(&self) -> Option<Duration> {
+        self.object.deadline()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::{
+        Win32::{
+            Foundation::DISP_E_TYPEMISMATCH,
+            System::Variant::{VARENUM, VT_ARRAY, VT_BSTR, VT_BYREF, VT_I2, VT_I4, VT_I8, VT_R8},
+        },
+        core::Error,
+    };
+
+    use super::{Conversion, ConversionError, VT_NAMES, VtName};
+    use crate::{FromVariant, ToVariant};
+
+    #[test]
+    fn rejections_are_recovered_from_errors() {
+        let error = Conversion::Widening
+            .read::<i16>(&7_i64.to_variant())
+            .unwrap_err();
+        assert_eq!(error.code(), DISP_E_TYPEMISMATCH);
+        let rejected = ConversionError::from_error(&error).unwrap();
+        assert_eq!(rejected.conversion(), Conversion::Widening);
+        assert_eq!(rejected.source_vt(), VT_I8);
+        assert_eq!(rejected.target_vt(), VT_I2);
+        assert_eq!(
+            rejected.to_string(),
+            "widening conversion cannot read VT_I8 as VT_I2"
+        );
+
+        let rejected = ConversionError {
+            conversion: Conversion::Strict,
+            source_vt: VARENUM(VT_BYREF.0 | VT_ARRAY.0 | VT_BSTR.0),
+            target_vt: VARENUM(VT_ARRAY.0 | VT_R8.0),
+        };
+        assert_eq!(
+            ConversionError::from_error(&rejected.into()),
+            Some(rejected)
+        );
+    }
+
+    #[test]
+    fn other_errors_are_not_rejections() {
+        let error = Conversion::Ole
+            .read::<i32>(&"abc".to_variant())
+            .unwrap_err();
+        assert_eq!(error.code(), DISP_E_TYPEMISMATCH);
+        assert_eq!(ConversionError::from_error(&error), None);
+        assert_eq!(
+            ConversionError::from_error(&Error::from(DISP_E_TYPEMISMATCH)),
+            None
+        );
+        assert!(
+            Conversion::Strict
+                .scope(|| i32::from_variant(&1.to_variant()))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn type_names_are_read_back() {
+        for (vt, _) in VT_NAMES {
+            for flags in [0, VT_ARRAY.0, VT_BYREF.0, VT_BYREF.0 | VT_ARRAY.0] {
+                let vt = VARENUM(vt.0 | flags);
+                assert_eq!(VtName::parse(&VtName(vt).to_string()), Some(vt));
+            }
+        }
+        assert_eq!(VtName::parse("VT 36"), Some(VARENUM(36)));
+        assert_eq!(VtName::parse("VT_I4 | VT_ARRAY"), None);
+        assert_eq!(VtName(VT_I4).to_string(), "VT_I4");
+    }
+}
@@ -0,0 +1,855 @@
+use std::{borrow::Cow, mem::ManuallyDrop};
+
+#[cfg(not(target_pointer_width = "64"))]
+use windows::Win32::System::Variant::{VT_INT, VT_UINT};
+use windows::{
+    Win32::{
+        Foundation::{DECIMAL, DISP_E_OVERFLOW, DISP_E_TYPEMISMATCH, E_POINTER, VARIANT_BOOL},
+        System::{
+            Com::SAFEARRAY,
+            Variant::{
+                VARIANT, VARIANT_0_0, VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF, VT_CY, VT_DATE,
+                VT_DECIMAL, VT_DISPATCH, VT_I1, VT_I2, VT_I4, VT_I8, VT_R4, VT_R8, VT_UI1, VT_UI2,
+                VT_UI4, VT_UI8,
+            },
+        },
+    },
+    core::{self, Result},
+};
+
+use crate::{
+    Array2, BStr, Currency, Decimal, IDispatch, OleDate, Variant, VariantExt, array, bstr,
+    conversion,
+};
+
+/// A type that can be read from a [`VARIANT`].
+///
+/// Values are coerced following OLE Automation rules, so a `VT_BSTR` holding `"42"` can be read
+/// as an `i32`. Every class generated by [`com_shim!`](crate::com_shim) implements this, and it
+/// can be derived for newtypes:
+///
+/// ```rust
+/// use com_shim::{FromVariant, ToVariant};
+///
+/// #[derive(Debug, PartialEq, FromVariant, ToVariant)]
+/// struct FieldId(i32);
+///
+/// let variant = FieldId(42).to_variant();
+/// assert_eq!(i32::from_variant(&variant), Ok(42));
+/// assert_eq!(FieldId::from_variant(&variant), Ok(FieldId(42)));
+///
+/// let values = (FieldId(1), Some("two"), vec![3.5]).to_variant();
+/// let (id, two, three) = <(FieldId, Option<String>, Vec<f64>)>::from_variant(&values).unwrap();
+/// assert_eq!((id, two.as_deref(), three), (FieldId(1), Some("two"), vec![3.5]));
+/// ```
+///
+/// There is no implementation for references such as `&str` or `&IDispatch`. Reading a value
+/// usually converts it into a new, temporary `VARIANT` first, so there is nothing that outlives the
+/// call for a reference to borrow from, and a `VT_BSTR` is UTF-16 rather than a `str` in any case.
+/// Read the owned type instead, such as `String` or `IDispatch`.
+pub trait FromVariant: Sized {
+    /// Convert from a [`VARIANT`] into this type.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the [`VARIANT`] cannot be converted into this type.
+    fn from_variant(variant: &VARIANT) -> Result<Self>;
+
+    /// Read a `Vec` of this type from a `VT_BSTR`, which only bytes can be.
+    ///
+    /// This cannot be called or overridden outside of this crate, as [`sealed::Token`] cannot be
+    /// named there. It exists only so that `Vec<u8>` can read strings without specialization.
+    #[doc(hidden)]
+    fn vec_from_string(variant: &VARIANT, _: sealed::Token) -> Result<Vec<Self>> {
+        let _ = variant;
+        Err(DISP_E_TYPEMISMATCH.into())
+    }
+}
+
+mod sealed {
+    /// Proof that a call to [`FromVariant::vec_from_string`](super::FromVariant) came from within
+    /// this crate.
+    #[derive(Clone, Copy)]
+    pub struct Token;
+}
+
+/// A type that can be stored in a [`VARIANT`].
+///
+/// Every class generated by [`com_shim!`](crate::com_shim) implements this, and it can be derived
+/// for newtypes. See [`FromVariant`].
+///
+/// [`ComVariant`](crate::ComVariant) derives both traits, for newtypes and for enums of unit
+/// variants. Enums are stored as the name of each variant with `#[com(repr = "string")]`, or as an
+/// `i32` with `#[com(repr = "i32")]`:
+///
+/// ```rust
+/// use com_shim::{ComVariant, FromVariant, ToVariant};
+///
+/// #[derive(Debug, PartialEq, ComVariant)]
+/// struct TransactionCode(String);
+///
+/// #[derive(Debug, PartialEq, ComVariant)]
+/// #[com(repr = "string")]
+/// enum Mode {
+///     Display,
+///     #[com(rename = "Change")]
+///     Edit,
+/// }
+///
+/// #[derive(Debug, PartialEq, ComVariant)]
+/// #[com(repr = "i32")]
+/// enum Key {
+///     Enter = 0,
+///     F1,
+///     F2,
+///     ShiftF1 = 13,
+/// }
+///
+/// let code = TransactionCode("SE80".to_string()).to_variant();
+/// assert_eq!(String::from_variant(&code), Ok("SE80".to_string()));
+///
+/// assert_eq!(String::from_variant(&Mode::Edit.to_variant()), Ok("Change".to_string()));
+/// assert_eq!(Mode::from_variant(&"Display".to_variant()), Ok(Mode::Display));
+/// assert!(Mode::from_variant(&"Edit".to_variant()).is_err());
+///
+/// assert_eq!(i32::from_variant(&Key::F2.to_variant()), Ok(2));
+/// assert_eq!(Key::from_variant(&13.to_variant()), Ok(Key::ShiftF1));
+/// assert!(Key::from_variant(&3.to_variant()).is_err());
+/// ```
+pub trait ToVariant {
+    /// Convert this value into an owned [`Variant`].
+    fn to_variant(&self) -> Variant;
+}
+
+/// Implement the conversions for types stored directly in a field of a [`VARIANT`].
+macro_rules! primitive {
+    ($($ty:ty => $vt:ident, $field:ident $({ $($from:item)* })?;)*) => {
+        $(
+            impl FromVariant for $ty {
+                fn from_variant(variant: &VARIANT) -> Result<Self> {
+                    let new = conversion::convert(variant, $vt)?;
+                    // SAFETY: the variant has just been converted to this type.
+                    Ok(unsafe { new.Anonymous.Anonymous.Anonymous.$field })
+                }
+
+                $($($from)*)?
+            }
+
+            impl ToVariant for $ty {
+                fn to_variant(&self) -> Variant {
+                    let mut v00 = VARIANT_0_0 {
+                        vt: $vt,
+                        ..Default::default()
+                    };
+                    v00.Anonymous.$field = *self;
+                    Variant::from_v00(v00)
+                }
+            }
+        )*
+    };
+}
+
+primitive! {
+    i16 => VT_I2, iVal;
+    i32 => VT_I4, lVal;
+    i64 => VT_I8, llVal;
+    u8 => VT_UI1, bVal {
+        /// A string is read as its bytes, as `VariantChangeType` does.
+        fn vec_from_string(variant: &VARIANT, _: sealed::Token) -> Result<Vec<Self>> {
+            let bytes = conversion::convert(variant, array::VT_ARRAY_UI1)?;
+            Vec::from_variant(&bytes)
+        }
+    };
+    u16 => VT_UI2, uiVal;
+    u32 => VT_UI4, ulVal;
+    u64 => VT_UI8, ullVal;
+    f32 => VT_R4, fltVal;
+    f64 => VT_R8, dblVal;
+}
+
+/// `()` is written as `VT_NULL`, and any value can be read as `()`.
+impl FromVariant for () {
+    fn from_variant(_variant: &VARIANT) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl ToVariant for () {
+    fn to_variant(&self) -> Variant {
+        Variant::null()
+    }
+}
+
+impl FromVariant for i8 {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        let new = conversion::convert(variant, VT_I1)?;
+        // SAFETY: the variant has just been converted to this type.
+        Ok(unsafe { new.Anonymous.Anonymous.Anonymous.cVal }.cast_signed())
+    }
+}
+
+impl ToVariant for i8 {
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_I1,
+            ..Default::default()
+        };
+        v00.Anonymous.cVal = self.cast_unsigned();
+        Variant::from_v00(v00)
+    }
+}
+
+/// `isize` is stored as a `VT_I8` on 64-bit platforms and as a `VT_INT` elsewhere, so the type
+/// written depends only on the target and never on the value. Values that do not fit in an
+/// `isize` are reported as [`DISP_E_OVERFLOW`].
+impl FromVariant for isize {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        isize::try_from(i64::from_variant(variant)?).map_err(|_| DISP_E_OVERFLOW.into())
+    }
+}
+
+impl ToVariant for isize {
+    #[cfg(target_pointer_width = "64")]
+    fn to_variant(&self) -> Variant {
+        (*self as i64).to_variant()
+    }
+
+    #[cfg(not(target_pointer_width = "64"))]
+    #[allow(clippy::cast_possible_truncation)] // `isize` is at most 32 bits wide here.
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_INT,
+            ..Default::default()
+        };
+        v00.Anonymous.intVal = *self as i32;
+        Variant::from_v00(v00)
+    }
+}
+
+/// `usize` is stored as a `VT_UI8` on 64-bit platforms and as a `VT_UINT` elsewhere, so the type
+/// written depends only on the target and never on the value. Values that do not fit in a
+/// `usize` are reported as [`DISP_E_OVERFLOW`].
+impl FromVariant for usize {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        usize::try_from(u64::from_variant(variant)?).map_err(|_| DISP_E_OVERFLOW.into())
+    }
+}
+
+impl ToVariant for usize {
+    #[cfg(target_pointer_width = "64")]
+    fn to_variant(&self) -> Variant {
+        (*self as u64).to_variant()
+    }
+
+    #[cfg(not(target_pointer_width = "64"))]
+    #[allow(clippy::cast_possible_truncation)] // `usize` is at most 32 bits wide here.
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_UINT,
+            ..Default::default()
+        };
+        v00.Anonymous.uintVal = *self as u32;
+        Variant::from_v00(v00)
+    }
+}
+
+impl FromVariant for bool {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        let new = conversion::convert(variant, VT_BOOL)?;
+        // SAFETY: the variant has just been converted to this type.
+        Ok(unsafe { new.Anonymous.Anonymous.Anonymous.boolVal }.as_bool())
+    }
+}
+
+impl ToVariant for bool {
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_BOOL,
+            ..Default::default()
+        };
+        v00.Anonymous.boolVal = VARIANT_BOOL::from(*self);
+        Variant::from_v00(v00)
+    }
+}
+
+/// Strings are decoded directly from the variant, without copying them first, replacing invalid
+/// UTF-16 with `U+FFFD`. Use [`BStr`] to keep every byte.
+impl FromVariant for String {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        if let Ok(text) = BStr::borrow(variant) {
+            return Ok(text.to_string_lossy());
+        }
+        Ok(BStr::from_variant(variant)?.to_string_lossy())
+    }
+}
+
+impl ToVariant for str {
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_BSTR,
+            ..Default::default()
+        };
+        v00.Anonymous.bstrVal = ManuallyDrop::new(bstr::encode(self));
+        Variant::from_v00(v00)
+    }
+}
+
+impl ToVariant for String {
+    fn to_variant(&self) -> Variant {
+        self.as_str().to_variant()
+    }
+}
+
+impl FromVariant for OleDate {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        let new = conversion::convert(variant, VT_DATE)?;
+        // SAFETY: the variant has just been converted to this type.
+        Ok(OleDate::try_from(unsafe {
+            new.Anonymous.Anonymous.Anonymous.date
+        })?)
+    }
+}
+
+impl ToVariant for OleDate {
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_DATE,
+            ..Default::default()
+        };
+        v00.Anonymous.date = f64::from(*self);
+        Variant::from_v00(v00)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromVariant for chrono::NaiveDateTime {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        Ok(OleDate::from_variant(variant)?.into())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ToVariant for chrono::NaiveDateTime {
+    fn to_variant(&self) -> Variant {
+        // Out of range dates saturate, as a `VARIANT` cannot represent them
+        let date = OleDate::try_from(*self).unwrap_or(
+            if *self < chrono::NaiveDateTime::from(OleDate::MIN) {
+                OleDate::MIN
+            } else {
+                OleDate::MAX
+            },
+        );
+        date.to_variant()
+    }
+}
+
+#[cfg(feature = "time")]
+impl FromVariant for time::PrimitiveDateTime {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        Ok(OleDate::from_variant(variant)?.into())
+    }
+}
+
+#[cfg(feature = "time")]
+impl ToVariant for time::PrimitiveDateTime {
+    fn to_variant(&self) -> Variant {
+        // Out of range dates saturate, as a `VARIANT` cannot represent them
+        let date = OleDate::try_from(*self).unwrap_or(
+            if *self < time::PrimitiveDateTime::from(OleDate::MIN) {
+                OleDate::MIN
+            } else {
+                OleDate::MAX
+            },
+        );
+        date.to_variant()
+    }
+}
+
+impl FromVariant for Currency {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        let new = conversion::convert(variant, VT_CY)?;
+        // SAFETY: the variant has just been converted to this type.
+        Ok(unsafe { new.Anonymous.Anonymous.Anonymous.cyVal }.into())
+    }
+}
+
+impl ToVariant for Currency {
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_CY,
+            ..Default::default()
+        };
+        v00.Anonymous.cyVal = (*self).into();
+        Variant::from_v00(v00)
+    }
+}
+
+impl FromVariant for Decimal {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        let new = conversion::convert(variant, VT_DECIMAL)?;
+        // SAFETY: the variant has just been converted to this type.
+        Ok(Decimal::try_from(unsafe { new.Anonymous.decVal })?)
+    }
+}
+
+impl ToVariant for Decimal {
+    fn to_variant(&self) -> Variant {
+        let mut variant = VARIANT::default();
+        let mut decimal = DECIMAL::from(*self);
+        // A `DECIMAL` overlays the whole `VARIANT`, with `vt` in its reserved field
+        decimal.wReserved = VT_DECIMAL.0;
+        variant.Anonymous.decVal = decimal;
+        // SAFETY: a `DECIMAL` does not reference any other value.
+        unsafe { Variant::from_raw(variant) }
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl FromVariant for rust_decimal::Decimal {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        Ok(Decimal::from_variant(variant)?.into())
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl ToVariant for rust_decimal::Decimal {
+    fn to_variant(&self) -> Variant {
+        Decimal::from(*self).to_variant()
+    }
+}
+
+/// Values are read as their nearest JSON equivalent: `Nothing` as `null`, numbers and booleans as
+/// themselves, dates, currency and decimals as text, and one and two-dimensional arrays as arrays
+/// and arrays of rows. This loses the type of the value, so use [`serde_json::to_value`] on the
+/// [`Variant`] to keep it.
+///
+/// ```rust
+/// use com_shim::{FromVariant, ToVariant};
+/// use serde_json::{Value, json};
+///
+/// let values = ("Widget", 3, vec![Some(0.5), None]).to_variant();
+/// assert_eq!(Value::from_variant(&values), Ok(json!(["Widget", 3, [0.5, null]])));
+/// ```
+#[cfg(feature = "serde_json")]
+impl FromVariant for serde_json::Value {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        use serde_json::Value;
+        use windows::Win32::System::{
+            Ole::SafeArrayGetDim,
+            Variant::{VT_ERROR, VT_INT, VT_UINT},
+        };
+
+        if variant.is_nothing() {
+            return Ok(Value::Null);
+        }
+        // SAFETY: `vt` is valid for every variant.
+        let vt = unsafe { variant.Anonymous.Anonymous.vt };
+        Ok(match vt {
+            VT_BOOL => Value::Bool(bool::from_variant(variant)?),
+            VT_I1 => Value::from(i8::from_variant(variant)?),
+            VT_I2 => Value::from(i16::from_variant(variant)?),
+            VT_I4 => Value::from(i32::from_variant(variant)?),
+            VT_I8 => Value::from(i64::from_variant(variant)?),
+            VT_UI1 => Value::from(u8::from_variant(variant)?),
+            VT_UI2 => Value::from(u16::from_variant(variant)?),
+            VT_UI4 => Value::from(u32::from_variant(variant)?),
+            VT_UI8 => Value::from(u64::from_variant(variant)?),
+            // SAFETY: `vt` has just been checked.
+            VT_INT => Value::from(unsafe { variant.Anonymous.Anonymous.Anonymous.intVal }),
+            VT_UINT => Value::from(unsafe { variant.Anonymous.Anonymous.Anonymous.uintVal }),
+            VT_R4 => Value::from(f32::from_variant(variant)?),
+            VT_R8 => Value::from(f64::from_variant(variant)?),
+            // SAFETY: `vt` has just been checked.
+            VT_ERROR => Value::from(unsafe { variant.Anonymous.Anonymous.Anonymous.scode }),
+            VT_BSTR => Value::String(String::from_variant(variant)?),
+            VT_DATE => Value::String(OleDate::from_variant(variant)?.to_string()),
+            VT_CY => Value::String(Currency::from_variant(variant)?.to_string()),
+            VT_DECIMAL => Value::String(Decimal::from_variant(variant)?.to_string()),
+            // SAFETY: `safearray` checks that the variant holds an array.
+            _ => match unsafe { SafeArrayGetDim(safearray(variant)?) } {
+                1 => Value::Array(Vec::from_variant(variant)?),
+                2 => {
+                    let array = Array2::<Value>::from_variant(variant)?;
+                    (0..array.rows())
+                        .map(|row| Value::Array(array.row(row).cloned().collect()))
+                        .collect()
+                }
+                _ => return Err(DISP_E_TYPEMISMATCH.into()),
+            },
+        })
+    }
+}
+
+/// Objects must be `VT_DISPATCH` references to an object. Use `Option<IDispatch>` to also accept
+/// `Nothing`.
+impl FromVariant for IDispatch {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        // SAFETY: `vt` is checked before reading the object.
+        unsafe {
+            let v00 = &variant.Anonymous.Anonymous;
+            if v00.vt != VT_DISPATCH {
+                return Err(DISP_E_TYPEMISMATCH.into());
+            }
+            v00.Anonymous.pdispVal.as_ref().cloned().ok_or_else(|| {
+                core::Error::new(
+                    E_POINTER,
+                    core::HSTRING::from("com-shim: Cannot read IDispatch"),
+                )
+            })
+        }
+    }
+}
+
+impl ToVariant for IDispatch {
+    fn to_variant(&self) -> Variant {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_DISPATCH,
+            ..Default::default()
+        };
+        v00.Anonymous.pdispVal = ManuallyDrop::new(Some(self.clone()));
+        Variant::from_v00(v00)
+    }
+}
+
+/// Any value can be read as a copy of itself.
+impl FromVariant for Variant {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        Variant::copy(variant)
+    }
+}
+
+impl ToVariant for Variant {
+    fn to_variant(&self) -> Variant {
+        self.clone()
+    }
+}
+
+impl<T: ToVariant + ?Sized> ToVariant for &T {
+    fn to_variant(&self) -> Variant {
+        (**self).to_variant()
+    }
+}
+
+impl<T: ToVariant + ?Sized> ToVariant for Box<T> {
+    fn to_variant(&self) -> Variant {
+        (**self).to_variant()
+    }
+}
+
+impl<T: ToVariant + ToOwned + ?Sized> ToVariant for Cow<'_, T> {
+    fn to_variant(&self) -> Variant {
+        (**self).to_variant()
+    }
+}
+
+impl<T: FromVariant> FromVariant for Box<T> {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        T::from_variant(variant).map(Box::new)
+    }
+}
+
+/// `None` is written as `VT_NULL`. `VT_NULL`, `VT_EMPTY` and null object references are read as
+/// `None`, and anything else is converted to `T`.
+impl<T: FromVariant> FromVariant for Option<T> {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        if variant.is_nothing() {
+            Ok(None)
+        } else {
+            T::from_variant(variant).map(Some)
+        }
+    }
+}
+
+impl<T: ToVariant> ToVariant for Option<T> {
+    fn to_variant(&self) -> Variant {
+        match self {
+            Some(value) => value.to_variant(),
+            None => Variant::null(),
+        }
+    }
+}
+
+/// Get the `SAFEARRAY` held by a [`VARIANT`], or referred to by it if it is `VT_BYREF`.
+pub(crate) fn safearray(variant: &VARIANT) -> Result<*const SAFEARRAY> {
+    // SAFETY: `vt` is checked before reading the array, and a reference is checked before it is
+    // followed.
+    unsafe {
+        let v00 = &variant.Anonymous.Anonymous;
+        if v00.vt.0 & VT_ARRAY.0 == 0 {
+            return Err(DISP_E_TYPEMISMATCH.into());
+        }
+        if v00.vt.0 & VT_BYREF.0 == 0 {
+            return Ok(v00.Anonymous.parray);
+        }
+        let pparray = v00.Anonymous.pparray;
+        if pparray.is_null() {
+            return Err(DISP_E_TYPEMISMATCH.into());
+        }
+        Ok((*pparray).cast_const())
+    }
+}
+
+/// Wrap a new `SAFEARRAY` of `VT_VARIANT` in a [`Variant`], or the error creating it as a
+/// `VT_ERROR`, since converting a value to a variant cannot fail.
+fn array_variant(psa: Result<*mut SAFEARRAY>) -> Variant {
+    let psa = match psa {
+        Ok(psa) => psa,
+        Err(error) => {
+            tracing::error!("Failed to create SAFEARRAY: {error}");
+            return Variant::from_error(&error);
+        }
+    };
+    let mut v00 = VARIANT_0_0 {
+        vt: array::VT_ARRAY_VARIANT,
+        ..Default::default()
+    };
+    v00.Anonymous.parray = psa;
+    Variant::from_v00(v00)
+}
+
+/// Arrays are read from a one-dimensional `SAFEARRAY` of any element type, discarding its lower
+/// bound, and written as a zero-based `SAFEARRAY` of `VT_VARIANT`. A string is read as its bytes,
+/// as `VariantChangeType` does, so byte strings can be read as a `Vec<u8>`, but not as a `Vec` of
+/// any other type.
+impl<T: FromVariant> FromVariant for Vec<T> {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        // SAFETY: `vt` is valid for every variant.
+        if unsafe { variant.Anonymous.Anonymous.vt }.0 & !VT_BYREF.0 == VT_BSTR.0 {
+            return T::vec_from_string(variant, sealed::Token);
+        }
+        // SAFETY: a `VT_ARRAY` variant holds a valid `SAFEARRAY`.
+        let (elements, _) =
+            unsafe { array::read_safearray(safearray(variant)?, 1, T::from_variant) }?;
+        Ok(elements)
+    }
+}
+
+impl<T: ToVariant> ToVariant for [T] {
+    fn to_variant(&self) -> Variant {
+        array_variant(array::create_safearray(
+            &[(0, self.len())],
+            self.iter().map(ToVariant::to_variant),
+        ))
+    }
+}
+
+impl<T: ToVariant> ToVariant for Vec<T> {
+    fn to_variant(&self) -> Variant {
+        self.as_slice().to_variant()
+    }
+}
+
+/// Two-dimensional arrays are read from a two-dimensional `SAFEARRAY` of any element type, and
+/// written as a `SAFEARRAY` of `VT_VARIANT`, keeping their lower bounds.
+impl<T: FromVariant> FromVariant for Array2<T> {
+    fn from_variant(variant: &VARIANT) -> Result<Self> {
+        // SAFETY: a `VT_ARRAY` variant holds a valid `SAFEARRAY`.
+        let (elements, bounds) =
+            unsafe { array::read_safearray(safearray(variant)?, 2, T::from_variant) }?;
+        let [(row_lower, rows), (column_lower, columns)] = bounds[..] else {
+            unreachable!("two dimensions were requested")
+        };
+        Ok(Array2::from_column_major(rows, columns, elements)
+            .expect("a SAFEARRAY has an element for every index")
+            .with_lower_bounds(row_lower, column_lower))
+    }
+}
+
+impl<T: ToVariant> ToVariant for Array2<T> {
+    fn to_variant(&self) -> Variant {
+        let (row_lower, column_lower) = self.lower_bounds();
+        let bounds = [(row_lower, self.rows()), (column_lower, self.columns())];
+        array_variant(array::create_safearray(
+            &bounds,
+            self.as_column_major().iter().map(ToVariant::to_variant),
+        ))
+    }
+}
+
+/// Tuples are read from a one-dimensional `SAFEARRAY` with exactly as many elements, converting
+/// each element to its own type, and written as a zero-based `SAFEARRAY` of `VT_VARIANT`.
+macro_rules! tuple {
+    ($len:literal => $($name:ident $index:tt),+) => {
+        impl<$($name: FromVariant),+> FromVariant for ($($name,)+) {
+            fn from_variant(variant: &VARIANT) -> Result<Self> {
+                // SAFETY: a `VT_ARRAY` variant holds a valid `SAFEARRAY`.
+                let (elements, _) =
+                    unsafe { array::read_safearray(safearray(variant)?, 1, Variant::copy) }?;
+                if elements.len() != $len {
+                    return Err(DISP_E_TYPEMISMATCH.into());
+                }
+                Ok(($($name::from_variant(&elements[$index])?,)+))
+            }
+        }
+
+        impl<$($name: ToVariant),+> ToVariant for ($($name,)+) {
+            fn to_variant(&self) -> Variant {
+                array_variant(array::create_safearray(
+                    &[(0, $len)],
+                    [$(self.$index.to_variant()),+],
+                ))
+            }
+        }
+    };
+}
+
+tuple!(1 => A 0);
+tuple!(2 => A 0, B 1);
+tuple!(3 => A 0, B 1, C 2);
+tuple!(4 => A 0, B 1, C 2, D 3);
+tuple!(5 => A 0, B 1, C 2, D 3, E 4);
+tuple!(6 => A 0, B 1, C 2, D 3, E 4, F 5);
+tuple!(7 => A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple!(8 => A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+#[cfg(test)]
+mod tests {
+    use std::mem::ManuallyDrop;
+
+    use windows::Win32::{
+        Foundation::{DISP_E_OVERFLOW, DISP_E_TYPEMISMATCH, E_POINTER},
+        System::Variant::{
+            VARENUM, VARIANT, VARIANT_0, VARIANT_0_0, VT_BYREF, VT_DISPATCH, VT_I8, VT_INT, VT_UI8,
+            VT_UINT,
+        },
+    };
+
+    use crate::{Array2, BStr, FromVariant, IDispatch, ToVariant};
+
+    /// A `VT_BYREF` variant referring to the array held by `array`, as event and callback arguments
+    /// often are.
+    fn by_reference(
+        array: &VARIANT,
+        parray: &mut *mut windows::Win32::System::Com::SAFEARRAY,
+    ) -> VARIANT {
+        // SAFETY: `array` holds an array.
+        let vt = unsafe { array.Anonymous.Anonymous.vt };
+        let mut v00 = VARIANT_0_0 {
+            vt: VARENUM(vt.0 | VT_BYREF.0),
+            ..Default::default()
+        };
+        v00.Anonymous.pparray = parray;
+        VARIANT {
+            Anonymous: VARIANT_0 {
+                Anonymous: ManuallyDrop::new(v00),
+            },
+        }
+    }
+
+    #[test]
+    fn reads_arrays_by_reference() {
+        let array = vec![1, 2, 3].to_variant();
+        // SAFETY: `array` holds an array.
+        let mut parray = unsafe { array.Anonymous.Anonymous.Anonymous.parray };
+        let variant = by_reference(&array, &mut parray);
+        assert_eq!(Vec::<i32>::from_variant(&variant), Ok(vec![1, 2, 3]));
+        assert_eq!(<(i32, i32, i32)>::from_variant(&variant), Ok((1, 2, 3)));
+
+        let grid = Array2::from_row_major(1, 2, vec![4, 5])
+            .unwrap()
+            .to_variant();
+        // SAFETY: `grid` holds an array.
+        let mut parray = unsafe { grid.Anonymous.Anonymous.Anonymous.parray };
+        let variant = by_reference(&grid, &mut parray);
+        let read = Array2::<i32>::from_variant(&variant).unwrap();
+        assert_eq!(read.as_column_major(), [4, 5]);
+    }
+
+    #[test]
+    fn rejects_null_array_references() {
+        let array = vec![1].to_variant();
+        let mut parray = std::ptr::null_mut();
+        let mut variant = by_reference(&array, &mut parray);
+        assert!(Vec::<i32>::from_variant(&variant).is_err());
+
+        // SAFETY: only the reference is changed.
+        unsafe { (*variant.Anonymous.Anonymous).Anonymous.pparray = std::ptr::null_mut() };
+        assert!(Vec::<i32>::from_variant(&variant).is_err());
+    }
+
+    #[test]
+    fn reads_only_bytes_from_strings() {
+        let bytes = BStr::from_bytes(b"abc").to_variant();
+        assert_eq!(Vec::<u8>::from_variant(&bytes), Ok(b"abc".to_vec()));
+
+        let text = "42".to_variant();
+        for error in [
+            Vec::<u16>::from_variant(&text).unwrap_err(),
+            Vec::<i32>::from_variant(&text).unwrap_err(),
+            Vec::<String>::from_variant(&text).unwrap_err(),
+        ] {
+            assert_eq!(error.code(), DISP_E_TYPEMISMATCH);
+        }
+    }
+
+    #[test]
+    fn null_objects_are_read_as_none_or_an_error() {
+        let variant = VARIANT {
+            Anonymous: VARIANT_0 {
+                Anonymous: ManuallyDrop::new(VARIANT_0_0 {
+                    vt: VT_DISPATCH,
+                    ..Default::default()
+                }),
+            },
+        };
+        assert_eq!(Option::<IDispatch>::from_variant(&variant), Ok(None));
+        assert_eq!(
+            IDispatch::from_variant(&variant).unwrap_err().code(),
+            E_POINTER
+        );
+    }
+
+    #[test]
+    fn numbers_round_trip() {
+        for value in [0.0f32, -1.5, f32::MAX, f32::MIN_POSITIVE] {
+            assert_eq!(f32::from_variant(&value.to_variant()), Ok(value));
+        }
+        for value in [0.0f64, -1.5, f64::MAX, f64::MIN_POSITIVE] {
+            assert_eq!(f64::from_variant(&value.to_variant()), Ok(value));
+        }
+        for value in [0i8, -1, i8::MIN, i8::MAX] {
+            assert_eq!(i8::from_variant(&value.to_variant()), Ok(value));
+        }
+        for value in [0isize, -1, isize::MIN, isize::MAX] {
+            assert_eq!(isize::from_variant(&value.to_variant()), Ok(value));
+        }
+        for value in [0usize, 1, usize::MAX] {
+            assert_eq!(usize::from_variant(&value.to_variant()), Ok(value));
+        }
+    }
+
+    #[test]
+    fn pointer_sized_integers_have_one_type() {
+        let (signed, unsigned) = if cfg!(target_pointer_width = "64") {
+            (VT_I8, VT_UI8)
+        } else {
+            (VT_INT, VT_UINT)
+        };
+
+        for value in [0isize, isize::MAX] {
+            assert_eq!(value.to_variant().vt(), signed);
+        }
+        for value in [0usize, usize::MAX] {
+            assert_eq!(value.to_variant().vt(), unsigned);
+        }
+    }
+
+    #[test]
+    fn out_of_range_numbers_overflow() {
+        for error in [
+            i8::from_variant(&128.to_variant()).unwrap_err(),
+            i8::from_variant(&(-129).to_variant()).unwrap_err(),
+            usize::from_variant(&(-1).to_variant()).unwrap_err(),
+            f32::from_variant(&f64::MAX.to_variant()).unwrap_err(),
+        ] {
+            assert_eq!(error.code(), DISP_E_OVERFLOW);
+        }
+        #[cfg(not(target_pointer_width = "64"))]
+        assert_eq!(
+            isize::from_variant(&i64::MAX.to_variant())
+                .unwrap_err()
+                .code(),
+            DISP_E_OVERFLOW
+        );
+    }
+}
@@ -0,0 +1,440 @@
+use std::fmt;
+
+const MILLIS_PER_SECOND: i64 = 1_000;
+const MILLIS_PER_MINUTE: i64 = 60 * MILLIS_PER_SECOND;
+const MILLIS_PER_HOUR: i64 = 60 * MILLIS_PER_MINUTE;
+const MILLIS_PER_DAY: i64 = 24 * MILLIS_PER_HOUR;
+
+/// Days from 1970-01-01 to 1899-12-30, the OLE Automation epoch.
+const EPOCH_DAYS_FROM_UNIX: i64 = -25_569;
+
+/// A date and time as stored in a `VT_DATE` [`VARIANT`](crate::VARIANT).
+///
+/// OLE Automation dates are stored as a number of days since 1899-12-30, with the fractional part
+/// giving the time of day. Dates before the epoch are negative, but the fractional part still
+/// counts *forwards* from midnight, so `-1.25` is 1899-12-29 06:00 rather than 1899-12-28 18:00.
+///
+/// This type stores the date as a linear number of milliseconds from the epoch, so it can be
+/// compared and hashed, and converts to and from the floating point representation following the
+/// same rules as .NET's `DateTime.FromOADate` and `DateTime.ToOADate`:
+///
+/// * Values are rounded to the nearest millisecond.
+/// * Only dates from 0100-01-01 to 9999-12-31 can be represented.
+///
+/// ```rust
+/// use com_shim::OleDate;
+///
+/// for (value, expected) in [
+///     (0.0, "1899-12-30 00:00:00"),
+///     (1.5, "1899-12-31 12:00:00"),
+///     (-1.25, "1899-12-29 06:00:00"),
+///     (-0.5, "1899-12-30 12:00:00"),
+///     (2.0, "1900-01-01 00:00:00"),
+///     (45_000.75, "2023-03-15 18:00:00"),
+/// ] {
+///     let date = OleDate::try_from(value).unwrap();
+///     assert_eq!(date.to_string(), expected);
+/// }
+///
+/// // Negative values round-trip back to their original representation
+/// assert_eq!(f64::from(OleDate::try_from(-1.25).unwrap()), -1.25);
+/// // -0.5 is the same instant as 0.5, and normalises to it
+/// assert_eq!(f64::from(OleDate::try_from(-0.5).unwrap()), 0.5);
+///
+/// assert_eq!(OleDate::MIN.to_string(), "0100-01-01 00:00:00");
+/// assert_eq!(OleDate::MAX.to_string(), "9999-12-31 23:59:59.999");
+/// assert_eq!(OleDate::from_ymd(2023, 3, 15), OleDate::try_from(45_000.0).ok());
+/// assert!(OleDate::try_from(f64::NAN).is_err());
+/// assert!(OleDate::try_from(3_000_000.0).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OleDate {
+    millis: i64,
+}
+
+/// A date was outside of the range that can be represented by an [`OleDate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OleDateOutOfRange;
+
+impl fmt::Display for OleDateOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "date is out of range for an OLE Automation date")
+    }
+}
+
+impl std::error::Error for OleDateOutOfRange {}
+
+impl From<OleDateOutOfRange> for windows::core::Error {
+    fn from(_: OleDateOutOfRange) -> Self {
+        windows::Win32::Foundation::DISP_E_OVERFLOW.into()
+    }
+}
+
+impl OleDate {
+    /// The OLE Automation epoch, 1899-12-30 00:00:00.
+    pub const EPOCH: OleDate = OleDate { millis: 0 };
+
+    /// The earliest representable date, 0100-01-01 00:00:00.
+    pub const MIN: OleDate = OleDate {
+        millis: -657_434 * MILLIS_PER_DAY,
+    };
+
+    /// The latest representable date, 9999-12-31 23:59:59.999.
+    pub const MAX: OleDate = OleDate {
+        millis: 2_958_466 * MILLIS_PER_DAY - 1,
+    };
+
+    /// Create a date from a number of milliseconds since the epoch, 1899-12-30 00:00:00.
+    ///
+    /// Returns [`None`] if the date is outside of [`OleDate::MIN`] to [`OleDate::MAX`].
+    #[must_use]
+    pub fn from_millis_since_epoch(millis: i64) -> Option<Self> {
+        (Self::MIN.millis..=Self::MAX.millis)
+            .contains(&millis)
+            .then_some(Self { millis })
+    }
+
+    /// The number of milliseconds since the epoch, 1899-12-30 00:00:00.
+    #[must_use]
+    pub fn millis_since_epoch(self) -> i64 {
+        self.millis
+    }
+
+    /// Create a date from its calendar date and time of day.
+    ///
+    /// Returns [`None`] if any component is out of range, or the date is outside of
+    /// [`OleDate::MIN`] to [`OleDate::MAX`].
+    #[must_use]
+    pub fn from_ymd_hms_milli(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+        millisecond: u32,
+    ) -> Option<Self> {
+        if !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+            || millisecond > 999
+        {
+            return None;
+        }
+        let days = days_from_civil(year, month, day) - EPOCH_DAYS_FROM_UNIX;
+        let time = i64::from(hour) * MILLIS_PER_HOUR
+            + i64::from(minute) * MILLIS_PER_MINUTE
+            + i64::from(second) * MILLIS_PER_SECOND
+            + i64::from(millisecond);
+        Self::from_millis_since_epoch(days * MILLIS_PER_DAY + time)
+    }
+
+    /// Create a date at midnight on the given calendar date.
+    ///
+    /// Returns [`None`] if any component is out of range, or the date is outside of
+    /// [`OleDate::MIN`] to [`OleDate::MAX`].
+    #[must_use]
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Option<Self> {
+        Self::from_ymd_hms_milli(year, month, day, 0, 0, 0, 0)
+    }
+
+    /// The calendar date, as a year, month and day.
+    #[must_use]
+    pub fn ymd(self) -> (i32, u32, u32) {
+        civil_from_days(self.millis.div_euclid(MILLIS_PER_DAY) + EPOCH_DAYS_FROM_UNIX)
+    }
+
+    /// The time of day, as an hour, minute, second and millisecond.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // all within a day
+    pub fn hms_milli(self) -> (u32, u32, u32, u32) {
+        let time = self.millis.rem_euclid(MILLIS_PER_DAY);
+        (
+            (time / MILLIS_PER_HOUR) as u32,
+            (time % MILLIS_PER_HOUR / MILLIS_PER_MINUTE) as u32,
+            (time % MILLIS_PER_MINUTE / MILLIS_PER_SECOND) as u32,
+            (time % MILLIS_PER_SECOND) as u32,
+        )
+    }
+
+    /// Parse a date in the format written by its [`Display`](fmt::Display) implementation.
+    #[cfg(feature = "serde")]
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let (date, time) = text.split_once(' ')?;
+        let (time, millisecond) = match time.split_once('.') {
+            Some((time, millisecond)) if millisecond.len() == 3 => {
+                (time, millisecond.parse().ok()?)
+            }
+            Some(_) => return None,
+            None => (time, 0),
+        };
+        let mut date = date.splitn(3, '-');
+        let mut time = time.splitn(3, ':');
+        let next = |parts: &mut std::str::SplitN<'_, char>| parts.next()?.parse::<u32>().ok();
+        let (year, month, day) = (next(&mut date)?, next(&mut date)?, next(&mut date)?);
+        let (hour, minute, second) = (next(&mut time)?, next(&mut time)?, next(&mut time)?);
+        Self::from_ymd_hms_milli(
+            i32::try_from(year).ok()?,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millisecond,
+        )
+    }
+}
+
+impl TryFrom<f64> for OleDate {
+    type Error = OleDateOutOfRange;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)] // range checked before casting
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if !(value > -657_435.0 && value < 2_958_466.0) {
+            return Err(OleDateOutOfRange);
+        }
+        let mut millis =
+            (value * MILLIS_PER_DAY as f64 + if value >= 0.0 { 0.5 } else { -0.5 }) as i64;
+        if millis < 0 {
+            // The fractional part of negative dates counts forwards from midnight
+            millis -= (millis % MILLIS_PER_DAY) * 2;
+        }
+        Self::from_millis_since_epoch(millis).ok_or(OleDateOutOfRange)
+    }
+}
+
+impl From<OleDate> for f64 {
+    #[allow(clippy::cast_precision_loss)] // dates are well within f64 precision
+    fn from(value: OleDate) -> Self {
+        let mut millis = value.millis;
+        if millis < 0 {
+            let frac = millis % MILLIS_PER_DAY;
+            if frac != 0 {
+                millis -= (MILLIS_PER_DAY + frac) * 2;
+            }
+        }
+        millis as f64 / MILLIS_PER_DAY as f64
+    }
+}
+
+impl fmt::Display for OleDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        let (hour, minute, second, millisecond) = self.hms_milli();
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02}"
+        )?;
+        if millisecond != 0 {
+            write!(f, ".{millisecond:03}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<OleDate> for chrono::NaiveDateTime {
+    fn from(value: OleDate) -> Self {
+        chrono_epoch() + chrono::TimeDelta::milliseconds(value.millis)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::NaiveDateTime> for OleDate {
+    type Error = OleDateOutOfRange;
+
+    /// Sub-millisecond precision is truncated.
+    fn try_from(value: chrono::NaiveDateTime) -> Result<Self, Self::Error> {
+        Self::from_millis_since_epoch((value - chrono_epoch()).num_milliseconds())
+            .ok_or(OleDateOutOfRange)
+    }
+}
+
+#[cfg(feature = "chrono")]
+fn chrono_epoch() -> chrono::NaiveDateTime {
+    chrono::NaiveDate::from_ymd_opt(1899, 12, 30)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .expect("the OLE Automation epoch is a valid date")
+}
+
+#[cfg(feature = "time")]
+impl From<OleDate> for time::PrimitiveDateTime {
+    fn from(value: OleDate) -> Self {
+        time_epoch() + time::Duration::milliseconds(value.millis)
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<time::PrimitiveDateTime> for OleDate {
+    type Error = OleDateOutOfRange;
+
+    /// Sub-millisecond precision is truncated.
+    fn try_from(value: time::PrimitiveDateTime) -> Result<Self, Self::Error> {
+        i64::try_from((value - time_epoch()).whole_milliseconds())
+            .ok()
+            .and_then(Self::from_millis_since_epoch)
+            .ok_or(OleDateOutOfRange)
+    }
+}
+
+#[cfg(feature = "time")]
+fn time_epoch() -> time::PrimitiveDateTime {
+    time::Date::from_calendar_date(1899, time::Month::December, 30)
+        .expect("the OLE Automation epoch is a valid date")
+        .midnight()
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The proleptic Gregorian date of a number of days since 1970-01-01.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // bounded by the date range
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year as i32, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Foundation::DISP_E_OVERFLOW;
+
+    use super::{MILLIS_PER_DAY, OleDate, OleDateOutOfRange};
+
+    #[allow(clippy::cast_precision_loss)] // small enough to be exact
+    fn days(millis: f64) -> f64 {
+        millis / MILLIS_PER_DAY as f64
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // quarter days are exact
+    fn negative_fractions_count_forwards_from_midnight() {
+        let date = OleDate::try_from(-1.25).unwrap();
+        assert_eq!(date.ymd(), (1899, 12, 29));
+        assert_eq!(date.hms_milli(), (6, 0, 0, 0));
+        assert_eq!(
+            date.millis_since_epoch(),
+            -MILLIS_PER_DAY + MILLIS_PER_DAY / 4
+        );
+        assert_eq!(f64::from(date), -1.25);
+
+        let date = OleDate::from_ymd_hms_milli(1899, 12, 29, 6, 0, 0, 0).unwrap();
+        assert_eq!(f64::from(date), -1.25);
+    }
+
+    #[test]
+    fn day_zero_boundary() {
+        assert_eq!(OleDate::try_from(0.0), Ok(OleDate::EPOCH));
+        assert_eq!(OleDate::try_from(-0.0), Ok(OleDate::EPOCH));
+
+        // The last millisecond before the epoch is on the previous day, late in the evening
+        let before = OleDate::from_millis_since_epoch(-1).unwrap();
+        assert_eq!(before.ymd(), (1899, 12, 29));
+        assert_eq!(before.hms_milli(), (23, 59, 59, 999));
+        assert_eq!(OleDate::try_from(f64::from(before)), Ok(before));
+
+        // Days -1 and 1 are either side of the epoch, and the whole of day 0 is after it
+        assert_eq!(OleDate::try_from(-1.0).unwrap().ymd(), (1899, 12, 29));
+        assert_eq!(OleDate::try_from(0.999).unwrap().ymd(), (1899, 12, 30));
+        assert_eq!(OleDate::try_from(1.0).unwrap().ymd(), (1899, 12, 31));
+    }
+
+    #[test]
+    fn rounds_to_the_next_second() {
+        let date = OleDate::try_from(days(59_999.6)).unwrap();
+        assert_eq!(date.hms_milli(), (0, 1, 0, 0));
+        assert_eq!(date.to_string(), "1899-12-30 00:01:00");
+
+        let date = OleDate::try_from(1.0 + days(999.5)).unwrap();
+        assert_eq!(date.to_string(), "1899-12-31 00:00:01");
+
+        let date = OleDate::try_from(days(999.4)).unwrap();
+        assert_eq!(date.to_string(), "1899-12-30 00:00:00.999");
+    }
+
+    #[test]
+    fn out_of_range_dates_overflow() {
+        for value in [-657_435.0, 2_958_466.0, f64::INFINITY, f64::NAN] {
+            assert_eq!(OleDate::try_from(value), Err(OleDateOutOfRange));
+        }
+        assert_eq!(
+            windows::core::Error::from(OleDateOutOfRange).code(),
+            DISP_E_OVERFLOW
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn out_of_range_chrono_dates_overflow() {
+        use chrono::NaiveDateTime;
+
+        use crate::{FromVariant, ToVariant};
+
+        let last = NaiveDateTime::from(OleDate::MAX);
+        assert_eq!(OleDate::try_from(last), Ok(OleDate::MAX));
+        for value in [
+            last + chrono::TimeDelta::milliseconds(1),
+            NaiveDateTime::from(OleDate::MIN) - chrono::TimeDelta::milliseconds(1),
+        ] {
+            let error = windows::core::Error::from(OleDate::try_from(value).unwrap_err());
+            assert_eq!(error.code(), DISP_E_OVERFLOW);
+        }
+        let error = NaiveDateTime::from_variant(&3_000_000.0.to_variant()).unwrap_err();
+        assert_eq!(error.code(), DISP_E_OVERFLOW);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn out_of_range_time_dates_overflow() {
+        use time::PrimitiveDateTime;
+
+        use crate::{FromVariant, ToVariant};
+
+        // `time` stops at the end of 9999, so only sub-millisecond precision lies beyond the maximum
+        assert_eq!(OleDate::try_from(PrimitiveDateTime::MAX), Ok(OleDate::MAX));
+        for value in [
+            PrimitiveDateTime::MIN,
+            PrimitiveDateTime::from(OleDate::MIN) - time::Duration::milliseconds(1),
+        ] {
+            let error = windows::core::Error::from(OleDate::try_from(value).unwrap_err());
+            assert_eq!(error.code(), DISP_E_OVERFLOW);
+        }
+        let error = PrimitiveDateTime::from_variant(&3_000_000.0.to_variant()).unwrap_err();
+        assert_eq!(error.code(), DISP_E_OVERFLOW);
+    }
+}
@@ -0,0 +1,653 @@
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use windows::{
+    Win32::{
+        Foundation::{E_ABORT, RPC_E_TIMEOUT},
+        System::Com::{DISPATCH_FLAGS, DISPPARAMS},
+    },
+    core::{Error, Result},
+};
+
+use self::imp::Moved;
+use crate::{
+    ComThread, Conversion, DryRun, FromVariant, HasIDispatch, IDispatch, IDispatchExt, ToVariant,
+    Variant, utils,
+};
+
+/// A call that is waited for, which can be failed before it returns.
+pub(crate) trait Fail: Send + Sync {
+    /// Complete the call with an error, unless it has already completed.
+    fn fail(&self, error: Error);
+}
+
+/// A call did not return before its deadline.
+///
+/// Calls through a [`Remote`](crate::Remote) handle from
+/// [`Remote::with_timeout`](crate::Remote::with_timeout), or on an object wrapped in
+/// [`WithDeadline`], return this as an `RPC_E_TIMEOUT` [`Error`] describing the member and timeout,
+/// from which it is recovered with [`TimeoutError::from_error`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeoutError {
+    member: Cow<'static, str>,
+    timeout: Duration,
+}
+
+impl TimeoutError {
+    pub(crate) fn new(member: impl Into<Cow<'static, str>>, timeout: Duration) -> Self {
+        Self {
+            member: member.into(),
+            timeout,
+        }
+    }
+
+    /// The name of the member that was called, or of the type of the object for a function run
+    /// with [`Remote::call`](crate::Remote::call).
+    #[must_use]
+    pub fn member(&self) -> &str {
+        &self.member
+    }
+
+    /// How long the call was waited for.
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Recover the timeout an [`Error`] was returned for.
+    ///
+    /// Returns [`None`] if the error was not returned for a call whose deadline passed, or has
+    /// lost its message, such as by being converted to an `HRESULT` and back.
+    #[must_use]
+    pub fn from_error(error: &Error) -> Option<Self> {
+        if error.code() != RPC_E_TIMEOUT {
+            return None;
+        }
+        let message = error.message().to_string();
+        let (member, timeout) = message
+            .strip_prefix('`')?
+            .rsplit_once("` did not return within ")?;
+        Some(Self::new(member.to_string(), parse_duration(timeout)?))
+    }
+}
+
+impl From<TimeoutError> for Error {
+    fn from(error: TimeoutError) -> Self {
+        Error::new(RPC_E_TIMEOUT, error.to_string().into())
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` did not return within {:?}",
+            self.member, self.timeout
+        )
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Read a duration as it is written by its `Debug` implementation, such as `1.5s` or `50ms`.
+fn parse_duration(text: &str) -> Option<Duration> {
+    const UNITS: [(&str, u128); 4] = [
+        ("ns", 1),
+        ("µs", 1_000),
+        ("ms", 1_000_000),
+        ("s", 1_000_000_000),
+    ];
+    let (number, unit) = UNITS
+        .iter()
+        .find_map(|&(suffix, unit)| Some((text.strip_suffix(suffix)?, unit)))?;
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    let digits = |text: &str| text.bytes().all(|byte| byte.is_ascii_digit());
+    if whole.is_empty() || !digits(whole) || !digits(fraction) {
+        return None;
+    }
+    let mut nanos = whole.parse::<u128>().ok()?.checked_mul(unit)?;
+    let mut place = unit;
+    for digit in fraction.bytes() {
+        place /= 10;
+        nanos += u128::from(digit - b'0') * place;
+    }
+    let seconds = u64::try_from(nanos / 1_000_000_000).ok()?;
+    #[allow(clippy::cast_possible_truncation)] // less than a billion
+    Some(Duration::new(seconds, (nanos % 1_000_000_000) as u32))
+}
+
+/// Stops callers waiting for calls made through a [`Remote`](crate::Remote) handle, such as when
+/// a service is shutting down.
+///
+/// Once cancelled, every call made through a handle from
+/// [`Remote::with_cancellation`](crate::Remote::with_cancellation) fails with `E_ABORT`, and
+/// calls that have not started are not made. A call that is already running is not interrupted.
+///
+/// Calls through a handle from [`Remote::with_timeout`](crate::Remote::with_timeout) similarly
+/// stop being waited for once their deadline passes, failing with `RPC_E_TIMEOUT`. A call that is
+/// still running, such as one blocked by a modal dialog, keeps the thread busy, so later calls
+/// also time out until it returns.
+///
+/// ```rust
+/// use std::{thread, time::Duration};
+///
+/// use com_shim::{CancellationToken, ComObject, ComThread, Result, com_object, com_shim};
+/// use windows::Win32::Foundation::{E_ABORT, RPC_E_TIMEOUT};
+///
+/// /// An object that takes its time.
+/// struct Sleeper;
+///
+/// com_object! {
+///     impl Sleeper {
+///         #[com(method)]
+///         fn sleep(&self, milliseconds: u32) {
+///             thread::sleep(Duration::from_millis(milliseconds.into()));
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     #[com(remote)]
+///     struct SleeperClass {
+///         fn Sleep(u32),
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let thread = ComThread::spawn()?;
+/// let sleeper = thread.create(|| Ok(SleeperClass::from(Sleeper.into_idispatch())))?;
+///
+/// let hasty = sleeper.with_timeout(Duration::from_millis(50));
+/// hasty.sleep(0)?;
+/// assert_eq!(hasty.sleep(300).unwrap_err().code(), RPC_E_TIMEOUT);
+///
+/// // Waiting callers can be cancelled, and calls are not made once they are
+/// let token = CancellationToken::new();
+/// let cancellable = sleeper.with_cancellation(token.clone());
+/// thread::scope(|scope| {
+///     scope.spawn(|| {
+///         thread::sleep(Duration::from_millis(50));
+///         token.cancel();
+///     });
+///     assert_eq!(cancellable.sleep(300).unwrap_err().code(), E_ABORT);
+/// });
+/// assert_eq!(cancellable.sleep(0).unwrap_err().code(), E_ABORT);
+/// sleeper.sleep(0)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<Mutex<Cancellation>>);
+
+#[derive(Default)]
+struct Cancellation {
+    cancelled: bool,
+    waiting: Vec<Weak<dyn Fail>>,
+}
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every call waited for with this token, now and later.
+    pub fn cancel(&self) {
+        let waiting = {
+            let mut cancellation = lock(&self.0);
+            cancellation.cancelled = true;
+            std::mem::take(&mut cancellation.waiting)
+        };
+        for call in waiting.iter().filter_map(Weak::upgrade) {
+            call.fail(E_ABORT.into());
+        }
+    }
+
+    /// Whether this token has been cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        lock(&self.0).cancelled
+    }
+
+    /// Fail a call when this token is cancelled, or now if it already is.
+    pub(crate) fn register(&self, call: Weak<dyn Fail>) {
+        let mut cancellation = lock(&self.0);
+        if cancellation.cancelled {
+            drop(cancellation);
+            if let Some(call) = call.upgrade() {
+                call.fail(E_ABORT.into());
+            }
+            return;
+        }
+        cancellation.waiting.retain(|call| call.strong_count() > 0);
+        cancellation.waiting.push(call);
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish_non_exhaustive()
+    }
+}
+
+thread_local! {
+    /// The timeout chosen for the object the current call is made on.
+    static OBJECT: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// An object whose calls are stopped being waited for once they take longer than a timeout.
+///
+/// Each call is made on a worker thread, while the calling thread waits for it, dispatching window
+/// messages meanwhile. If the timeout passes first, the call fails with `RPC_E_TIMEOUT`, and a
+/// [`TimeoutError`] naming the member can be recovered from the error. The call itself is not
+/// interrupted, so one that is blocked, such as by a modal dialog, keeps the worker busy, and
+/// later calls with a timeout also time out until it returns.
+///
+/// On Windows, the object, and any object passed to or returned from a call, is marshalled to and
+/// from the worker. Objects held in arrays are not, so must not be passed to a call with a
+/// timeout.
+///
+/// This has every method of the class it wraps, and [`IDispatchExt`] is implemented for it, so
+/// that calls made by name have a timeout too:
+///
+/// ```rust
+/// use std::{thread, time::Duration};
+///
+/// use com_shim::{ComObject, IDispatchExt, TimeoutError, ToVariant, WithDeadline, com_object, com_shim};
+/// use windows::Win32::Foundation::RPC_E_TIMEOUT;
+///
+/// /// An object that takes its time.
+/// struct Sleeper;
+///
+/// com_object! {
+///     impl Sleeper {
+///         #[com(method)]
+///         fn sleep(&self, milliseconds: u32) {
+///             thread::sleep(Duration::from_millis(milliseconds.into()));
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     struct SleeperClass {
+///         fn Sleep(u32),
+///     }
+/// }
+///
+/// let sleeper = WithDeadline::new(
+///     SleeperClass::from(Sleeper.into_idispatch()),
+///     Duration::from_millis(50),
+/// );
+/// sleeper.sleep(0).unwrap();
+///
+/// let error = sleeper.sleep(300).unwrap_err();
+/// assert_eq!(error.code(), RPC_E_TIMEOUT);
+/// let timeout = TimeoutError::from_error(&error).unwrap();
+/// assert_eq!(timeout.member(), "Sleep");
+/// assert_eq!(timeout.timeout(), Duration::from_millis(50));
+///
+/// // Calls by name wait behind the call that is still running
+/// let error = sleeper.call("Sleep", vec![0_u32.to_variant()]).unwrap_err();
+/// assert_eq!(error.code(), RPC_E_TIMEOUT);
+/// thread::sleep(Duration::from_millis(300));
+/// sleeper.call("Sleep", vec![0_u32.to_variant()]).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct WithDeadline<T> {
+    object: T,
+    timeout: Duration,
+}
+
+impl<T> WithDeadline<T> {
+    /// Stop waiting for calls on `object` once they take longer than `timeout`.
+    pub fn new(object: T, timeout: Duration) -> Self {
+        Self { object, timeout }
+    }
+
+    /// How long calls are waited for.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Get back the object.
+    pub fn into_inner(self) -> T {
+        self.object
+    }
+}
+
+impl<T: HasIDispatch> HasIDispatch for WithDeadline<T> {
+    fn get_idispatch(&self) -> &IDispatch {
+        self.object.get_idispatch()
+    }
+
+    fn conversion(&self) -> Option<Conversion> {
+        self.object.conversion()
+    }
+
+    fn dry_run(&self) -> Option<&DryRun> {
+        self.object.dry_run()
+    }
+
+    fn deadline(&self) -> Option<Duration> {
+        Some(self.timeout)
+    }
+}
+
+impl<T: HasIDispatch> IDispatchExt for WithDeadline<T> {
+    fn call<S>(&self, name: S, args: Vec<Variant>) -> Result<Variant>
+    where
+        S: AsRef<str>,
+    {
+        object_scope(Some(self.timeout), || self.get_idispatch().call(name, args))
+    }
+
+    fn get<S>(&self, name: S) -> Result<Variant>
+    where
+        S: AsRef<str>,
+    {
+        object_scope(Some(self.timeout), || self.get_idispatch().get(name))
+    }
+
+    fn set<S>(&self, name: S, value: Variant) -> Result<Variant>
+    where
+        S: AsRef<str>,
+    {
+        object_scope(Some(self.timeout), || self.get_idispatch().set(name, value))
+    }
+}
+
+/// Run `f` with the timeout chosen for the object a call is made on, if there is one.
+pub(crate) fn object_scope<R>(timeout: Option<Duration>, f: impl FnOnce() -> R) -> R {
+    utils::scoped(&OBJECT, timeout, f)
+}
+
+/// Run `f` outside any call being made on this thread, without the timeout chosen for it.
+pub(crate) fn detached<R>(f: impl FnOnce() -> R) -> R {
+    utils::scoped(&OBJECT, None, f)
+}
+
+/// The timeout chosen for the object the current call is made on, if there is one.
+pub(crate) fn current() -> Option<Duration> {
+    OBJECT.get()
+}
+
+/// Invoke a member of `object` by name on the worker thread, waiting for it until `timeout`
+/// passes.
+pub(crate) fn invoke(
+    object: &IDispatch,
+    name: &str,
+    flags: DISPATCH_FLAGS,
+    arguments: &[Variant],
+    assemble: fn(&mut Vec<Variant>) -> DISPPARAMS,
+    timeout: Duration,
+) -> Result<Variant> {
+    let object = Moved::new(object.to_variant())?;
+    let arguments = arguments
+        .iter()
+        .cloned()
+        .map(Moved::new)
+        .collect::<Result<Vec<_>>>()?;
+    let name = name.to_string();
+    worker()?
+        .run_with_timeout(name.clone(), timeout, move || {
+            let object = IDispatch::from_variant(object.take()?.as_raw())?;
+            let mut arguments = arguments
+                .into_iter()
+                .map(Moved::take)
+                .collect::<Result<Vec<_>>>()?;
+            let params = assemble(&mut arguments);
+            let dispid = utils::get_method_dispid(&object, &name)?;
+            Moved::new(utils::invoke(&object, dispid, flags, &params)?)
+        })?
+        .take()
+}
+
+/// The thread calls with a timeout are made on, which is started by the first of them.
+fn worker() -> Result<&'static ComThread> {
+    static WORKER: OnceLock<ComThread> = OnceLock::new();
+
+    if let Some(worker) = WORKER.get() {
+        return Ok(worker);
+    }
+    let worker = ComThread::spawn()?;
+    Ok(WORKER.get_or_init(|| worker))
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::mem::ManuallyDrop;
+
+    use windows::{
+        Win32::System::{
+            Com::{
+                IDispatch, IStream, Marshal::CoMarshalInterThreadInterfaceInStream,
+                StructuredStorage::CoGetInterfaceAndReleaseStream,
+            },
+            Variant::{VARENUM, VARIANT_0_0, VARIANT_0_0_0, VT_DISPATCH, VT_UNKNOWN},
+        },
+        core::{ComInterface, IUnknown, Result},
+    };
+
+    use crate::Variant;
+
+    /// A value passed to or from the worker, with the object it holds, if any, marshalled into
+    /// the apartment of the thread that takes it.
+    pub(super) enum Moved {
+        Value(Variant),
+        Object(IStream, VARENUM),
+    }
+
+    // SAFETY: objects are passed as streams, which `CoMarshalInterThreadInterfaceInStream` makes
+    // for use by any thread, and other values are not tied to a thread.
+    unsafe impl Send for Moved {}
+
+    impl Moved {
+        pub(super) fn new(value: Variant) -> Result<Self> {
+            let vt = value.vt();
+            if vt != VT_DISPATCH && vt != VT_UNKNOWN {
+                return Ok(Self::Value(value));
+            }
+            // SAFETY: the variant holds an object, of the interface `vt` names.
+            let Some(object) = (unsafe { &*value.as_raw().Anonymous.Anonymous.Anonymous.punkVal })
+            else {
+                return Ok(Self::Value(value));
+            };
+            let iid = if vt == VT_DISPATCH {
+                IDispatch::IID
+            } else {
+                IUnknown::IID
+            };
+            // SAFETY: `object` implements the interface `iid` identifies.
+            let stream = unsafe { CoMarshalInterThreadInterfaceInStream(&raw const iid, object) }?;
+            Ok(Self::Object(stream, vt))
+        }
+
+        pub(super) fn take(self) -> Result<Variant> {
+            let (stream, vt) = match self {
+                Self::Value(value) => return Ok(value),
+                Self::Object(stream, vt) => (ManuallyDrop::new(stream), vt),
+            };
+            // SAFETY: the stream was made by `CoMarshalInterThreadInterfaceInStream`, and is
+            // released by this call, so is not dropped.
+            let object: IUnknown = unsafe {
+                if vt == VT_DISPATCH {
+                    CoGetInterfaceAndReleaseStream::<_, IDispatch>(&*stream)?.into()
+                } else {
+                    CoGetInterfaceAndReleaseStream(&*stream)?
+                }
+            };
+            Ok(Variant::from_v00(VARIANT_0_0 {
+                vt,
+                Anonymous: VARIANT_0_0_0 {
+                    punkVal: ManuallyDrop::new(Some(object)),
+                },
+                ..Default::default()
+            }))
+        }
+    }
+}
+
+/// Off Windows, objects are in-process and have no apartment, so are passed as they are.
+#[cfg(not(windows))]
+mod imp {
+    use windows::core::Result;
+
+    use crate::Variant;
+
+    /// A value passed to or from the worker.
+    pub(super) struct Moved(Variant);
+
+    // SAFETY: off Windows, objects are those implemented with `com_object!` for tests, which are
+    // only called by one thread at a time unless a call on them times out.
+    unsafe impl Send for Moved {}
+
+    impl Moved {
+        #[allow(clippy::unnecessary_wraps)] // marshalling an object can fail on Windows
+        pub(super) fn new(value: Variant) -> Result<Self> {
+            Ok(Self(value))
+        }
+
+        #[allow(clippy::unnecessary_wraps)] // unmarshalling an object can fail on Windows
+        pub(super) fn take(self) -> Result<Variant> {
+            Ok(self.0)
+        }
+    }
+}
+
+/// A call to fail once its deadline passes.
+struct Deadline {
+    at: Instant,
+    call: Weak<dyn Fail>,
+    error: TimeoutError,
+}
+
+/// Fail a call with a timeout error if it has not completed once `error.timeout()` has passed.
+///
+/// # Panics
+///
+/// Panics if the timer thread cannot be started.
+pub(crate) fn expire(call: Weak<dyn Fail>, error: TimeoutError) {
+    static TIMER: OnceLock<mpsc::Sender<Deadline>> = OnceLock::new();
+
+    let timer = TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("com-shim-timer".to_string())
+            .spawn(move || run_timer(&receiver))
+            .expect("failed to spawn the timer thread");
+        sender
+    });
+    let _ = timer.send(Deadline {
+        at: Instant::now() + error.timeout(),
+        call,
+        error,
+    });
+}
+
+/// Fail calls as their deadlines pass.
+fn run_timer(receiver: &mpsc::Receiver<Deadline>) {
+    let mut deadlines: Vec<Deadline> = vec![];
+    loop {
+        let next = match deadlines.iter().map(|d| d.at).min() {
+            Some(at) => receiver.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => receiver
+                .recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match next {
+            Ok(deadline) => deadlines.push(deadline),
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        deadlines.retain(|deadline| {
+            if deadline.at > now {
+                return deadline.call.strong_count() > 0;
+            }
+            if let Some(call) = deadline.call.upgrade() {
+                call.fail(deadline.error.clone().into());
+            }
+            false
+        });
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use windows::{
+        Win32::Foundation::{E_FAIL, RPC_E_TIMEOUT},
+        core::Error,
+    };
+
+    use super::{TimeoutError, parse_duration};
+
+    #[test]
+    fn timeouts_name_the_member() {
+        let timeout = TimeoutError::new("Sleep", Duration::from_millis(50));
+        assert_eq!(timeout.member(), "Sleep");
+        assert_eq!(timeout.timeout(), Duration::from_millis(50));
+        assert_eq!(timeout.to_string(), "`Sleep` did not return within 50ms");
+        assert_eq!(Error::from(timeout).code(), RPC_E_TIMEOUT);
+    }
+
+    #[test]
+    fn timeouts_are_recovered_from_errors() {
+        for timeout in [
+            Duration::ZERO,
+            Duration::from_nanos(7),
+            Duration::from_micros(1500),
+            Duration::from_millis(50),
+            Duration::new(2, 5),
+            Duration::MAX,
+        ] {
+            let error = TimeoutError::new("Sleep", timeout);
+            assert_eq!(TimeoutError::from_error(&error.clone().into()), Some(error));
+        }
+
+        // Backticks in a name are kept
+        let error = TimeoutError::new("`a` b`", Duration::from_secs(1));
+        assert_eq!(TimeoutError::from_error(&error.clone().into()), Some(error));
+
+        assert_eq!(TimeoutError::from_error(&RPC_E_TIMEOUT.into()), None);
+        let message = "`Sleep` did not return within 50ms";
+        assert_eq!(
+            TimeoutError::from_error(&Error::new(E_FAIL, message.into())),
+            None
+        );
+    }
+
+    #[test]
+    fn durations_are_read_as_debug_writes_them() {
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("10µs"), Some(Duration::from_micros(10)));
+        assert_eq!(parse_duration("0ns"), Some(Duration::ZERO));
+        for text in [
+            "",
+            "s",
+            ".5s",
+            "1.s5",
+            "+1s",
+            "1 s",
+            "1m",
+            "18446744073709551616s",
+        ] {
+            assert_eq!(parse_duration(text), None, "{text}");
+        }
+    }
+}
@@ -0,0 +1,515 @@
+use std::fmt;
+
+use windows::Win32::{Foundation::DECIMAL, System::Com::CY};
+
+const DECIMAL_NEG: u8 = 0x80;
+
+/// A value was outside of the range that can be represented by a [`Currency`] or [`Decimal`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecimalOutOfRange;
+
+impl fmt::Display for DecimalOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "value is out of range for an OLE Automation decimal")
+    }
+}
+
+impl std::error::Error for DecimalOutOfRange {}
+
+impl From<DecimalOutOfRange> for windows::core::Error {
+    fn from(_: DecimalOutOfRange) -> Self {
+        windows::Win32::Foundation::DISP_E_OVERFLOW.into()
+    }
+}
+
+/// A currency value as stored in a `VT_CY` [`VARIANT`](crate::VARIANT).
+///
+/// Currencies are fixed point numbers, stored as an `i64` scaled by 10,000, giving four decimal
+/// places of precision.
+///
+/// ```rust
+/// use com_shim::Currency;
+///
+/// let amount = Currency::from_scaled(1_234_500);
+/// assert_eq!(amount.to_string(), "123.45");
+/// assert_eq!(Currency::from_scaled(-5).to_string(), "-0.0005");
+/// assert_eq!(Currency::from_units(7), Some(Currency::from_scaled(70_000)));
+/// assert_eq!(Currency::MAX.to_string(), "922337203685477.5807");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency {
+    scaled: i64,
+}
+
+impl Currency {
+    /// The factor the stored value is scaled by.
+    pub const SCALE: i64 = 10_000;
+
+    /// The smallest representable currency value, -922,337,203,685,477.5808.
+    pub const MIN: Currency = Currency { scaled: i64::MIN };
+
+    /// The largest representable currency value, 922,337,203,685,477.5807.
+    pub const MAX: Currency = Currency { scaled: i64::MAX };
+
+    /// Create a currency value from its underlying representation, scaled by
+    /// [`Currency::SCALE`].
+    #[must_use]
+    pub const fn from_scaled(scaled: i64) -> Self {
+        Self { scaled }
+    }
+
+    /// Create a currency value from a whole number of units.
+    ///
+    /// Returns [`None`] if the value cannot be represented.
+    #[must_use]
+    pub fn from_units(units: i64) -> Option<Self> {
+        units.checked_mul(Self::SCALE).map(Self::from_scaled)
+    }
+
+    /// The underlying representation, scaled by [`Currency::SCALE`].
+    #[must_use]
+    pub const fn scaled(self) -> i64 {
+        self.scaled
+    }
+
+    /// Parse a currency value in the format written by its [`Display`](fmt::Display)
+    /// implementation, which must be exactly representable.
+    #[cfg(feature = "serde")]
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let value = Decimal::parse(text)?.normalize();
+        let scaled = i128::try_from(value.mantissa)
+            .ok()?
+            .checked_mul(10_i128.pow(4_u32.checked_sub(u32::from(value.scale))?))?;
+        let scaled = if value.negative { -scaled } else { scaled };
+        i64::try_from(scaled).ok().map(Self::from_scaled)
+    }
+}
+
+impl From<CY> for Currency {
+    fn from(value: CY) -> Self {
+        // SAFETY: every bit pattern of the union is a valid `i64`.
+        Self::from_scaled(unsafe { value.int64 })
+    }
+}
+
+impl From<Currency> for CY {
+    fn from(value: Currency) -> Self {
+        CY {
+            int64: value.scaled,
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Decimal::from(*self).normalize().fmt(f)
+    }
+}
+
+/// A decimal value as stored in a `VT_DECIMAL` [`VARIANT`](crate::VARIANT).
+///
+/// Decimals are a 96-bit unsigned mantissa, a sign and a scale from 0 to 28, giving the number
+/// of decimal places. The same number can be represented with different scales, and these are
+/// not equal to each other, so that values round-trip through a [`DECIMAL`] exactly:
+///
+/// ```rust
+/// use com_shim::Decimal;
+/// use windows::Win32::Foundation::DECIMAL;
+///
+/// let value = Decimal::new(-123_450, 3).unwrap();
+/// assert_eq!(value.to_string(), "-123.450");
+/// assert_eq!(value.normalize().to_string(), "-123.45");
+/// assert_ne!(value, value.normalize());
+///
+/// for value in [
+///     Decimal::new(0, 0).unwrap(),
+///     Decimal::new(1, 28).unwrap(),
+///     Decimal::new(-(1 << 95), 10).unwrap(),
+///     Decimal::MAX,
+///     Decimal::MIN,
+/// ] {
+///     assert_eq!(Decimal::try_from(DECIMAL::from(value)), Ok(value));
+/// }
+///
+/// assert_eq!(Decimal::new(1 << 96, 0), None);
+/// assert_eq!(Decimal::new(1, 29), None);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: u128,
+    scale: u8,
+    negative: bool,
+}
+
+impl Decimal {
+    /// The largest supported scale.
+    pub const MAX_SCALE: u8 = 28;
+
+    /// The largest supported mantissa, `2^96 - 1`.
+    pub const MAX_MANTISSA: u128 = (1 << 96) - 1;
+
+    /// The smallest representable decimal value.
+    pub const MIN: Decimal = Decimal {
+        mantissa: Self::MAX_MANTISSA,
+        scale: 0,
+        negative: true,
+    };
+
+    /// The largest representable decimal value.
+    pub const MAX: Decimal = Decimal {
+        mantissa: Self::MAX_MANTISSA,
+        scale: 0,
+        negative: false,
+    };
+
+    /// Create a decimal value of `mantissa / 10^scale`.
+    ///
+    /// Returns [`None`] if the mantissa does not fit in 96 bits or the scale is greater than
+    /// [`Decimal::MAX_SCALE`].
+    #[must_use]
+    pub fn new(mantissa: i128, scale: u8) -> Option<Self> {
+        Self::from_parts(mantissa.unsigned_abs(), scale, mantissa < 0)
+    }
+
+    /// Create a decimal value from its unsigned mantissa, scale and sign.
+    ///
+    /// Unlike [`Decimal::new`], this can represent a negative zero.
+    ///
+    /// Returns [`None`] if the mantissa does not fit in 96 bits or the scale is greater than
+    /// [`Decimal::MAX_SCALE`].
+    #[must_use]
+    pub fn from_parts(mantissa: u128, scale: u8, negative: bool) -> Option<Self> {
+        (mantissa <= Self::MAX_MANTISSA && scale <= Self::MAX_SCALE).then_some(Self {
+            mantissa,
+            scale,
+            negative,
+        })
+    }
+
+    /// The signed mantissa.
+    #[must_use]
+    #[allow(clippy::cast_possible_wrap)] // the mantissa is at most 96 bits
+    pub fn mantissa(self) -> i128 {
+        if self.negative {
+            -(self.mantissa as i128)
+        } else {
+            self.mantissa as i128
+        }
+    }
+
+    /// The number of decimal places.
+    #[must_use]
+    pub fn scale(self) -> u8 {
+        self.scale
+    }
+
+    /// Whether the sign bit is set. This is also true of a negative zero.
+    #[must_use]
+    pub fn is_sign_negative(self) -> bool {
+        self.negative
+    }
+
+    /// Remove trailing zeroes from the fractional part, reducing the scale as far as possible.
+    #[must_use]
+    pub fn normalize(self) -> Self {
+        let mut value = self;
+        while value.scale > 0 && value.mantissa.is_multiple_of(10) {
+            value.mantissa /= 10;
+            value.scale -= 1;
+        }
+        value
+    }
+
+    /// Parse a decimal value in the format written by its [`Display`](fmt::Display)
+    /// implementation, keeping the scale.
+    #[cfg(feature = "serde")]
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text),
+        };
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if whole.is_empty()
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let mantissa = format!("{whole}{fraction}").parse().ok()?;
+        Self::from_parts(mantissa, u8::try_from(fraction.len()).ok()?, negative)
+    }
+}
+
+impl TryFrom<DECIMAL> for Decimal {
+    type Error = DecimalOutOfRange;
+
+    fn try_from(value: DECIMAL) -> Result<Self, Self::Error> {
+        // SAFETY: every bit pattern of these unions is valid.
+        let (scale, sign, lo) = unsafe {
+            (
+                value.Anonymous1.Anonymous.scale,
+                value.Anonymous1.Anonymous.sign,
+                value.Anonymous2.Lo64,
+            )
+        };
+        let mantissa = (u128::from(value.Hi32) << 64) | u128::from(lo);
+        Self::from_parts(mantissa, scale, sign & DECIMAL_NEG != 0).ok_or(DecimalOutOfRange)
+    }
+}
+
+impl From<Decimal> for DECIMAL {
+    #[allow(clippy::cast_possible_truncation)] // deliberately splitting the mantissa
+    fn from(value: Decimal) -> Self {
+        let mut decimal = DECIMAL {
+            Hi32: (value.mantissa >> 64) as u32,
+            ..Default::default()
+        };
+        decimal.Anonymous1.Anonymous.scale = value.scale;
+        decimal.Anonymous1.Anonymous.sign = if value.negative { DECIMAL_NEG } else { 0 };
+        decimal.Anonymous2.Lo64 = value.mantissa as u64;
+        decimal
+    }
+}
+
+impl From<Currency> for Decimal {
+    fn from(value: Currency) -> Self {
+        Self {
+            mantissa: u128::from(value.scaled.unsigned_abs()),
+            scale: 4,
+            negative: value.scaled < 0,
+        }
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Self {
+            mantissa: u128::from(value.unsigned_abs()),
+            scale: 0,
+            negative: value < 0,
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!(
+            "{:0>width$}",
+            self.mantissa,
+            width = usize::from(self.scale) + 1
+        );
+        let (whole, fraction) = digits.split_at(digits.len() - usize::from(self.scale));
+        if self.negative && self.mantissa != 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{whole}")?;
+        if !fraction.is_empty() {
+            write!(f, ".{fraction}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Change the scale of `mantissa / 10^from` to `to`, rounding half to even.
+pub(crate) fn rescale(mantissa: i128, from: u32, to: u32) -> Option<i128> {
+    if to >= from {
+        return mantissa.checked_mul(10_i128.checked_pow(to - from)?);
+    }
+    let Some(divisor) = 10_i128.checked_pow(from - to) else {
+        // Every mantissa is less than half of this
+        return Some(0);
+    };
+    let (quotient, remainder) = (mantissa / divisor, mantissa % divisor);
+    let twice = remainder.unsigned_abs() * 2;
+    let divisor = divisor.unsigned_abs();
+    if twice > divisor || (twice == divisor && quotient % 2 != 0) {
+        Some(quotient + mantissa.signum())
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Conversions to and from `rust_decimal` are exact, including the scale and sign, except that
+/// `rust_decimal` does not preserve negative zero:
+///
+/// ```rust
+/// use com_shim::{Currency, Decimal};
+///
+/// for value in [
+///     Decimal::new(-123_450, 3).unwrap(),
+///     Decimal::new(1, 28).unwrap(),
+///     Decimal::MAX,
+///     Decimal::MIN,
+/// ] {
+///     let converted = rust_decimal::Decimal::from(value);
+///     assert_eq!(converted.to_string(), value.to_string());
+///     assert_eq!(Decimal::from(converted), value);
+/// }
+///
+/// let negative_zero = Decimal::from_parts(0, 2, true).unwrap();
+/// assert_eq!(Decimal::from(rust_decimal::Decimal::from(negative_zero)), Decimal::new(0, 2).unwrap());
+///
+/// let amount = Currency::from_scaled(1_234_567);
+/// assert_eq!(Currency::try_from(rust_decimal::Decimal::from(amount)), Ok(amount));
+/// assert_eq!(
+///     Currency::try_from(rust_decimal::Decimal::new(123_456_785, 6)),
+///     Ok(Currency::from_scaled(1_234_568)),
+/// );
+/// ```
+#[cfg(feature = "rust_decimal")]
+impl From<Decimal> for rust_decimal::Decimal {
+    #[allow(clippy::cast_possible_truncation)] // deliberately splitting the mantissa
+    fn from(value: Decimal) -> Self {
+        rust_decimal::Decimal::from_parts(
+            value.mantissa as u32,
+            (value.mantissa >> 32) as u32,
+            (value.mantissa >> 64) as u32,
+            value.negative,
+            u32::from(value.scale),
+        )
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl From<rust_decimal::Decimal> for Decimal {
+    #[allow(clippy::cast_possible_truncation)] // `rust_decimal` has the same limits
+    fn from(value: rust_decimal::Decimal) -> Self {
+        Self {
+            mantissa: value.mantissa().unsigned_abs(),
+            scale: value.scale() as u8,
+            negative: value.is_sign_negative(),
+        }
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl From<Currency> for rust_decimal::Decimal {
+    fn from(value: Currency) -> Self {
+        rust_decimal::Decimal::new(value.scaled, 4)
+    }
+}
+
+#[cfg(feature = "rust_decimal")]
+impl TryFrom<rust_decimal::Decimal> for Currency {
+    type Error = DecimalOutOfRange;
+
+    /// Values with more than four decimal places are rounded, half to even, as OLE Automation
+    /// does.
+    fn try_from(value: rust_decimal::Decimal) -> Result<Self, Self::Error> {
+        let rounded =
+            value.round_dp_with_strategy(4, rust_decimal::RoundingStrategy::MidpointNearestEven);
+        let scaled = rounded.mantissa() * 10_i128.pow(4 - rounded.scale().min(4));
+        i64::try_from(scaled)
+            .map(Currency::from_scaled)
+            .map_err(|_| DecimalOutOfRange)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::{Foundation::DECIMAL, System::Com::CY};
+
+    use super::{Currency, Decimal, DecimalOutOfRange, rescale};
+    use crate::{FromVariant, ToVariant};
+
+    #[test]
+    fn currency_limits() {
+        assert_eq!(Currency::MAX.to_string(), "922337203685477.5807");
+        assert_eq!(Currency::MIN.to_string(), "-922337203685477.5808");
+        for value in [Currency::MAX, Currency::MIN] {
+            assert_eq!(Currency::from(CY::from(value)), value);
+            assert_eq!(Currency::from_variant(&value.to_variant()), Ok(value));
+            assert_eq!(Decimal::from(value).to_string(), value.to_string());
+        }
+        assert_eq!(
+            Currency::from_units(922_337_203_685_477),
+            Some(Currency::from_scaled(9_223_372_036_854_770_000))
+        );
+        assert_eq!(Currency::from_units(922_337_203_685_478), None);
+        assert_eq!(Currency::from_units(-922_337_203_685_478), None);
+    }
+
+    #[test]
+    fn decimal_limits() {
+        assert_eq!(Decimal::MAX.to_string(), "79228162514264337593543950335");
+        assert_eq!(Decimal::MIN.to_string(), "-79228162514264337593543950335");
+        assert_eq!(
+            Decimal::from_parts(Decimal::MAX_MANTISSA + 1, 0, false),
+            None
+        );
+        for value in [Decimal::MAX, Decimal::MIN] {
+            assert_eq!(Decimal::try_from(DECIMAL::from(value)), Ok(value));
+            assert_eq!(Decimal::from_variant(&value.to_variant()), Ok(value));
+        }
+    }
+
+    #[test]
+    fn largest_scale() {
+        let smallest = Decimal::new(1, 28).unwrap();
+        assert_eq!(smallest.to_string(), "0.0000000000000000000000000001");
+        assert_eq!(Decimal::try_from(DECIMAL::from(smallest)), Ok(smallest));
+
+        let largest = Decimal::from_parts(Decimal::MAX_MANTISSA, 28, false).unwrap();
+        assert_eq!(largest.to_string(), "7.9228162514264337593543950335");
+        assert_eq!(Decimal::from_variant(&largest.to_variant()), Ok(largest));
+
+        assert_eq!(Decimal::new(1, 29), None);
+        let mut decimal = DECIMAL::from(smallest);
+        decimal.Anonymous1.Anonymous.scale = 29;
+        assert_eq!(Decimal::try_from(decimal), Err(DecimalOutOfRange));
+    }
+
+    #[test]
+    fn negative_zero() {
+        let zero = Decimal::from_parts(0, 2, true).unwrap();
+        assert!(zero.is_sign_negative());
+        assert_eq!(zero.mantissa(), 0);
+        assert_eq!(zero.to_string(), "0.00");
+
+        let normalized = zero.normalize();
+        assert_eq!(normalized.scale(), 0);
+        assert!(normalized.is_sign_negative());
+
+        let round_trip = Decimal::try_from(DECIMAL::from(zero)).unwrap();
+        assert_eq!(round_trip, zero);
+        assert!(round_trip.is_sign_negative());
+    }
+
+    #[test]
+    fn rescaling_rounds_half_to_even() {
+        for (mantissa, from, to, expected) in [
+            (15, 1, 0, Some(2)),
+            (25, 1, 0, Some(2)),
+            (-25, 1, 0, Some(-2)),
+            (-35, 1, 0, Some(-4)),
+            (251, 2, 0, Some(3)),
+            (1, 0, 4, Some(10_000)),
+            (1, 50, 0, Some(0)),
+            (i128::MAX, 0, 1, None),
+        ] {
+            assert_eq!(
+                rescale(mantissa, from, to),
+                expected,
+                "{mantissa} from scale {from} to {to}"
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn parsing_limits() {
+        for value in [Currency::MAX, Currency::MIN] {
+            assert_eq!(Currency::parse(&value.to_string()), Some(value));
+        }
+        assert_eq!(Currency::parse("922337203685477.5808"), None);
+        assert_eq!(Currency::parse("0.00001"), None);
+
+        for value in [Decimal::MAX, Decimal::MIN, Decimal::new(1, 28).unwrap()] {
+            assert_eq!(Decimal::parse(&value.to_string()), Some(value));
+        }
+        assert_eq!(Decimal::parse("79228162514264337593543950336"), None);
+        assert_eq!(Decimal::parse("0.00000000000000000000000000001"), None);
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    cell::{RefCell, RefMut},
+    fmt, mem,
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use windows::Win32::{
+    Foundation::DISP_E_TYPEMISMATCH,
+    System::{
+        Ole::SafeArrayGetDim,
+        Variant::{
+            VARENUM, VARIANT, VT_ARRAY, VT_BYREF, VT_DISPATCH, VT_RECORD, VT_TYPEMASK, VT_UNKNOWN,
+            VT_VARIANT,
+        },
+    },
+};
+
+use crate::{
+    Conversion, HasIDispatch, IDispatch, Locale, Member, MemberKind, Variant, array,
+    conversion::VtName, format_variant, utils,
+};
+
+/// Records the changes that calls would make, instead of making them.
+///
+/// While a dry run applies, [mutating](crate::Effect::Mutating) members of classes declared with
+/// [`com_shim!`](crate::com_shim), which are setters and methods unless they are marked otherwise,
+/// are not called. Each call is recorded as a [`Change`] and returns a placeholder, which is
+/// `Empty` unless one is given with [`DryRun::with_placeholder`]. Idempotent members, such as
+/// getters, are still called, so a script can be tried against a live application without
+/// changing anything.
+///
+/// A dry run applies to every call made on this thread with [`DryRun::scope`], or to the calls
+/// made on one object with [`WithDryRun`], which takes precedence. Calls made directly through
+/// [`IDispatchExt`](crate::IDispatchExt) are always made. A dry run also applies to calls made
+/// through a [`Remote`](crate::Remote) handle, whose changes are recorded on the
+/// [`ComThread`](crate::ComThread) and sent back; objects they are passed cannot leave the thread,
+/// so they are recorded as `Null`.
+///
+/// ```rust
+/// use std::cell::{Cell, RefCell};
+///
+/// use com_shim::{ComObject, DryRun, Result, ToVariant, WithDryRun, com_object, com_shim};
+///
+/// #[derive(Default)]
+/// struct Form {
+///     title: RefCell<String>,
+///     saved: Cell<bool>,
+/// }
+///
+/// com_object! {
+///     impl Form {
+///         #[com(get)]
+///         fn title(&self) -> String {
+///             self.title.borrow().clone()
+///         }
+///
+///         #[com(set)]
+///         fn set_title(&self, title: String) {
+///             *self.title.borrow_mut() = title;
+///         }
+///
+///         #[com(method)]
+///         fn save(&self, copies: i32) -> bool {
+///             self.saved.set(copies > 0);
+///             true
+///         }
+///
+///         #[com(method)]
+///         fn is_valid(&self) -> bool {
+///             !self.title.borrow().is_empty()
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     struct FormClass {
+///         mut Title: String,
+///         fn Save(i32) -> bool,
+///         #[com(idempotent)]
+///         fn IsValid() -> bool,
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let form = FormClass::from(Form::default().into_idispatch());
+/// form.set_title("Draft".to_string())?;
+///
+/// let dry_run = DryRun::new();
+/// dry_run.scope(|| -> Result<()> {
+///     form.set_title("Final".to_string())?;
+///     assert_eq!(form.title()?, "Draft");
+///     assert!(form.is_valid()?);
+///     assert!(!form.save(2)?);
+///     Ok(())
+/// })?;
+/// let report = dry_run.changes().iter().map(ToString::to_string).collect::<Vec<_>>();
+/// assert_eq!(report, ["`Title` = Final", "`Save`(2)"]);
+///
+/// // A dry run can also be chosen for an object, with a placeholder for its methods to return
+/// let form = WithDryRun::new(form, DryRun::with_placeholder(true.to_variant()));
+/// assert!(form.save(1)?);
+/// assert_eq!(form.dry_run().take_changes().len(), 1);
+/// assert!(form.dry_run().changes().is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct DryRun(Rc<Recorder>);
+
+#[derive(Default)]
+struct Recorder {
+    placeholder: Variant,
+    changes: RefCell<Vec<Change>>,
+    /// The changes recorded on a `ComThread` for calls through a `Remote` handle.
+    remote: Arc<Mutex<Vec<Sent<Change>>>>,
+}
+
+impl Recorder {
+    /// The changes recorded so far, including those sent back from a `ComThread`.
+    fn changes(&self) -> RefMut<'_, Vec<Change>> {
+        let mut changes = self.changes.borrow_mut();
+        let remote = mem::take(&mut *self.remote.lock().unwrap_or_else(PoisonError::into_inner));
+        changes.extend(remote.into_iter().map(|change| change.0));
+        changes
+    }
+}
+
+thread_local! {
+    /// The dry run chosen for calls on this thread.
+    static SESSION: RefCell<Option<DryRun>> = const { RefCell::new(None) };
+    /// The dry run chosen for the object the current call is made on.
+    static OBJECT: RefCell<Option<DryRun>> = const { RefCell::new(None) };
+}
+
+impl DryRun {
+    /// Start a dry run whose calls return `Empty`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a dry run whose calls return `placeholder`.
+    #[must_use]
+    pub fn with_placeholder(placeholder: Variant) -> Self {
+        Self(Rc::new(Recorder {
+            placeholder,
+            ..Recorder::default()
+        }))
+    }
+
+    /// The value calls that are not made return.
+    #[must_use]
+    pub fn placeholder(&self) -> &Variant {
+        &self.0.placeholder
+    }
+
+    /// Run `f` in this dry run, recording the changes its calls would make.
+    pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        utils::scoped(&SESSION, Some(self.clone()), f)
+    }
+
+    /// The changes recorded so far, in the order they would have been made.
+    #[must_use]
+    pub fn changes(&self) -> Vec<Change> {
+        self.0.changes().clone()
+    }
+
+    /// Take the changes recorded so far, leaving none.
+    #[must_use]
+    pub fn take_changes(&self) -> Vec<Change> {
+        mem::take(&mut self.0.changes())
+    }
+}
+
+impl fmt::Debug for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DryRun")
+            .field("placeholder", &self.0.placeholder)
+            .field("changes", &self.0.changes())
+            .finish()
+    }
+}
+
+/// A call that was recorded by a [`DryRun`] instead of being made.
+#[derive(Clone, Debug)]
+pub struct Change {
+    member: Member,
+    arguments: Vec<Variant>,
+}
+
+impl Change {
+    /// The member that would have been set or called.
+    #[must_use]
+    pub fn member(&self) -> Member {
+        self.member
+    }
+
+    /// The arguments the member would have been called with, or the value it would have been set
+    /// to.
+    #[must_use]
+    pub fn arguments(&self) -> &[Variant] {
+        &self.arguments
+    }
+}
+
+/// Values are written in general format, or as their type if they cannot be formatted.
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: &Variant| match format_variant(value, "", &Locale::EN_US) {
+            Ok(text) => text,
+            Err(_) => VtName(value.vt()).to_string(),
+        };
+        let arguments = self.arguments.iter().map(value).collect::<Vec<_>>();
+        match self.member.kind() {
+            MemberKind::Set => write!(f, "`{}` = {}", self.member.name(), arguments.join(", ")),
+            MemberKind::Get | MemberKind::Method => {
+                write!(f, "`{}`({})", self.member.name(), arguments.join(", "))
+            }
+        }
+    }
+}
+
+/// An object whose setters and mutating methods are recorded by a [`DryRun`] instead of being
+/// called.
+///
+/// This has every method of the class it wraps.
+#[derive(Clone, Debug)]
+pub struct WithDryRun<T> {
+    object: T,
+    dry_run: DryRun,
+}
+
+impl<T> WithDryRun<T> {
+    /// Record the changes calls on `object` would make in `dry_run`.
+    pub fn new(object: T, dry_run: DryRun) -> Self {
+        Self { object, dry_run }
+    }
+
+    /// The dry run changes are recorded in.
+    pub fn dry_run(&self) -> &DryRun {
+        &self.dry_run
+    }
+
+    /// Get back the object.
+    pub fn into_inner(self) -> T {
+        self.object
+    }
+}
+
+impl<T: HasIDispatch> HasIDispatch for WithDryRun<T> {
+    fn get_idispatch(&self) -> &IDispatch {
+        self.object.get_idispatch()
+    }
+
+    fn conversion(&self) -> Option<Conversion> {
+        self.object.conversion()
+    }
+
+    fn dry_run(&self) -> Option<&DryRun> {
+        Some(&self.dry_run)
+    }
+
+    fn deadline(&self) -> Option<Duration> {
+        self.object.deadline()
+    }
+}
+
+/// Run `f` with the dry run chosen for the object a call is made on, or none.
+pub(crate) fn object_scope<R>(dry_run: Option<&DryRun>, f: impl FnOnce() -> R) -> R {
+    utils::scoped(&OBJECT, dry_run.cloned(), f)
+}
+
+/// Run `f` without any dry run, so that its calls are made.
+pub(crate) fn detached<R>(f: impl FnOnce() -> R) -> R {
+    utils::scoped(&SESSION, None, || utils::scoped(&OBJECT, None, f))
+}
+
+/// The dry run that applies to calls made on this thread now, if one does.
+fn current() -> Option<DryRun> {
+    OBJECT
+        .with_borrow(Clone::clone)
+        .or_else(|| SESSION.with_borrow(Clone::clone))
+}
+
+/// Record a call to the current member instead of making it, returning the placeholder to return
+/// from it, if it would change anything and a dry run applies.
+pub(crate) fn intercept(arguments: &[Variant]) -> Option<Variant> {
+    let member = Member::current().filter(|member| !member.is_idempotent())?;
+    let dry_run = current()?;
+    tracing::debug!("Recording a call to {} in a dry run", member.name());
+    dry_run.0.changes().push(Change {
+        member,
+        arguments: arguments.to_vec(),
+    });
+    Some(dry_run.0.placeholder.clone())
+}
+
+/// A dry run carried to a `ComThread` by a call through a [`Remote`](crate::Remote) handle.
+pub(crate) struct Carried {
+    placeholder: Sent<Variant>,
+    changes: Arc<Mutex<Vec<Sent<Change>>>>,
+}
+
+impl Carried {
+    /// The dry run that applies to calls made on this thread now, if one does.
+    pub(crate) fn current() -> Option<Self> {
+        current().map(|dry_run| Self {
+            placeholder: Sent::value(dry_run.0.placeholder.clone()),
+            changes: dry_run.0.remote.clone(),
+        })
+    }
+
+    /// Run `f` in a dry run on this thread, sending back the changes its calls would make.
+    pub(crate) fn scope<R>(self, f: impl FnOnce() -> R) -> R {
+        let dry_run = DryRun::with_placeholder(self.placeholder.0);
+        let result = dry_run.scope(f);
+        let changes = dry_run.take_changes().into_iter().map(Sent::change);
+        self.changes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(changes);
+        result
+    }
+}
+
+/// A value recorded on one thread and read on another, without the objects it was given.
+struct Sent<T>(T);
+
+// SAFETY: a `Sent` is only made by `Sent::value` and `Sent::change`, which leave out objects and
+// references, so it only owns memory that any thread can free.
+unsafe impl<T> Send for Sent<T> {}
+
+impl Sent<Variant> {
+    /// Send a value, or `Null` if it holds an object.
+    fn value(value: Variant) -> Self {
+        Self(if holds_object(&value) {
+            Variant::null()
+        } else {
+            value
+        })
+    }
+}
+
+/// Whether a value holds an object or a reference, itself or in an element of an array of
+/// variants. Arrays that cannot be read are taken to hold one.
+fn holds_object(value: &VARIANT) -> bool {
+    // SAFETY: `vt` is valid for every variant.
+    let vt = unsafe { value.Anonymous.Anonymous.vt }.0;
+    if vt & VT_BYREF.0 != 0 {
+        return true;
+    }
+    match VARENUM(vt & VT_TYPEMASK.0) {
+        VT_VARIANT if vt & VT_ARRAY.0 != 0 => {
+            // SAFETY: the variant holds an array of variants, which are only borrowed. Reading
+            // stops at the first element holding an object.
+            unsafe {
+                let psa = value.Anonymous.Anonymous.Anonymous.parray;
+                if psa.is_null() || SafeArrayGetDim(psa) == 0 {
+                    return true;
+                }
+                array::read_safearray(psa, SafeArrayGetDim(psa), |element| {
+                    if holds_object(element) {
+                        Err(DISP_E_TYPEMISMATCH.into())
+                    } else {
+                        Ok(())
+                    }
+                })
+                .is_err()
+            }
+        }
+        VT_DISPATCH | VT_UNKNOWN | VT_VARIANT | VT_RECORD => true,
+        _ => false,
+    }
+}
+
+impl Sent<Change> {
+    /// Send a change, with each argument that holds an object as `Null`.
+    fn change(change: Change) -> Self {
+        Self(Change {
+            member: change.member,
+            arguments: change
+                .arguments
+                .into_iter()
+                .map(|argument| Sent::value(argument).0)
+                .collect(),
+        })
+    }
+}
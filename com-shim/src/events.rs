@@ -0,0 +1,487 @@
+use std::{
+    collections::VecDeque,
+    future,
+    marker::PhantomData,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+use windows::{
+    Win32::System::Com::{DISPATCH_FLAGS, DISPPARAMS, IConnectionPoint, IConnectionPointContainer},
+    core::{ComInterface, GUID, Result},
+};
+
+use crate::{
+    HasIDispatch, Variant,
+    server::{self, Dispatch},
+};
+
+/// An outgoing interface declared with [`com_events!`](crate::com_events), through which an
+/// object raises events on a handler of type `H`.
+///
+/// `com_events!` generates a trait with a method for each event, and implements this for that
+/// trait as `dyn Trait`. Handlers implement the trait, and are connected to an object with its
+/// `advise` method, which returns an [`EventConnection`]. Events are delivered through an
+/// `IDispatch` sink, and their arguments are converted with [`FromVariant`](crate::FromVariant).
+///
+/// ```rust
+/// use std::{cell::RefCell, rc::Rc};
+///
+/// use com_shim::{EventConnection, IDispatch, Result, com_events};
+///
+/// com_events! {
+///     /// Events raised by a session.
+///     trait SessionEvents("{2B3A5F0E-7C1D-4E8B-9A6F-1D2C3B4A5E6F}") {
+///         fn Change(String, i32) = 1001,
+///         fn Destroy(),
+///     }
+/// }
+///
+/// struct Log(Rc<RefCell<Vec<String>>>);
+///
+/// impl SessionEvents for Log {
+///     fn change(&self, id: String, count: i32) -> Result<()> {
+///         self.0.borrow_mut().push(format!("{id} changed {count} times"));
+///         Ok(())
+///     }
+/// }
+///
+/// /// Log the changes made in a session, until the connection is dropped.
+/// fn watch(session: &IDispatch, log: Rc<RefCell<Vec<String>>>) -> Result<EventConnection> {
+///     Log(log).advise(session)
+/// }
+/// ```
+pub trait EventInterface<H> {
+    /// The IID of the interface.
+    const IID: GUID;
+
+    /// The name and `DISPID` of each event.
+    const EVENTS: &'static [(&'static str, i32)];
+
+    /// Raise an event on a handler, with its arguments in reverse order.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no such event, if the arguments cannot be converted, or if the handler
+    /// fails.
+    fn raise(handler: &H, dispid: i32, params: &DISPPARAMS) -> Result<()>;
+}
+
+/// An `IDispatch` receiving the events of an interface `I`.
+struct Sink<I: ?Sized, H> {
+    handler: H,
+    interface: PhantomData<fn(&I)>,
+}
+
+impl<I, H> Dispatch for Sink<I, H>
+where
+    I: EventInterface<H> + ?Sized + 'static,
+    H: 'static,
+{
+    fn implements(&self, iid: &GUID) -> bool {
+        *iid == I::IID
+    }
+
+    fn dispid(&self, name: &str) -> Option<i32> {
+        crate::__private::find_dispid(I::EVENTS, name)
+    }
+
+    fn invoke(&self, dispid: i32, _flags: DISPATCH_FLAGS, params: &DISPPARAMS) -> Result<Variant> {
+        I::raise(&self.handler, dispid, params)?;
+        Ok(Variant::empty())
+    }
+}
+
+/// A handler connected to the events of an object, which is disconnected when this is dropped.
+#[must_use = "the handler is disconnected when the connection is dropped"]
+pub struct EventConnection {
+    point: Option<IConnectionPoint>,
+    cookie: u32,
+}
+
+impl EventConnection {
+    /// Connect a handler to the events an object raises through the interface `I`.
+    ///
+    /// This is usually called through the `advise` method of a trait generated by
+    /// [`com_events!`](crate::com_events).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the object does not raise events through `I`, or refuses the connection.
+    pub fn advise<I, H>(source: &impl HasIDispatch, handler: H) -> Result<Self>
+    where
+        I: EventInterface<H> + ?Sized + 'static,
+        H: 'static,
+    {
+        let container: IConnectionPointContainer = source.get_idispatch().cast()?;
+        // SAFETY: the IID is valid for the duration of the call.
+        let point = unsafe { container.FindConnectionPoint(&I::IID) }?;
+        let sink = server::into_idispatch(Sink::<I, H> {
+            handler,
+            interface: PhantomData,
+        });
+        // SAFETY: the sink is a valid object.
+        let cookie = unsafe { point.Advise(&sink) }?;
+        tracing::debug!("Connected to events {:?} with cookie {cookie}", I::IID);
+        Ok(Self {
+            point: Some(point),
+            cookie,
+        })
+    }
+
+    /// Disconnect the handler, reporting any failure to do so.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the object refuses to disconnect the handler.
+    pub fn unadvise(mut self) -> Result<()> {
+        match self.point.take() {
+            // SAFETY: the cookie was returned when the handler was connected to this point.
+            Some(point) => unsafe { point.Unadvise(self.cookie) },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for EventConnection {
+    fn drop(&mut self) {
+        if let Some(point) = self.point.take() {
+            // SAFETY: the cookie was returned when the handler was connected to this point.
+            if let Err(e) = unsafe { point.Unadvise(self.cookie) } {
+                tracing::warn!("Failed to disconnect from events: {e}");
+            }
+        }
+    }
+}
+
+/// The buffer shared by an [`EventStream`] and the handler filling it.
+struct Queue<E> {
+    events: VecDeque<E>,
+    capacity: usize,
+    dropped: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// The handler behind an [`EventStream`], which buffers each event it receives.
+///
+/// [`com_events!`](crate::com_events) implements the trait it generates for this, so that it can
+/// be connected to an object.
+pub struct EventSender<E>(Arc<Mutex<Queue<E>>>);
+
+impl<E> EventSender<E> {
+    /// Buffer an event, dropping the oldest buffered event if the buffer is full.
+    pub fn send(&self, event: E) {
+        let mut queue = lock(&self.0);
+        if queue.closed {
+            return;
+        }
+        if queue.events.len() == queue.capacity {
+            queue.events.pop_front();
+            queue.dropped += 1;
+            tracing::debug!("Dropped an event, as the buffer is full");
+        }
+        queue.events.push_back(event);
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+fn lock<E>(queue: &Mutex<Queue<E>>) -> MutexGuard<'_, Queue<E>> {
+    queue.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The events an object raises, received as an asynchronous stream of a typed event enum.
+///
+/// For each trait it generates, [`com_events!`](crate::com_events) also generates an enum with a
+/// variant for each event, named after the trait with a trailing `Events` replaced by `Event`, or
+/// with `Event` appended. Its `stream` function connects to an object and returns this stream,
+/// which also implements `futures::Stream` with the `futures` feature.
+///
+/// COM delivers events on the thread that connected to them, whenever it processes messages, and
+/// cannot wait for them to be received. So events are buffered, up to the capacity given, and
+/// when the buffer is full **the oldest buffered event is dropped** to make room for the newest.
+/// The number of events dropped is counted by [`EventStream::dropped`].
+///
+/// The stream ends once it is [closed](EventStream::close) and every buffered event has been
+/// received. Dropping it disconnects from the object.
+///
+/// Events only arrive while the thread that connected dispatches window messages, so the stream
+/// must be polled on that thread by an executor that keeps doing so, such as the event loop of a
+/// user interface. Blocking the thread on it, such as with `futures::executor::block_on`, waits
+/// for events that cannot arrive. Events that are already buffered can be taken without waiting
+/// with [`EventStream::try_next`].
+///
+/// An `EventStream` is not `Send`, because it must disconnect on the thread that connected. With
+/// tokio, it can be polled by a task spawned with `tokio::task::spawn_local` on that thread, but
+/// not by one spawned with `tokio::spawn`.
+///
+/// ```rust
+/// use com_shim::{IDispatch, Result, com_events};
+///
+/// com_events! {
+///     trait SessionEvents("{2B3A5F0E-7C1D-4E8B-9A6F-1D2C3B4A5E6F}") {
+///         fn Change(String, i32),
+///         fn Destroy(),
+///     }
+/// }
+///
+/// /// Print the changes made in a session, until it is destroyed.
+/// async fn watch(session: &IDispatch) -> Result<()> {
+///     let mut events = SessionEvent::stream(session, 64)?;
+///     while let Some(event) = events.next().await {
+///         match event {
+///             SessionEvent::Change(id, count) => println!("{id} changed {count} times"),
+///             SessionEvent::Destroy => events.close()?,
+///         }
+///     }
+///     println!("{} changes were missed", events.dropped());
+///     Ok(())
+/// }
+/// ```
+pub struct EventStream<E> {
+    queue: Arc<Mutex<Queue<E>>>,
+    connection: Option<EventConnection>,
+}
+
+impl<E: 'static> EventStream<E> {
+    /// Connect to the events an object raises through the interface `I`, buffering up to
+    /// `capacity` of them, and at least one.
+    ///
+    /// This is usually called through the `stream` function of an enum generated by
+    /// [`com_events!`](crate::com_events).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the object does not raise events through `I`, or refuses the connection.
+    pub fn advise<I>(source: &impl HasIDispatch, capacity: usize) -> Result<Self>
+    where
+        I: EventInterface<EventSender<E>> + ?Sized + 'static,
+    {
+        let capacity = capacity.max(1);
+        let queue = Arc::new(Mutex::new(Queue {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            dropped: 0,
+            closed: false,
+            waker: None,
+        }));
+        let connection = EventConnection::advise::<I, _>(source, EventSender(queue.clone()))?;
+        Ok(Self {
+            queue,
+            connection: Some(connection),
+        })
+    }
+}
+
+impl<E> EventStream<E> {
+    /// Receive the next event, waiting for one to be raised if none are buffered. Returns `None`
+    /// once the stream is closed and empty.
+    pub async fn next(&mut self) -> Option<E> {
+        future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    /// Receive the next buffered event without waiting, or `None` if none are buffered.
+    pub fn try_next(&mut self) -> Option<E> {
+        lock(&self.queue).events.pop_front()
+    }
+
+    /// The number of events dropped because the buffer was full.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        lock(&self.queue).dropped
+    }
+
+    /// Disconnect from the object, so that no more events are received. Events that are already
+    /// buffered can still be received.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the object refuses to disconnect.
+    pub fn close(&mut self) -> Result<()> {
+        lock(&self.queue).closed = true;
+        match self.connection.take() {
+            Some(connection) => connection.unadvise(),
+            None => Ok(()),
+        }
+    }
+
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<Option<E>> {
+        let mut queue = lock(&self.queue);
+        if let Some(event) = queue.events.pop_front() {
+            Poll::Ready(Some(event))
+        } else if queue.closed {
+            Poll::Ready(None)
+        } else {
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<E> futures_core::Stream for EventStream<E> {
+    type Item = E;
+
+    fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<E>> {
+        self.poll_event(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    use futures::{FutureExt, executor::block_on};
+
+    use crate::{
+        ComObject, DryRun, EventInterface, EventSender, Result, ToVariant, com_events, com_object,
+        com_shim,
+        test_support::{EventSource, raise},
+    };
+
+    com_events! {
+        trait SessionEvents("{2B3A5F0E-7C1D-4E8B-9A6F-1D2C3B4A5E6F}") {
+            fn Change(String, i32) = 1001,
+            fn Größe(i32),
+            fn Destroy(),
+        }
+    }
+
+    struct Log(Rc<RefCell<Vec<String>>>);
+
+    impl SessionEvents for Log {
+        fn change(&self, id: String, count: i32) -> Result<()> {
+            self.0
+                .borrow_mut()
+                .push(format!("{id} changed {count} times"));
+            Ok(())
+        }
+
+        fn größe(&self, size: i32) -> Result<()> {
+            self.0.borrow_mut().push(format!("resized to {size}"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handlers_receive_events_until_disconnected() -> Result<()> {
+        let (session, sinks) = EventSource::create(<dyn SessionEvents as EventInterface<Log>>::IID);
+        let log = Rc::new(RefCell::new(vec![]));
+        let connection = Log(log.clone()).advise(&session)?;
+
+        raise(&sinks, "Change", &["wnd[0]".to_variant(), 2.to_variant()])?;
+        raise(&sinks, "Destroy", &[])?;
+        assert_eq!(*log.borrow(), ["wnd[0] changed 2 times"]);
+
+        drop(connection);
+        assert!(sinks.borrow()[0].is_none());
+        raise(&sinks, "Change", &["wnd[0]".to_variant(), 3.to_variant()])?;
+        assert_eq!(log.borrow().len(), 1);
+        Ok(())
+    }
+
+    /// A counter, which a handler adds the counts of changes to.
+    #[derive(Default)]
+    struct Counter(Cell<i32>);
+
+    com_object! {
+        impl Counter {
+            #[com(get)]
+            fn count(&self) -> i32 {
+                self.0.get()
+            }
+
+            #[com(method)]
+            fn add(&self, amount: i32) {
+                self.0.set(self.0.get() + amount);
+            }
+        }
+    }
+
+    com_shim! {
+        struct CounterClass {
+            Count: i32,
+            fn Add(i32),
+        }
+    }
+
+    struct Tally(CounterClass);
+
+    impl SessionEvents for Tally {
+        fn change(&self, _: String, count: i32) -> Result<()> {
+            self.0.add(count)
+        }
+    }
+
+    #[test]
+    fn handlers_are_not_part_of_the_call_raising_the_event() -> Result<()> {
+        let (session, sinks) =
+            EventSource::create(<dyn SessionEvents as EventInterface<Tally>>::IID);
+        let counter = Counter::default().into_idispatch();
+        let _connection = Tally(CounterClass::from(counter.clone())).advise(&session)?;
+
+        // The handler's call is made, not recorded as a change by the call raising the event
+        let dry_run = DryRun::new();
+        dry_run.scope(|| raise(&sinks, "Change", &["wnd[0]".to_variant(), 2.to_variant()]))?;
+        assert!(dry_run.changes().is_empty());
+        assert_eq!(CounterClass::from(counter).count()?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn events_are_found_ignoring_case() -> Result<()> {
+        let (session, sinks) = EventSource::create(<dyn SessionEvents as EventInterface<Log>>::IID);
+        let log = Rc::new(RefCell::new(vec![]));
+        let _connection = Log(log.clone()).advise(&session)?;
+
+        raise(&sinks, "CHANGE", &["wnd[0]".to_variant(), 1.to_variant()])?;
+        raise(&sinks, "GRÖßE", &[3.to_variant()])?;
+        assert_eq!(*log.borrow(), ["wnd[0] changed 1 times", "resized to 3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn streams_drop_the_oldest_events() -> Result<()> {
+        let iid = <dyn SessionEvents as EventInterface<EventSender<SessionEvent>>>::IID;
+        let (session, sinks) = EventSource::create(iid);
+        let mut events = SessionEvent::stream(&session, 2)?;
+        assert!(events.next().now_or_never().is_none());
+
+        raise(&sinks, "Change", &["wnd[0]".to_variant(), 1.to_variant()])?;
+        raise(&sinks, "Change", &["wnd[0]".to_variant(), 2.to_variant()])?;
+        raise(&sinks, "Destroy", &[])?;
+        assert_eq!(events.dropped(), 1);
+        let Some(SessionEvent::Change(id, count)) = events.try_next() else {
+            panic!("expected a change");
+        };
+        assert_eq!((id.as_str(), count), ("wnd[0]", 2));
+        assert!(matches!(
+            block_on(events.next()),
+            Some(SessionEvent::Destroy)
+        ));
+        assert!(events.try_next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn closed_streams_end_once_empty() -> Result<()> {
+        let iid = <dyn SessionEvents as EventInterface<EventSender<SessionEvent>>>::IID;
+        let (session, sinks) = EventSource::create(iid);
+        let mut events = SessionEvent::stream(&session, 2)?;
+
+        raise(&sinks, "Größe", &[4.to_variant()])?;
+        events.close()?;
+        assert!(sinks.borrow()[0].is_none());
+        assert!(matches!(
+            block_on(events.next()),
+            Some(SessionEvent::Größe(4))
+        ));
+        assert!(block_on(events.next()).is_none());
+        Ok(())
+    }
+}
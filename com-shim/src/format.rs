@@ -0,0 +1,1100 @@
+//! Formatting of variants with Visual Basic format strings, as `Format` and `VarFormat` do.
+
+use windows::{
+    Win32::{
+        Foundation::DISP_E_TYPEMISMATCH,
+        System::Variant::{
+            VARENUM, VARIANT, VT_BOOL, VT_BSTR, VT_BYREF, VT_CY, VT_DATE, VT_DECIMAL, VT_EMPTY,
+            VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_NULL, VT_R4, VT_R8, VT_UI1, VT_UI2, VT_UI4,
+            VT_UI8, VT_UINT,
+        },
+    },
+    core::Result,
+};
+
+use crate::{Currency, Decimal, OleDate, variant};
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1_000;
+
+/// The separators, names and standard formats of a locale, used by [`format_variant`].
+///
+/// Separators and patterns are given as they are written in format strings, so the standard
+/// formats use `/` and `:` for the [`date_separator`](Locale::date_separator) and
+/// [`time_separator`](Locale::time_separator).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locale {
+    /// Written for `.` in numbers.
+    pub decimal_separator: char,
+    /// Written between groups of thousands for `,` in numbers.
+    pub thousands_separator: char,
+    /// Written for `/` in dates.
+    pub date_separator: char,
+    /// Written for `:` in times.
+    pub time_separator: char,
+    /// Written for `AMPM` before noon.
+    pub am: &'static str,
+    /// Written for `AMPM` after noon.
+    pub pm: &'static str,
+    /// The names of the months, from January, abbreviated to their first three letters by `mmm`.
+    pub months: [&'static str; 12],
+    /// The names of the days of the week, from Sunday, abbreviated to their first three letters
+    /// by `ddd`.
+    pub days: [&'static str; 7],
+    /// The format of `Short Date` and `ddddd`.
+    pub short_date: &'static str,
+    /// The format of `Long Date` and `dddddd`.
+    pub long_date: &'static str,
+    /// The format of `Long Time` and `ttttt`.
+    pub long_time: &'static str,
+    /// The format of `Currency`.
+    pub currency: &'static str,
+}
+
+impl Locale {
+    /// English, as used in the United States.
+    pub const EN_US: Locale = Locale {
+        decimal_separator: '.',
+        thousands_separator: ',',
+        date_separator: '/',
+        time_separator: ':',
+        am: "AM",
+        pm: "PM",
+        months: [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ],
+        days: [
+            "Sunday",
+            "Monday",
+            "Tuesday",
+            "Wednesday",
+            "Thursday",
+            "Friday",
+            "Saturday",
+        ],
+        short_date: "m/d/yyyy",
+        long_date: "dddd, mmmm d, yyyy",
+        long_time: "h:mm:ss AMPM",
+        currency: "$#,##0.00;($#,##0.00)",
+    };
+
+    /// German, as used in Germany.
+    pub const DE_DE: Locale = Locale {
+        decimal_separator: ',',
+        thousands_separator: '.',
+        date_separator: '.',
+        time_separator: ':',
+        am: "",
+        pm: "",
+        months: [
+            "Januar",
+            "Februar",
+            "März",
+            "April",
+            "Mai",
+            "Juni",
+            "Juli",
+            "August",
+            "September",
+            "Oktober",
+            "November",
+            "Dezember",
+        ],
+        days: [
+            "Sonntag",
+            "Montag",
+            "Dienstag",
+            "Mittwoch",
+            "Donnerstag",
+            "Freitag",
+            "Samstag",
+        ],
+        short_date: "dd/mm/yyyy",
+        long_date: "dddd, d. mmmm yyyy",
+        long_time: "hh:mm:ss",
+        currency: "#,##0.00 €;-#,##0.00 €",
+    };
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::EN_US
+    }
+}
+
+/// Format a value as the Visual Basic `Format` function does.
+///
+/// The format can be one of the named formats, or a user-defined format:
+///
+/// - Numbers are written with `0` for a digit or zero, `#` for a digit or nothing, `.` for the
+///   decimal separator, `,` for the thousands separator or, just before the decimal point, to
+///   divide by 1000, `%` to multiply by 100, and `E+`, `E-`, `e+` or `e-` for an exponent. Up to
+///   four sections separated by `;` give the formats of positive numbers, negative numbers, zero
+///   and `Null`.
+/// - Dates are written with `d`, `dd`, `ddd` and `dddd` for the day, `w` and `ww` for the day of
+///   the week and week of the year, `m`, `mm`, `mmm` and `mmmm` for the month, `q` for the
+///   quarter, `y`, `yy` and `yyyy` for the day of the year and year, `h` and `hh` for the hour,
+///   `n`, `nn` or, after an hour, `m` and `mm` for the minute, `s` and `ss` for the second,
+///   `AM/PM`, `am/pm`, `A/P`, `a/p` or `AMPM` for a 12-hour clock, `c`, `ddddd`, `dddddd` and
+///   `ttttt` for the locale's standard formats, and `/` and `:` for the date and time
+///   separators.
+/// - Strings are written with `@` for a character or space, `&` for a character or nothing,
+///   `<` or `>` to write them in lower or upper case, and `!` to fill placeholders from left to
+///   right. A second section gives the format of empty strings and `Null`.
+/// - Text in `"` quotes, or a character after `\`, is written as it is.
+///
+/// Numbers are formatted with dates formats as dates, and strings that hold a number or date are
+/// formatted with number and date formats as one. Floating point numbers are rounded to 15
+/// significant digits, or 7 for a `Single`, and then to the digits of the format, half away
+/// from zero.
+///
+/// ```rust
+/// use com_shim::{Locale, OleDate, ToVariant, format_variant};
+///
+/// let date = OleDate::from_ymd_hms_milli(2024, 2, 29, 13, 5, 9, 0).unwrap().to_variant();
+/// let number = 1234.567.to_variant();
+/// assert_eq!(format_variant(&number, "#,##0.00", &Locale::EN_US)?, "1,234.57");
+/// assert_eq!(format_variant(&number, "#,##0.00", &Locale::DE_DE)?, "1.234,57");
+/// let long_date = format_variant(&date, "dddd, mmmm d, yyyy", &Locale::EN_US)?;
+/// assert_eq!(long_date, "Thursday, February 29, 2024");
+/// assert_eq!(format_variant(&date, "h:nn AM/PM", &Locale::EN_US)?, "1:05 PM");
+/// assert_eq!(format_variant(&"ab".to_variant(), ">@@@@", &Locale::EN_US)?, "  AB");
+/// # Ok::<_, windows::core::Error>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if the value is not a number, date, string, `Empty` or `Null`, or a number is
+/// formatted as a date outside of the range of [`OleDate`].
+pub fn format_variant(value: &VARIANT, format: &str, locale: &Locale) -> Result<String> {
+    let value = read(value)?;
+    if let Some(text) = named(&value, format, locale)? {
+        return Ok(text);
+    }
+    let sections = sections(format);
+    let Some(first) = sections.first() else {
+        return Ok(general(&value, locale));
+    };
+
+    Ok(match (style(first), value) {
+        (_, Value::Text(text)) if text.is_empty() || style(first) == Style::Text => {
+            format_text(&text, &sections)
+        }
+        (Style::Text, Value::Null) => format_text("", &sections),
+        (_, Value::Null) => sections.get(3).map_or_else(String::new, |section| {
+            format_number(&Numeral::zero(), section, locale)
+        }),
+        (Style::Text, value) => format_text(&general(&value, locale), &sections),
+        (Style::Date, value) => match date(&value)? {
+            Some(date) => format_date(date, first, locale),
+            None => general(&value, locale),
+        },
+        (Style::Number, value) => match number(&value)? {
+            Some(number) => format_numbers(number, &sections, locale),
+            None => general(&value, locale),
+        },
+    })
+}
+
+/// A value read from a variant.
+enum Value {
+    Null,
+    Empty,
+    Boolean(bool),
+    Number(Numeral),
+    Date(OleDate),
+    Text(String),
+}
+
+fn read(source: &VARIANT) -> Result<Value> {
+    // SAFETY: `vt` is valid for every variant.
+    let vt = unsafe { source.Anonymous.Anonymous.vt };
+    if vt.0 & VT_BYREF.0 != 0 {
+        let value = variant::change_type(source, VARENUM(vt.0 & !VT_BYREF.0))?;
+        return read(&value);
+    }
+    // SAFETY: each field is only read for its type.
+    unsafe {
+        let value = &source.Anonymous.Anonymous.Anonymous;
+        Ok(match vt {
+            VT_NULL => Value::Null,
+            VT_EMPTY => Value::Empty,
+            VT_BOOL => Value::Boolean(value.boolVal.as_bool()),
+            VT_I1 => Value::Number(Numeral::exact(value.cVal.cast_signed().into(), 0)),
+            VT_I2 => Value::Number(Numeral::exact(value.iVal.into(), 0)),
+            VT_I4 => Value::Number(Numeral::exact(value.lVal.into(), 0)),
+            VT_I8 => Value::Number(Numeral::exact(value.llVal.into(), 0)),
+            VT_UI1 => Value::Number(Numeral::exact(value.bVal.into(), 0)),
+            VT_UI2 => Value::Number(Numeral::exact(value.uiVal.into(), 0)),
+            VT_UI4 => Value::Number(Numeral::exact(value.ulVal.into(), 0)),
+            VT_UI8 => Value::Number(Numeral::exact(value.ullVal.into(), 0)),
+            VT_INT => Value::Number(Numeral::exact(value.intVal.into(), 0)),
+            VT_UINT => Value::Number(Numeral::exact(value.uintVal.into(), 0)),
+            VT_R4 => Value::Number(Numeral::float(value.fltVal.into(), 7)),
+            VT_R8 => Value::Number(Numeral::float(value.dblVal, 15)),
+            VT_CY => {
+                let value = Currency::from(value.cyVal).scaled();
+                Value::Number(Numeral::exact(value.into(), 4))
+            }
+            VT_DECIMAL => {
+                let value = Decimal::try_from(source.Anonymous.decVal)?;
+                Value::Number(Numeral::exact(value.mantissa(), value.scale().into()))
+            }
+            VT_DATE => Value::Date(OleDate::try_from(value.date)?),
+            VT_BSTR => Value::Text(value.bstrVal.to_string()),
+            _ => return Err(DISP_E_TYPEMISMATCH.into()),
+        })
+    }
+}
+
+/// Read a value as a number, or `None` if it is a string that does not hold one.
+fn number(value: &Value) -> Result<Option<Numeral>> {
+    Ok(match value {
+        Value::Null | Value::Empty => Some(Numeral::zero()),
+        Value::Boolean(value) => Some(Numeral::exact(-i128::from(*value), 0)),
+        Value::Number(number) => Some(number.clone()),
+        Value::Date(date) => Some(Numeral::float(f64::from(*date), 15)),
+        Value::Text(text) => {
+            let text = crate::ToVariant::to_variant(text.as_str());
+            variant::change_type(&text, VT_DECIMAL)
+                .ok()
+                .map(|value| read(&value))
+                .transpose()?
+                .and_then(|value| match value {
+                    Value::Number(number) => Some(number),
+                    _ => None,
+                })
+        }
+    })
+}
+
+/// Read a value as a date, or `None` if it is a string that does not hold one.
+fn date(value: &Value) -> Result<Option<OleDate>> {
+    Ok(match value {
+        Value::Date(date) => Some(*date),
+        Value::Text(text) => {
+            let text = crate::ToVariant::to_variant(text.as_str());
+            match variant::change_type(&text, VT_DATE) {
+                // SAFETY: the variant has just been converted to a date.
+                Ok(value) => Some(OleDate::try_from(unsafe {
+                    value.Anonymous.Anonymous.Anonymous.date
+                })?),
+                Err(_) => None,
+            }
+        }
+        value => {
+            let number = number(value)?.ok_or(DISP_E_TYPEMISMATCH)?;
+            let value: f64 = number
+                .to_string()
+                .parse()
+                .map_err(|_| DISP_E_TYPEMISMATCH)?;
+            Some(OleDate::try_from(value)?)
+        }
+    })
+}
+
+/// Format a value without a format, as `Format` does.
+fn general(value: &Value, locale: &Locale) -> String {
+    match value {
+        Value::Null | Value::Empty => String::new(),
+        Value::Boolean(value) => if *value { "True" } else { "False" }.to_string(),
+        Value::Number(number) => number.general(locale),
+        Value::Date(date) => general_date(*date, locale),
+        Value::Text(text) => text.clone(),
+    }
+}
+
+/// Format a value with a named format, if the format is one.
+fn named(value: &Value, format: &str, locale: &Locale) -> Result<Option<String>> {
+    let words = |yes: &str, no: &str| -> Result<Option<String>> {
+        Ok(number(value)?.map(|number| if number.is_zero() { no } else { yes }.to_string()))
+    };
+    let numbers = |format: &str| -> Result<Option<String>> {
+        Ok(number(value)?.map(|number| format_numbers(number, &sections(format), locale)))
+    };
+    let dates = |format: &str| -> Result<Option<String>> {
+        Ok(date(value)?.map(|date| format_date(date, format, locale)))
+    };
+    if matches!(value, Value::Null) {
+        return Ok(None);
+    }
+    match format.to_ascii_lowercase().as_str() {
+        "general number" => Ok(number(value)?.map(|number| number.general(locale))),
+        "currency" => numbers(locale.currency),
+        "fixed" => numbers("0.00"),
+        "standard" => numbers("#,##0.00"),
+        "percent" => numbers("0.00%"),
+        "scientific" => numbers("0.00E+00"),
+        "yes/no" => words("Yes", "No"),
+        "true/false" => words("True", "False"),
+        "on/off" => words("On", "Off"),
+        "general date" => Ok(date(value)?.map(|date| general_date(date, locale))),
+        "long date" => dates(locale.long_date),
+        "medium date" => dates("dd-mmm-yy"),
+        "short date" => dates(locale.short_date),
+        "long time" => dates(locale.long_time),
+        "medium time" => dates("hh:mm AMPM"),
+        "short time" => dates("hh:mm"),
+        _ => Ok(None),
+    }
+}
+
+/// Split a format into its sections, which are separated by `;`.
+fn sections(format: &str) -> Vec<&str> {
+    if format.is_empty() {
+        return Vec::new();
+    }
+    let mut sections = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (index, c) in format.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if !quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                sections.push(&format[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    sections.push(&format[start..]);
+    sections
+}
+
+/// Split a format section into literal text and the characters of the format.
+fn tokens(section: &str) -> Vec<(char, bool)> {
+    let mut tokens = Vec::new();
+    let mut chars = section.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => tokens.extend(chars.next().map(|c| (c, true))),
+            '"' => tokens.extend(chars.by_ref().take_while(|&c| c != '"').map(|c| (c, true))),
+            c => tokens.push((c, false)),
+        }
+    }
+    tokens
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Number,
+    Date,
+    Text,
+}
+
+/// Whether a format section is for numbers, dates or strings.
+fn style(section: &str) -> Style {
+    let tokens = tokens(section);
+    let has = |chars: &str| {
+        tokens
+            .iter()
+            .any(|&(c, literal)| !literal && chars.contains(c.to_ascii_lowercase()))
+    };
+    if has("0#") {
+        Style::Number
+    } else if has("@&<>!") {
+        Style::Text
+    } else if has("dmyhnswqct") {
+        Style::Date
+    } else {
+        Style::Number
+    }
+}
+
+/// A decimal number, `0.digits * 10^exponent`, without leading or trailing zeros.
+#[derive(Clone, Debug)]
+struct Numeral {
+    negative: bool,
+    digits: Vec<u8>,
+    exponent: i32,
+}
+
+impl Numeral {
+    fn zero() -> Self {
+        Self {
+            negative: false,
+            digits: Vec::new(),
+            exponent: 0,
+        }
+    }
+
+    /// The digits of `mantissa / 10^scale`.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)] // at most 40 digits
+    fn exact(mantissa: i128, scale: u32) -> Self {
+        let digits = mantissa.unsigned_abs().to_string().into_bytes();
+        let exponent = digits.len() as i32 - scale.cast_signed();
+        Self::new(mantissa < 0, digits, exponent)
+    }
+
+    /// The digits of a floating point value, to `precision` significant digits.
+    fn float(value: f64, precision: usize) -> Self {
+        if !value.is_finite() {
+            return Self::zero();
+        }
+        let text = format!("{:.*e}", precision - 1, value.abs());
+        let (mantissa, exponent) = text.split_once('e').expect("written with an exponent");
+        let digits = mantissa.bytes().filter(u8::is_ascii_digit).collect();
+        let exponent: i32 = exponent.parse().expect("the exponent is an integer");
+        Self::new(value.is_sign_negative(), digits, exponent + 1)
+    }
+
+    fn new(negative: bool, mut digits: Vec<u8>, mut exponent: i32) -> Self {
+        let leading = digits.iter().take_while(|&&d| d == b'0').count();
+        digits.drain(..leading);
+        exponent -= i32::try_from(leading).expect("at most 40 digits");
+        let mut number = Self {
+            negative,
+            digits,
+            exponent,
+        };
+        number.trim();
+        number
+    }
+
+    fn trim(&mut self) {
+        while self.digits.last() == Some(&b'0') {
+            self.digits.pop();
+        }
+        if self.digits.is_empty() {
+            self.exponent = 0;
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// Round to `places` decimal places, half away from zero.
+    fn round(&mut self, places: i32) {
+        let keep = self.exponent + places;
+        match usize::try_from(keep) {
+            Err(_) => *self = Self::zero(),
+            Ok(keep) => self.round_to(keep),
+        }
+    }
+
+    /// Round to the first `keep` digits, half away from zero.
+    fn round_to(&mut self, keep: usize) {
+        if keep >= self.digits.len() {
+            return;
+        }
+        let up = self.digits[keep] >= b'5';
+        self.digits.truncate(keep);
+        if up {
+            loop {
+                match self.digits.last_mut() {
+                    Some(b'9') => {
+                        self.digits.pop();
+                    }
+                    Some(digit) => {
+                        *digit += 1;
+                        break;
+                    }
+                    None => {
+                        self.digits.push(b'1');
+                        self.exponent += 1;
+                        break;
+                    }
+                }
+            }
+        }
+        self.trim();
+    }
+
+    /// The digits before the decimal point, or nothing if there are none.
+    fn integer(&self) -> String {
+        let Ok(count) = usize::try_from(self.exponent) else {
+            return String::new();
+        };
+        let mut integer: String = self
+            .digits
+            .iter()
+            .take(count)
+            .map(|&d| char::from(d))
+            .collect();
+        while integer.len() < count {
+            integer.push('0');
+        }
+        integer
+    }
+
+    /// The first `places` digits after the decimal point.
+    fn fraction(&self, places: usize) -> String {
+        let zeros = usize::try_from(-self.exponent).unwrap_or(0);
+        let skip = usize::try_from(self.exponent).unwrap_or(0);
+        let digits = self.digits.iter().skip(skip).map(|&d| char::from(d));
+        std::iter::repeat_n('0', zeros)
+            .chain(digits)
+            .chain(std::iter::repeat('0'))
+            .take(places)
+            .collect()
+    }
+
+    /// Write the number with as many digits as needed, in scientific notation if it is very
+    /// large or small.
+    fn general(&self, locale: &Locale) -> String {
+        let sign = if self.negative && !self.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        let decimal = self.exponent - 1;
+        if self.is_zero() {
+            "0".to_string()
+        } else if !(-5..15).contains(&decimal) {
+            let mantissa = Numeral {
+                negative: false,
+                exponent: 1,
+                ..self.clone()
+            };
+            let mut text = format!("{sign}{}", mantissa.integer());
+            if self.digits.len() > 1 {
+                text.push(locale.decimal_separator);
+                text.push_str(&mantissa.fraction(self.digits.len() - 1));
+            }
+            let exponent_sign = if decimal < 0 { '-' } else { '+' };
+            format!("{text}E{exponent_sign}{:02}", decimal.unsigned_abs())
+        } else {
+            let length = i32::try_from(self.digits.len()).expect("at most 40 digits");
+            let places = usize::try_from(length - self.exponent).unwrap_or(0);
+            let integer = self.integer();
+            let mut text = format!("{sign}{}", if integer.is_empty() { "0" } else { &integer });
+            if places > 0 {
+                text.push(locale.decimal_separator);
+                text.push_str(&self.fraction(places));
+            }
+            text
+        }
+    }
+}
+
+impl std::fmt::Display for Numeral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.negative { "-" } else { "" };
+        let digits: String = self.digits.iter().map(|&d| char::from(d)).collect();
+        write!(f, "{sign}0.{digits}e{}", self.exponent)
+    }
+}
+
+/// Format a number with the sections of a number format.
+fn format_numbers(number: Numeral, sections: &[&str], locale: &Locale) -> String {
+    let section = |index: usize| sections.get(index).filter(|section| !section.is_empty());
+    let negative = number.negative && !number.is_zero();
+    let absolute = Numeral {
+        negative: false,
+        ..number
+    };
+    if absolute.is_zero()
+        && let Some(section) = section(2)
+    {
+        format_number(&absolute, section, locale)
+    } else if negative && let Some(section) = section(1) {
+        format_number(&absolute, section, locale)
+    } else {
+        let text = format_number(&absolute, sections.first().unwrap_or(&""), locale);
+        if negative { format!("-{text}") } else { text }
+    }
+}
+
+/// A part of a number format.
+#[derive(Clone, PartialEq, Eq)]
+enum Part {
+    Literal(char),
+    Zero,
+    Hash,
+    Point,
+    Comma,
+    Percent,
+    Exponent(char, bool),
+}
+
+/// Format a positive number with a section of a number format.
+#[allow(clippy::too_many_lines)]
+fn format_number(number: &Numeral, section: &str, locale: &Locale) -> String {
+    let mut parts = Vec::new();
+    let mut tokens = tokens(section).into_iter().peekable();
+    while let Some((c, literal)) = tokens.next() {
+        let has_digits = parts.iter().any(|p| matches!(p, Part::Zero | Part::Hash));
+        parts.push(match c {
+            _ if literal => Part::Literal(c),
+            '0' => Part::Zero,
+            '#' => Part::Hash,
+            '.' if !parts.contains(&Part::Point) => Part::Point,
+            ',' => Part::Comma,
+            '%' => Part::Percent,
+            'E' | 'e' if has_digits && matches!(tokens.peek(), Some(('+' | '-', false))) => {
+                let plus = tokens.next().is_some_and(|(sign, _)| sign == '+');
+                Part::Exponent(c, plus)
+            }
+            c => Part::Literal(c),
+        });
+    }
+
+    let is_digit = |part: &Part| matches!(part, Part::Zero | Part::Hash);
+    let exponent_at = parts.iter().position(|p| matches!(p, Part::Exponent(..)));
+    let mantissa = &parts[..exponent_at.unwrap_or(parts.len())];
+    let point_at = mantissa.iter().position(|p| *p == Part::Point);
+    let integer_parts = &mantissa[..point_at.unwrap_or(mantissa.len())];
+    let fraction_parts = point_at.map_or(&[][..], |point| &mantissa[point + 1..]);
+
+    // Commas between digits group thousands, and commas after the last digit divide by 1000
+    let mut grouped = false;
+    let mut number = number.clone();
+    for (index, part) in integer_parts.iter().enumerate() {
+        if *part == Part::Comma {
+            if integer_parts[index..].iter().any(is_digit) {
+                grouped = true;
+            } else if integer_parts[..index].iter().any(is_digit) && !number.is_zero() {
+                number.exponent -= 3;
+            }
+        }
+    }
+    if !number.is_zero() {
+        let percents = mantissa.iter().filter(|p| **p == Part::Percent).count();
+        number.exponent += 2 * i32::try_from(percents).expect("a format is short");
+    }
+
+    let integer_digits: Vec<&Part> = integer_parts.iter().filter(|p| is_digit(p)).collect();
+    let fraction_digits: Vec<&Part> = fraction_parts.iter().filter(|p| is_digit(p)).collect();
+    let integer_count = integer_digits.len();
+    let integer_min = integer_digits
+        .iter()
+        .position(|p| **p == Part::Zero)
+        .map_or(0, |first| integer_count - first);
+    let fraction_count = fraction_digits.len();
+    let fraction_min = fraction_digits
+        .iter()
+        .rposition(|p| **p == Part::Zero)
+        .map_or(0, |last| last + 1);
+    let places = i32::try_from(fraction_count).expect("a format is short");
+
+    // The exponent is chosen so that there are as many digits as placeholders before the point
+    let mut exponent = None;
+    if exponent_at.is_some() {
+        let before = i32::try_from(integer_count.max(1)).expect("a format is short");
+        let significant = usize::try_from(before + places).expect("both are positive");
+        number.round_to(significant);
+        if number.is_zero() {
+            exponent = Some(0);
+        } else {
+            exponent = Some(number.exponent - before);
+            number.exponent = before;
+        }
+    } else {
+        number.round(places);
+    }
+
+    let mut integer = number.integer();
+    while integer.len() < integer_min {
+        integer.insert(0, '0');
+    }
+    let mut fraction = number.fraction(fraction_count);
+    while fraction.len() > fraction_min && fraction.ends_with('0') {
+        fraction.pop();
+    }
+
+    let integer: Vec<char> = integer.chars().collect();
+    let mut text = String::new();
+    let write_digit = |text: &mut String, index: usize| {
+        text.push(integer[index]);
+        let from_right = integer.len() - 1 - index;
+        if grouped && from_right > 0 && from_right.is_multiple_of(3) {
+            text.push(locale.thousands_separator);
+        }
+    };
+    // Numeral are aligned to the right, with any that don't fit written by the first placeholder
+    let offset = integer.len().cast_signed() - integer_count.cast_signed();
+    let mut placeholder = 0;
+    let mut fraction = fraction.chars();
+    let mut in_fraction = false;
+    let mut in_exponent = false;
+    for part in &parts {
+        match part {
+            Part::Literal(c) => text.push(*c),
+            Part::Percent => text.push('%'),
+            Part::Comma => {}
+            _ if in_exponent => {
+                // Every digit of the exponent is written by its first placeholder
+                if let Some(exponent) = exponent.take() {
+                    let zeros = parts[exponent_at.unwrap_or(0)..]
+                        .iter()
+                        .filter(|p| **p == Part::Zero)
+                        .count();
+                    let digits = exponent.unsigned_abs().to_string();
+                    text.extend(std::iter::repeat_n('0', zeros.saturating_sub(digits.len())));
+                    text.push_str(&digits);
+                }
+            }
+            Part::Zero | Part::Hash if in_fraction => text.extend(fraction.next()),
+            Part::Zero | Part::Hash => {
+                let index = placeholder + offset;
+                let from = if placeholder == 0 { 0 } else { index };
+                for index in from.max(0)..=index {
+                    write_digit(&mut text, index.cast_unsigned());
+                }
+                placeholder += 1;
+            }
+            Part::Point => {
+                if integer_count == 0 {
+                    (0..integer.len()).for_each(|index| write_digit(&mut text, index));
+                }
+                text.push(locale.decimal_separator);
+                in_fraction = true;
+            }
+            Part::Exponent(e, plus) => {
+                let exponent = exponent.unwrap_or(0);
+                text.push(*e);
+                if exponent < 0 {
+                    text.push('-');
+                } else if *plus {
+                    text.push('+');
+                }
+                in_exponent = true;
+            }
+        }
+    }
+    if integer_count == 0 && point_at.is_none() {
+        (0..integer.len()).for_each(|index| write_digit(&mut text, index));
+    }
+    text
+}
+
+/// Format a string with the sections of a string format.
+fn format_text(text: &str, sections: &[&str]) -> String {
+    let section = match sections.get(1) {
+        Some(section) if text.is_empty() => section,
+        _ => sections.first().unwrap_or(&""),
+    };
+    let tokens = tokens(section);
+    let has = |c| tokens.contains(&(c, false));
+    let text: Vec<char> = if has('<') {
+        text.to_lowercase().chars().collect()
+    } else if has('>') {
+        text.to_uppercase().chars().collect()
+    } else {
+        text.chars().collect()
+    };
+    let is_placeholder = |&(c, literal): &(char, bool)| !literal && (c == '@' || c == '&');
+    let count = tokens.iter().filter(|t| is_placeholder(t)).count();
+    if count == 0 {
+        let mut written: String = tokens
+            .iter()
+            .filter(|&&(c, literal)| literal || !"<>!".contains(c))
+            .map(|&(c, _)| c)
+            .collect();
+        written.extend(&text);
+        return written;
+    }
+
+    // Characters fill placeholders from the right, unless `!` is given
+    let left_to_right = has('!');
+    let offset = if left_to_right {
+        0
+    } else {
+        text.len().cast_signed() - count.cast_signed()
+    };
+    let mut written = String::new();
+    let mut placeholder = 0;
+    for token @ (c, literal) in tokens {
+        if is_placeholder(&token) {
+            let index = placeholder + offset;
+            let from = if placeholder == 0 && !left_to_right {
+                index.min(0)
+            } else {
+                index
+            };
+            for index in from..=index {
+                match text.get(index.cast_unsigned()) {
+                    Some(&c) if index >= 0 => written.push(c),
+                    _ if c == '@' => written.push(' '),
+                    _ => {}
+                }
+            }
+            placeholder += 1;
+            if left_to_right && placeholder.cast_unsigned() == count {
+                written.extend(text.iter().skip(count));
+            }
+        } else if literal || !"<>!".contains(c) {
+            written.push(c);
+        }
+    }
+    written
+}
+
+/// Format a date as the locale's short date and long time, leaving out the date on day zero and
+/// the time at midnight.
+fn general_date(date: OleDate, locale: &Locale) -> String {
+    let date = round_to_second(date);
+    let (hour, minute, second, _) = date.hms_milli();
+    if date.ymd() == (1899, 12, 30) {
+        format_date(date, locale.long_time, locale)
+    } else if (hour, minute, second) == (0, 0, 0) {
+        format_date(date, locale.short_date, locale)
+    } else {
+        let format = format!("{} {}", locale.short_date, locale.long_time);
+        format_date(date, &format, locale)
+    }
+}
+
+fn round_to_second(date: OleDate) -> OleDate {
+    let millis = date.millis_since_epoch();
+    OleDate::from_millis_since_epoch((millis + 500).div_euclid(1000) * 1000).unwrap_or(date)
+}
+
+/// Format a date with a section of a date format.
+#[allow(clippy::too_many_lines)]
+fn format_date(date: OleDate, section: &str, locale: &Locale) -> String {
+    let date = round_to_second(date);
+    let (year, month, day) = date.ymd();
+    let (hour, minute, second, _) = date.hms_milli();
+    let days = date.millis_since_epoch().div_euclid(MILLIS_PER_DAY);
+    // The epoch was a Saturday
+    let weekday = (days + 6).rem_euclid(7);
+    let first_day = OleDate::from_ymd(year, 1, 1).expect("the year is in range");
+    let day_of_year = days - first_day.millis_since_epoch().div_euclid(MILLIS_PER_DAY) + 1;
+    // Weeks start on Sunday, and the first is the one with the 1st of January
+    let first_weekday = (weekday - (day_of_year - 1)).rem_euclid(7);
+    let week = (day_of_year - 1 + first_weekday) / 7 + 1;
+
+    // Split the format into runs of the same letter, and literal text
+    let tokens = tokens(section);
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let (c, literal) = tokens[index];
+        let rest: String = tokens[index..]
+            .iter()
+            .take_while(|(_, literal)| !literal)
+            .map(|(c, _)| *c)
+            .collect();
+        let lower = rest.to_ascii_lowercase();
+        let length = if literal {
+            1
+        } else if lower.starts_with("am/pm") {
+            5
+        } else if lower.starts_with("a/p") {
+            3
+        } else if lower.starts_with("ampm") {
+            4
+        } else if "dwmqyhnst".contains(c.to_ascii_lowercase()) {
+            lower
+                .chars()
+                .take_while(|&next| next == c.to_ascii_lowercase())
+                .count()
+        } else {
+            1
+        };
+        let run = tokens[index..index + length]
+            .iter()
+            .map(|(c, _)| *c)
+            .collect();
+        runs.push((run, literal));
+        index += length;
+    }
+    let is_clock = |run: &str| {
+        let run = run.to_ascii_lowercase();
+        run == "am/pm" || run == "a/p" || run == "ampm"
+    };
+    let twelve_hour = runs.iter().any(|(run, literal)| !literal && is_clock(run));
+    let kind = |run: &(String, bool)| {
+        (!run.1)
+            .then(|| run.0.chars().next().map(|c| c.to_ascii_lowercase()))
+            .flatten()
+    };
+
+    let mut text = String::new();
+    for (index, run) in runs.iter().enumerate() {
+        let (token, literal) = run;
+        if *literal {
+            text.push_str(token);
+            continue;
+        }
+        let lower = token.to_ascii_lowercase();
+        // `m` is the minute after an hour or before a second
+        let previous = runs[..index]
+            .iter()
+            .rev()
+            .find_map(|run| kind(run).filter(|c| "dwmqyhnst".contains(*c)));
+        let next = runs[index + 1..]
+            .iter()
+            .find_map(|run| kind(run).filter(|c| "dwmqyhnst".contains(*c)));
+        let is_minute = lower.starts_with('m')
+            && lower.len() <= 2
+            && (previous == Some('h') || next == Some('s'));
+        let name = |names: &[&str], index: usize, length: usize| -> String {
+            if length == 3 {
+                names[index].chars().take(3).collect()
+            } else {
+                names[index].to_string()
+            }
+        };
+        let clock = if twelve_hour {
+            (hour + 11) % 12 + 1
+        } else {
+            hour
+        };
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // all small
+        let written = match lower.as_str() {
+            "d" => day.to_string(),
+            "dd" => format!("{day:02}"),
+            "ddd" | "dddd" => name(&locale.days, weekday as usize, lower.len()),
+            "ddddd" => format_date(date, locale.short_date, locale),
+            "dddddd" => format_date(date, locale.long_date, locale),
+            "w" => (weekday + 1).to_string(),
+            "ww" => week.to_string(),
+            "m" if is_minute => minute.to_string(),
+            "mm" if is_minute => format!("{minute:02}"),
+            "m" => month.to_string(),
+            "mm" => format!("{month:02}"),
+            "mmm" | "mmmm" => name(&locale.months, month as usize - 1, lower.len()),
+            "q" => month.div_ceil(3).to_string(),
+            "y" => day_of_year.to_string(),
+            "yy" => format!("{:02}", year.rem_euclid(100)),
+            "yyy" | "yyyy" => year.to_string(),
+            "h" => clock.to_string(),
+            "hh" => format!("{clock:02}"),
+            "n" => minute.to_string(),
+            "nn" => format!("{minute:02}"),
+            "s" => second.to_string(),
+            "ss" => format!("{second:02}"),
+            "ttttt" => format_date(date, locale.long_time, locale),
+            "c" => general_date(date, locale),
+            "ampm" => if hour < 12 { locale.am } else { locale.pm }.to_string(),
+            "am/pm" | "a/p" => {
+                // Written in the case of the format
+                let (am, pm) = token.split_once('/').expect("split by a slash");
+                if hour < 12 { am } else { pm }.to_string()
+            }
+            "/" => locale.date_separator.to_string(),
+            ":" => locale.time_separator.to_string(),
+            _ => token.clone(),
+        };
+        text.push_str(&written);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Foundation::DISP_E_TYPEMISMATCH;
+
+    use super::{Locale, format_variant};
+    use crate::{Currency, Decimal, OleDate, ToVariant, Variant};
+
+    #[test]
+    fn formats() {
+        let date = OleDate::from_ymd_hms_milli(2024, 2, 29, 13, 5, 9, 0).unwrap();
+        let us = Locale::EN_US;
+        let de = Locale::DE_DE;
+        let table = [
+            (1234.567.to_variant(), "#,##0.00", &us, "1,234.57"),
+            (1234.567.to_variant(), "#,##0.00", &de, "1.234,57"),
+            (0.5.to_variant(), "0.00%", &us, "50.00%"),
+            (0.125.to_variant(), "#.##", &us, ".13"),
+            (5.to_variant(), "#.##", &us, "5."),
+            (7.to_variant(), "000", &us, "007"),
+            ((-3.5).to_variant(), "0.0", &us, "-3.5"),
+            ((-3.5).to_variant(), "0.0;(0.0)", &us, "(3.5)"),
+            (0.to_variant(), "0.0;(0.0);\"zero\"", &us, "zero"),
+            (Variant::null(), "0;0;0;\"n/a\"", &us, "n/a"),
+            (12_345_678.to_variant(), "#,##0,", &us, "12,346"),
+            (
+                5_551_234_567_i64.to_variant(),
+                "(###) ###-####",
+                &us,
+                "(555) 123-4567",
+            ),
+            (12345.to_variant(), "0.00E+00", &us, "1.23E+04"),
+            (0.000_12.to_variant(), "0.0e-0", &us, "1.2e-4"),
+            (
+                Currency::from_scaled(-12_345_000).to_variant(),
+                "Currency",
+                &us,
+                "($1,234.50)",
+            ),
+            (
+                Decimal::new(1_234_500, 3).unwrap().to_variant(),
+                "Standard",
+                &de,
+                "1.234,50",
+            ),
+            (2.5.to_variant(), "0", &us, "3"),
+            (1.005.to_variant(), "0.00", &us, "1.01"),
+            (0.1.to_variant(), "General Number", &us, "0.1"),
+            (1e20.to_variant(), "", &us, "1E+20"),
+            (true.to_variant(), "Yes/No", &us, "Yes"),
+            (0.to_variant(), "On/Off", &us, "Off"),
+            (false.to_variant(), "", &us, "False"),
+            (date.to_variant(), "dd.mm.yyyy", &us, "29.02.2024"),
+            (date.to_variant(), "d/m/yy", &de, "29.2.24"),
+            (
+                date.to_variant(),
+                "dddd, mmmm d, yyyy",
+                &us,
+                "Thursday, February 29, 2024",
+            ),
+            (date.to_variant(), "ddd d mmm", &de, "Don 29 Feb"),
+            (date.to_variant(), "hh:mm:ss", &us, "13:05:09"),
+            (date.to_variant(), "h:nn AM/PM", &us, "1:05 PM"),
+            (date.to_variant(), "h:mm a/p", &us, "1:05 p"),
+            (
+                date.to_variant(),
+                "yyyy-mm-dd\"T\"hh:mm",
+                &us,
+                "2024-02-29T13:05",
+            ),
+            (date.to_variant(), "w ww q y", &us, "5 9 1 60"),
+            (date.to_variant(), "", &us, "2/29/2024 1:05:09 PM"),
+            (
+                date.to_variant(),
+                "General Date",
+                &de,
+                "29.02.2024 13:05:09",
+            ),
+            (
+                date.to_variant(),
+                "Long Date",
+                &de,
+                "Donnerstag, 29. Februar 2024",
+            ),
+            (date.to_variant(), "Medium Date", &us, "29-Feb-24"),
+            (date.to_variant(), "Short Time", &us, "13:05"),
+            (date.to_variant(), "Medium Time", &us, "01:05 PM"),
+            (45_351.to_variant(), "dd.mm.yyyy", &us, "29.02.2024"),
+            ("2024-02-29".to_variant(), "mmm yyyy", &us, "Feb 2024"),
+            ("42".to_variant(), "0.0", &us, "42.0"),
+            ("abc".to_variant(), "0.0", &us, "abc"),
+            ("abc".to_variant(), ">", &us, "ABC"),
+            ("ab".to_variant(), "@@@@", &us, "  ab"),
+            ("ab".to_variant(), "!@@@@", &us, "ab  "),
+            ("5551234".to_variant(), "&&&-&&&&", &us, "555-1234"),
+            ("".to_variant(), "@;\"(empty)\"", &us, "(empty)"),
+            (Variant::null(), "", &us, ""),
+        ];
+        for (row, (value, format, locale, expected)) in table.iter().enumerate() {
+            let result = format_variant(value, format, locale).map_err(|e| e.code());
+            assert_eq!(
+                result.as_deref(),
+                Ok(*expected),
+                "row {row}: {value:?} as {format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_values_that_cannot_be_formatted() {
+        let error = format_variant(&vec![1].to_variant(), "0", &Locale::EN_US).unwrap_err();
+        assert_eq!(error.code(), DISP_E_TYPEMISMATCH);
+    }
+}
@@ -2,117 +2,383 @@
 #![warn(clippy::pedantic)]
 #![doc = include_str!("../README.md")]
 
-use std::mem::ManuallyDrop;
+// Off Windows, the Win32 functions that com-shim calls are faked
+#[cfg(test)]
+extern crate com_shim_fake_win32;
+// The macros name this crate, for its own tests
+#[cfg(test)]
+extern crate self as com_shim;
+
+use std::time::Duration;
 
 use windows::{
-    Win32::{
-        Foundation::VARIANT_BOOL,
-        System::{
-            Com::{DISPATCH_METHOD, DISPATCH_PROPERTYGET, DISPATCH_PROPERTYPUT, DISPPARAMS},
-            Variant::{
-                VAR_CHANGE_FLAGS, VARIANT_0_0, VT_BOOL, VT_BSTR, VT_DISPATCH, VT_I2, VT_I4, VT_I8,
-                VT_NULL, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VariantChangeType, VariantClear,
-            },
-        },
+    Win32::System::{
+        Com::{DISPATCH_METHOD, DISPATCH_PROPERTYGET, DISPATCH_PROPERTYPUT, DISPPARAMS},
+        Variant::{VT_DISPATCH, VT_EMPTY, VT_NULL, VT_UNKNOWN},
     },
-    core::{self, BSTR},
+    core,
 };
 
-pub use com_shim_macro::com_shim;
+pub use com_shim_macro::{
+    ComVariant, FromDispatch, FromVariant, ToVariant, com_events, com_object, com_shim,
+};
 
 pub use windows::Win32::System::{Com::IDispatch, Variant::VARIANT};
 pub use windows::core::{GUID, Result};
 
+mod array;
+mod bstr;
+mod cache;
+mod callback;
+#[cfg(any(not(windows), test))]
+mod coerce;
+mod conversion;
+mod convert;
+mod date;
+mod deadline;
+mod decimal;
+mod dry_run;
+mod events;
+mod format;
+mod member;
+mod operators;
+mod retry;
+#[cfg(feature = "serde")]
+mod serialize;
+mod server;
+#[cfg(test)]
+mod test_support;
+mod thread;
 mod utils;
+mod variant;
+mod wait;
+
+pub use array::Array2;
+pub use bstr::BStr;
+pub use cache::CallCache;
+pub use callback::{Callback, CallbackArguments, callback};
+pub use conversion::{Conversion, ConversionError, WithConversion};
+pub use convert::{FromVariant, ToVariant};
+pub use date::{OleDate, OleDateOutOfRange};
+pub use deadline::{CancellationToken, TimeoutError, WithDeadline};
+pub use decimal::{Currency, Decimal, DecimalOutOfRange};
+pub use dry_run::{Change, DryRun, WithDryRun};
+pub use events::{EventConnection, EventInterface, EventSender, EventStream};
+pub use format::{Locale, format_variant};
+pub use member::{Effect, Member, MemberKind};
+pub use operators::StringComparison;
+pub use retry::RetryPolicy;
+pub use server::ComObject;
+pub use thread::{ComThread, Marshal, Pending, Remote};
+pub use variant::Variant;
+
+/// Implementation details of the derive macros.
+#[doc(hidden)]
+pub mod __private {
+    use windows::Win32::{
+        Foundation::{
+            DISP_E_BADPARAMCOUNT, DISP_E_MEMBERNOTFOUND, DISP_E_TYPEMISMATCH, DISP_E_UNKNOWNNAME,
+        },
+        System::Com::{DISPATCH_PROPERTYPUT, DISPATCH_PROPERTYPUTREF},
+    };
+
+    pub use windows::Win32::System::Com::{DISPATCH_FLAGS, DISPPARAMS};
+
+    use crate::{FromVariant, ToVariant, VARIANT, Variant, VariantExt};
+
+    /// A value did not match any variant of a derived enum.
+    #[must_use]
+    pub fn unknown_value() -> windows::core::Error {
+        DISP_E_TYPEMISMATCH.into()
+    }
+
+    /// Run `f` with the conversion chosen for the object a call is made on, if there is one.
+    pub fn object_scope<R>(conversion: Option<crate::Conversion>, f: impl FnOnce() -> R) -> R {
+        crate::conversion::object_scope(conversion, f)
+    }
+
+    /// Find the `DISPID` of a member by name, ignoring case.
+    #[must_use]
+    pub fn find_dispid(members: &[(&str, i32)], name: &str) -> Option<i32> {
+        let lower = |s: &str| s.chars().flat_map(char::to_lowercase).collect::<String>();
+        let name = lower(name);
+        members
+            .iter()
+            .find(|(member, _)| lower(member) == name)
+            .map(|&(_, dispid)| dispid)
+    }
+
+    /// An event or member was raised or invoked with an unknown `DISPID`.
+    #[must_use]
+    pub fn member_not_found() -> windows::core::Error {
+        DISP_E_MEMBERNOTFOUND.into()
+    }
+
+    /// Read an argument of an event or call, which are given in reverse order. Arguments that were
+    /// not given are read from `VT_NULL`, so that they can be optional.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the argument cannot be converted.
+    pub fn argument<T: FromVariant>(params: &DISPPARAMS, index: usize) -> windows::core::Result<T> {
+        let count = params.cArgs as usize;
+        if index >= count || params.rgvarg.is_null() {
+            return T::from_variant(&VARIANT::null());
+        }
+        // SAFETY: `rgvarg` holds `cArgs` arguments.
+        T::from_variant(unsafe { &*params.rgvarg.add(count - 1 - index) })
+    }
 
-/// A component that has an IDispatch value. Every component needs this, and this trait guarantees that.
+    /// Check that a call was not given more than `expected` arguments.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `DISP_E_BADPARAMCOUNT` if there are too many arguments.
+    pub fn expect_arguments(params: &DISPPARAMS, expected: usize) -> windows::core::Result<()> {
+        if params.cArgs as usize > expected {
+            return Err(DISP_E_BADPARAMCOUNT.into());
+        }
+        Ok(())
+    }
+
+    /// Whether a member is being invoked to write a property.
+    #[must_use]
+    pub fn is_put(flags: DISPATCH_FLAGS) -> bool {
+        flags.0 & (DISPATCH_PROPERTYPUT.0 | DISPATCH_PROPERTYPUTREF.0) != 0
+    }
+
+    /// A value returned from a member of an object, which may have failed.
+    pub trait ReturnValue {
+        /// Convert the value into the result of the call.
+        ///
+        /// # Errors
+        ///
+        /// Fails if the member failed.
+        fn into_result(self) -> windows::core::Result<Variant>;
+    }
+
+    impl<T: ToVariant> ReturnValue for T {
+        fn into_result(self) -> windows::core::Result<Variant> {
+            Ok(self.to_variant())
+        }
+    }
+
+    impl<T: ToVariant> ReturnValue for windows::core::Result<T> {
+        fn into_result(self) -> windows::core::Result<Variant> {
+            self.map(|value| value.to_variant())
+        }
+    }
+
+    /// Run `f` as a call to a member declared in `com_shim!`, on an object that may be in a dry
+    /// run, or have a timeout.
+    pub fn call_member<R>(
+        member: crate::Member,
+        dry_run: Option<&crate::DryRun>,
+        deadline: Option<std::time::Duration>,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        member.scope(|| {
+            crate::dry_run::object_scope(dry_run, || crate::deadline::object_scope(deadline, f))
+        })
+    }
+
+    /// Run `f` without retrying the calls it makes, for a member marked `#[com(no_retry)]`.
+    pub fn without_retry<R>(f: impl FnOnce() -> R) -> R {
+        crate::retry::never(f)
+    }
+
+    /// Whether an error was caused by an object not having the requested member.
+    #[must_use]
+    pub fn is_missing_member(error: &windows::core::Error) -> bool {
+        [DISP_E_UNKNOWNNAME, DISP_E_MEMBERNOTFOUND].contains(&error.code())
+    }
+}
+
+/// A component that has an [`IDispatch`] value. Every component needs this, and this trait guarantees that.
 pub trait HasIDispatch<T = Self> {
-    /// Get the IDispatch object for low-level access to this component.
+    /// Get the [`IDispatch`] object for low-level access to this component.
     fn get_idispatch(&self) -> &IDispatch;
+
+    /// The conversion values read from this component are read with, if one was chosen for it.
+    fn conversion(&self) -> Option<Conversion> {
+        None
+    }
+
+    /// The dry run calls to this component are recorded in, if one was chosen for it.
+    fn dry_run(&self) -> Option<&DryRun> {
+        None
+    }
+
+    /// How long calls to this component are waited for, if a timeout was chosen for it.
+    fn deadline(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl HasIDispatch for IDispatch {
+    fn get_idispatch(&self) -> &IDispatch {
+        self
+    }
+}
+
+/// A type that can be read from the properties of an object.
+///
+/// This can be derived for structs with named fields, reading each field from the property with
+/// the same name in `UpperCamelCase`. Fields can be given with `#[com(...)]` attributes:
+///
+/// - `name = "..."` reads a property with a different name.
+/// - `optional` reads the default value, such as `None`, if the object has no such property.
+/// - `nested` reads an object, and then reads the field from it with [`FromDispatch`].
+///
+/// ```rust
+/// # use com_shim::{ComObject, com_object};
+/// #
+/// # struct Field(IDispatch);
+/// # com_object! {
+/// #     impl Field {
+/// #         #[com(get)]
+/// #         fn text(&self) -> String { "Hello".to_string() }
+/// #         #[com(get)]
+/// #         fn id(&self) -> String { "wnd[0]/usr/txtName".to_string() }
+/// #         #[com(get)]
+/// #         fn changeable(&self) -> bool { true }
+/// #         #[com(get)]
+/// #         fn parent(&self) -> IDispatch { self.0.clone() }
+/// #     }
+/// # }
+/// #
+/// # struct Container;
+/// # com_object! {
+/// #     impl Container {
+/// #         #[com(get)]
+/// #         fn id(&self) -> String { "wnd[0]/usr".to_string() }
+/// #     }
+/// # }
+/// #
+/// use com_shim::{FromDispatch, IDispatch, Result};
+///
+/// #[derive(FromDispatch)]
+/// struct FieldInfo {
+///     #[com(name = "Text")]
+///     text: String,
+///     id: String,
+///     changeable: bool,
+///     #[com(optional)]
+///     max_length: Option<i32>,
+///     #[com(nested)]
+///     parent: Parent,
+/// }
+///
+/// #[derive(FromDispatch)]
+/// struct Parent {
+///     id: String,
+/// }
+///
+/// # fn main() -> Result<()> {
+/// # let parent = Container.into_idispatch();
+/// # let field = Field(parent.clone()).into_idispatch();
+/// let info = FieldInfo::from_dispatch(&field)?;
+/// assert_eq!(info.text, "Hello");
+/// assert_eq!(info.id, "wnd[0]/usr/txtName");
+/// assert!(info.changeable);
+/// assert_eq!(info.max_length, None);
+/// assert_eq!(info.parent.id, "wnd[0]/usr");
+///
+/// assert!(Parent::from_dispatch(&parent).is_ok());
+/// assert!(FieldInfo::from_dispatch(&parent).is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub trait FromDispatch: Sized {
+    /// Read this type from the properties of an object.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any property cannot be read, or cannot be converted.
+    fn from_dispatch(object: &impl HasIDispatch) -> Result<Self>;
 }
 
 /// Additional functions for working with an [`IDispatch`].
 pub trait IDispatchExt {
-    /// Call a function on this IDispatch
-    fn call<S>(&self, name: S, args: Vec<VARIANT>) -> Result<VARIANT>
+    /// Call a function on this [`IDispatch`]
+    ///
+    /// # Errors
+    ///
+    /// Fails if the name cannot be resolved or the invocation fails.
+    fn call<S>(&self, name: S, args: Vec<Variant>) -> Result<Variant>
     where
         S: AsRef<str>;
 
-    /// Get the value of a variable on this IDispatch
-    fn get<S>(&self, name: S) -> Result<VARIANT>
+    /// Get the value of a variable on this [`IDispatch`]
+    ///
+    /// # Errors
+    ///
+    /// Fails if the name cannot be resolved or the invocation fails.
+    fn get<S>(&self, name: S) -> Result<Variant>
     where
         S: AsRef<str>;
 
-    /// Set a value of a variable on this IDispatch
-    fn set<S>(&self, name: S, value: VARIANT) -> Result<VARIANT>
+    /// Set a value of a variable on this [`IDispatch`]
+    ///
+    /// # Errors
+    ///
+    /// Fails if the name cannot be resolved or the invocation fails.
+    fn set<S>(&self, name: S, value: Variant) -> Result<Variant>
     where
         S: AsRef<str>;
 }
 
 impl IDispatchExt for IDispatch {
-    fn call<S>(&self, name: S, mut args: Vec<VARIANT>) -> Result<VARIANT>
+    fn call<S>(&self, name: S, args: Vec<Variant>) -> Result<Variant>
     where
         S: AsRef<str>,
     {
-        let iid_null = GUID::zeroed();
-        let mut result = VARIANT::null();
-        unsafe {
-            tracing::debug!("Invoking method: {}", name.as_ref());
-            self.Invoke(
-                utils::get_method_dispid(self, name)?,
-                &iid_null,
-                0,
-                DISPATCH_METHOD,
-                &utils::assemble_dispparams_get(&mut args),
-                Some(&mut result),
-                None,
-                None,
-            )?;
+        tracing::debug!("Invoking method: {}", name.as_ref());
+        if let Some(placeholder) = dry_run::intercept(&args) {
+            return Ok(placeholder);
         }
-        Ok(result)
+        cache::call(self, args, |args| {
+            utils::dispatch(
+                self,
+                name.as_ref(),
+                DISPATCH_METHOD,
+                args,
+                utils::assemble_dispparams_get,
+            )
+        })
     }
 
-    fn get<S>(&self, name: S) -> Result<VARIANT>
+    fn get<S>(&self, name: S) -> Result<Variant>
     where
         S: AsRef<str>,
     {
-        let iid_null = GUID::zeroed();
-        let mut result = VARIANT::null();
-        unsafe {
-            self.Invoke(
-                utils::get_method_dispid(self, name)?,
-                &iid_null,
-                0,
-                DISPATCH_PROPERTYGET,
-                &DISPPARAMS::default(),
-                Some(&mut result),
-                None,
-                None,
-            )?;
+        if let Some(placeholder) = dry_run::intercept(&[]) {
+            return Ok(placeholder);
         }
-        Ok(result)
+        cache::call(self, vec![], |args| {
+            utils::dispatch(self, name.as_ref(), DISPATCH_PROPERTYGET, args, |_| {
+                DISPPARAMS::default()
+            })
+        })
     }
 
-    fn set<S>(&self, name: S, value: VARIANT) -> Result<VARIANT>
+    fn set<S>(&self, name: S, value: Variant) -> Result<Variant>
     where
         S: AsRef<str>,
     {
-        let iid_null = GUID::zeroed();
-        let mut result = VARIANT::null();
-        let mut args = vec![value];
-        unsafe {
-            self.Invoke(
-                utils::get_method_dispid(self, name)?,
-                &iid_null,
-                0,
-                DISPATCH_PROPERTYPUT,
-                &utils::assemble_dispparams_put(&mut args),
-                Some(&mut result),
-                None,
-                None,
-            )?;
+        let args = vec![value];
+        if let Some(placeholder) = dry_run::intercept(&args) {
+            return Ok(placeholder);
         }
-        Ok(result)
+        cache::call(self, args, |args| {
+            utils::dispatch(
+                self,
+                name.as_ref(),
+                DISPATCH_PROPERTYPUT,
+                args,
+                utils::assemble_dispparams_put,
+            )
+        })
     }
 }
 
@@ -120,292 +386,122 @@ impl IDispatchExt for IDispatch {
 pub trait VariantExt {
     /// Generate a null [`VARIANT`].
     fn null() -> VARIANT;
+
+    /// Whether this [`VARIANT`] holds no value: it is `VT_NULL`, `VT_EMPTY`, or an object
+    /// reference to `Nothing`. These are read as `None` when converting to an `Option<T>`.
+    ///
+    /// ```rust
+    /// # extern crate com_shim_fake_win32;
+    /// use com_shim::{IDispatch, ToVariant, VARIANT, VariantExt};
+    ///
+    /// assert!(VARIANT::null().is_nothing());
+    /// assert!(VARIANT::default().is_nothing());
+    /// assert!(None::<IDispatch>.to_variant().is_nothing());
+    /// assert!(!0_i32.to_variant().is_nothing());
+    /// ```
+    fn is_nothing(&self) -> bool;
 }
 
 impl VariantExt for VARIANT {
     fn null() -> VARIANT {
-        let mut variant = VARIANT::default();
-        let v00 = VARIANT_0_0 {
-            vt: VT_NULL,
-            ..Default::default()
-        };
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
+        Variant::null().into_raw()
     }
-}
-
-/// Indicates that this type is also a parent type and can be upcast to it.
-pub trait IsA<T> {
-    /// Upcast this value to it's parent type.
-    fn upcast(&self) -> T;
-}
 
-/// Functions to convert to and from a type that can be stored in a [`VARIANT`].
-pub trait VariantTypeExt<'a, T> {
-    /// Convert from a [`VARIANT`] into a type, T.
-    fn variant_into(&'a self) -> core::Result<T>;
-
-    /// Convert from a type T into a [`VARIANT`].
-    fn variant_from(value: T) -> VARIANT;
-}
-
-impl VariantTypeExt<'_, ()> for VARIANT {
-    fn variant_from(_value: ()) -> VARIANT {
-        VARIANT::null()
-    }
-
-    fn variant_into(&'_ self) -> core::Result<()> {
-        Ok(())
-    }
-}
-
-impl VariantTypeExt<'_, i16> for VARIANT {
-    fn variant_from(value: i16) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_I2,
-            ..Default::default()
-        };
-        v00.Anonymous.iVal = value;
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
-
-    fn variant_into(&self) -> core::Result<i16> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_I2)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let n = v00.Anonymous.iVal;
-            VariantClear(&mut new)?;
-            Ok(n)
-        }
-    }
-}
-
-impl VariantTypeExt<'_, i32> for VARIANT {
-    fn variant_from(value: i32) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_I4,
-            ..Default::default()
-        };
-        v00.Anonymous.lVal = value;
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
-
-    fn variant_into(&self) -> core::Result<i32> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_I4)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let n = v00.Anonymous.lVal;
-            VariantClear(&mut new)?;
-            Ok(n)
-        }
-    }
-}
-
-impl VariantTypeExt<'_, i64> for VARIANT {
-    fn variant_from(value: i64) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_I8,
-            ..Default::default()
-        };
-        v00.Anonymous.llVal = value;
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
-
-    fn variant_into(&self) -> core::Result<i64> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_I8)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let n = v00.Anonymous.llVal;
-            VariantClear(&mut new)?;
-            Ok(n)
-        }
-    }
-}
-
-impl VariantTypeExt<'_, u8> for VARIANT {
-    fn variant_from(value: u8) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_UI1,
-            ..Default::default()
-        };
-        v00.Anonymous.bVal = value;
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
-
-    fn variant_into(&self) -> core::Result<u8> {
+    fn is_nothing(&self) -> bool {
         unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_UI1)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let n = v00.Anonymous.bVal;
-            VariantClear(&mut new)?;
-            Ok(n)
+            let v00 = &self.Anonymous.Anonymous;
+            match v00.vt {
+                VT_NULL | VT_EMPTY => true,
+                VT_DISPATCH => v00.Anonymous.pdispVal.is_none(),
+                VT_UNKNOWN => v00.Anonymous.punkVal.is_none(),
+                _ => false,
+            }
         }
     }
 }
 
-impl VariantTypeExt<'_, u16> for VARIANT {
-    fn variant_from(value: u16) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_UI2,
-            ..Default::default()
-        };
-        v00.Anonymous.uiVal = value;
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
-
-    fn variant_into(&self) -> core::Result<u16> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_UI2)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let n = v00.Anonymous.uiVal;
-            VariantClear(&mut new)?;
-            Ok(n)
-        }
+/// A class generated by [`com_shim!`], which is nothing more than a wrapper around an [`IDispatch`].
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(transparent)]` over [`IDispatch`], so that a reference to an
+/// [`IDispatch`] can be reinterpreted as a reference to the implementor. [`com_shim!`] guarantees
+/// this for every class it generates.
+pub unsafe trait ComClass: HasIDispatch + From<IDispatch> {
+    /// The members declared for this class, not including those it inherits.
+    const MEMBERS: &'static [Member] = &[];
+
+    /// Borrow an [`IDispatch`] as this class, without cloning it.
+    #[must_use]
+    fn from_idispatch_ref(idispatch: &IDispatch) -> &Self {
+        // SAFETY: implementors guarantee that `Self` is `#[repr(transparent)]` over `IDispatch`.
+        unsafe { &*std::ptr::from_ref(idispatch).cast::<Self>() }
     }
 }
 
-impl VariantTypeExt<'_, u32> for VARIANT {
-    fn variant_from(value: u32) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_UI4,
-            ..Default::default()
-        };
-        v00.Anonymous.ulVal = value;
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
+/// Indicates that this type is also a parent type and can be upcast to it.
+///
+/// Every class is also a parent of itself, so functions can accept any descendant of a class
+/// without cloning it:
+///
+/// ```rust
+/// use com_shim::{com_shim, IsA};
+///
+/// com_shim! {
+///     struct GuiComponent {
+///         Text: String,
+///     }
+/// }
+///
+/// com_shim! {
+///     struct GuiTextField: GuiComponent {
+///         DisplayedText: String,
+///     }
+/// }
+///
+/// fn component_text(component: &impl IsA<GuiComponent>) -> com_shim::Result<String> {
+///     component.upcast_ref().text()
+/// }
+/// ```
+pub trait IsA<T> {
+    /// Upcast this value to its parent type.
+    fn upcast(&self) -> T;
 
-    fn variant_into(&self) -> core::Result<u32> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_UI4)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let n = v00.Anonymous.ulVal;
-            VariantClear(&mut new)?;
-            Ok(n)
-        }
-    }
+    /// Borrow this value as its parent type, without cloning the underlying [`IDispatch`].
+    fn upcast_ref(&self) -> &T;
 }
 
-impl VariantTypeExt<'_, u64> for VARIANT {
-    fn variant_from(value: u64) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_UI8,
-            ..Default::default()
-        };
-        v00.Anonymous.ullVal = value;
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
+impl<T: ComClass> IsA<T> for T {
+    fn upcast(&self) -> T {
+        T::from(self.get_idispatch().clone())
     }
 
-    fn variant_into(&self) -> core::Result<u64> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_UI8)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let n = v00.Anonymous.ullVal;
-            VariantClear(&mut new)?;
-            Ok(n)
-        }
+    fn upcast_ref(&self) -> &T {
+        self
     }
 }
 
-impl VariantTypeExt<'_, String> for VARIANT {
-    fn variant_from(value: String) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_BSTR,
-            ..Default::default()
-        };
-        let bstr = BSTR::from(&value);
-        v00.Anonymous.bstrVal = ManuallyDrop::new(bstr);
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
-
-    fn variant_into(&self) -> core::Result<String> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_BSTR)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let str = v00.Anonymous.bstrVal.to_string();
-            VariantClear(&mut new)?;
-            Ok(str)
-        }
-    }
-}
-
-impl VariantTypeExt<'_, bool> for VARIANT {
-    fn variant_from(value: bool) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_BOOL,
-            ..Default::default()
-        };
-        v00.Anonymous.boolVal = VARIANT_BOOL::from(value);
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
-    }
+/// Functions to convert to and from a type that can be stored in a [`VARIANT`].
+#[deprecated(note = "use `FromVariant` and `ToVariant` instead")]
+pub trait VariantTypeExt<'a, T> {
+    /// Convert from a [`VARIANT`] into a type, T.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the [`VARIANT`] cannot be converted into T.
+    fn variant_into(&'a self) -> core::Result<T>;
 
-    fn variant_into(&self) -> core::Result<bool> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let mut new = VARIANT::default();
-            VariantChangeType(&mut new, self, VAR_CHANGE_FLAGS(0), VT_BOOL)?;
-            let v00 = &new.Anonymous.Anonymous;
-            let b = v00.Anonymous.boolVal.as_bool();
-            VariantClear(&mut new)?;
-            Ok(b)
-        }
-    }
+    /// Convert from a type T into a [`VARIANT`], which must be cleared by the caller.
+    fn variant_from(value: T) -> VARIANT;
 }
 
-impl<'a> VariantTypeExt<'a, &'a IDispatch> for VARIANT {
-    fn variant_from(value: &'a IDispatch) -> VARIANT {
-        let mut variant = VARIANT::default();
-        let mut v00 = VARIANT_0_0 {
-            vt: VT_DISPATCH,
-            ..Default::default()
-        };
-        v00.Anonymous.pdispVal = ManuallyDrop::new(Some(value.clone()));
-        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
-        variant
+#[allow(deprecated)]
+impl<T: FromVariant + ToVariant> VariantTypeExt<'_, T> for VARIANT {
+    fn variant_into(&self) -> core::Result<T> {
+        T::from_variant(self)
     }
 
-    fn variant_into(&'a self) -> core::Result<&'a IDispatch> {
-        unsafe {
-            tracing::debug!("Own type: {:?}", self.Anonymous.Anonymous.vt);
-            let v00 = &self.Anonymous.Anonymous;
-            let idisp = v00.Anonymous.pdispVal.as_ref().ok_or(core::Error::new(
-                core::HRESULT(0x00123456),
-                core::HSTRING::from("com-shim: Cannot read IDispatch"),
-            ))?;
-            Ok(idisp)
-        }
+    fn variant_from(value: T) -> VARIANT {
+        value.to_variant().into_raw()
     }
 }
@@ -0,0 +1,129 @@
+use std::cell::Cell;
+
+use crate::utils;
+
+/// Whether calling a member changes anything, which decides whether a call can safely be repeated.
+///
+/// Members declared in [`com_shim!`](crate::com_shim) are idempotent when they are read, and
+/// mutating when they are written or called, unless they are marked `#[com(idempotent)]` or
+/// `#[com(mutating)]`. On a property, the attribute describes reading it, and writing it is always
+/// mutating.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Effect {
+    /// Calling the member changes nothing, so it can be repeated.
+    Idempotent,
+    /// Calling the member may change something, so it is made once.
+    Mutating,
+}
+
+/// How a member is called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemberKind {
+    /// Reading a property.
+    Get,
+    /// Writing a property.
+    Set,
+    /// Calling a method.
+    Method,
+}
+
+/// A member of a class declared with [`com_shim!`](crate::com_shim), as listed in
+/// [`ComClass::MEMBERS`](crate::ComClass::MEMBERS).
+///
+/// While a member is being called, it can be read with [`Member::current`], which is how
+/// [`RetryPolicy`](crate::RetryPolicy) decides which calls to repeat.
+///
+/// ```rust
+/// use com_shim::{ComClass, Effect, Member, MemberKind, com_shim};
+///
+/// com_shim! {
+///     struct GuiTable {
+///         RowCount: i32,
+///         mut CurrentRow: i32,
+///         fn Submit(),
+///         #[com(idempotent)]
+///         fn FindRow(String) -> i32,
+///         #[com(mutating)]
+///         NextId: i32,
+///         #[com(idempotent)]
+///         mut Zoom: i32,
+///     }
+/// }
+///
+/// let effect = |name, kind| {
+///     GuiTable::MEMBERS
+///         .iter()
+///         .find(|member| member.name() == name && member.kind() == kind)
+///         .map(Member::effect)
+/// };
+/// assert_eq!(effect("RowCount", MemberKind::Get), Some(Effect::Idempotent));
+/// assert_eq!(effect("CurrentRow", MemberKind::Get), Some(Effect::Idempotent));
+/// assert_eq!(effect("CurrentRow", MemberKind::Set), Some(Effect::Mutating));
+/// assert_eq!(effect("Submit", MemberKind::Method), Some(Effect::Mutating));
+/// assert_eq!(effect("FindRow", MemberKind::Method), Some(Effect::Idempotent));
+/// assert_eq!(effect("NextId", MemberKind::Get), Some(Effect::Mutating));
+/// assert_eq!(effect("Zoom", MemberKind::Get), Some(Effect::Idempotent));
+/// assert_eq!(effect("Zoom", MemberKind::Set), Some(Effect::Mutating));
+/// assert_eq!(GuiTable::MEMBERS.len(), 8);
+/// assert_eq!(Member::current(), None);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Member {
+    name: &'static str,
+    kind: MemberKind,
+    effect: Effect,
+}
+
+thread_local! {
+    /// The member being called on this thread.
+    static CURRENT: Cell<Option<Member>> = const { Cell::new(None) };
+}
+
+impl Member {
+    /// Describe a member.
+    #[must_use]
+    pub const fn new(name: &'static str, kind: MemberKind, effect: Effect) -> Self {
+        Self { name, kind, effect }
+    }
+
+    /// The COM name of the member.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// How the member is called.
+    #[must_use]
+    pub fn kind(&self) -> MemberKind {
+        self.kind
+    }
+
+    /// Whether calling the member changes anything.
+    #[must_use]
+    pub fn effect(&self) -> Effect {
+        self.effect
+    }
+
+    /// Whether the member can safely be called again.
+    #[must_use]
+    pub fn is_idempotent(&self) -> bool {
+        self.effect == Effect::Idempotent
+    }
+
+    /// The member being called on this thread through a class declared with
+    /// [`com_shim!`](crate::com_shim), if one is.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT.get()
+    }
+
+    /// Run `f` as a call to this member.
+    pub(crate) fn scope<R>(self, f: impl FnOnce() -> R) -> R {
+        utils::scoped(&CURRENT, Some(self), f)
+    }
+}
+
+/// Run `f` as if no member were being called.
+pub(crate) fn detached<R>(f: impl FnOnce() -> R) -> R {
+    utils::scoped(&CURRENT, None, f)
+}
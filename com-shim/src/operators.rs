@@ -0,0 +1,693 @@
+//! Comparison and arithmetic on variants, following the rules of `VarCmp`, `VarAdd`, `VarSub`,
+//! `VarMul`, `VarDiv` and `VarCat`.
+
+use std::{
+    cmp::Ordering,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use windows::{
+    Win32::{
+        Foundation::{DISP_E_DIVBYZERO, DISP_E_OVERFLOW, DISP_E_TYPEMISMATCH},
+        System::Variant::{
+            VAR_CHANGE_FLAGS, VARENUM, VARIANT, VARIANT_ALPHABOOL, VT_BOOL, VT_BSTR, VT_BYREF,
+            VT_CY, VT_DATE, VT_DECIMAL, VT_EMPTY, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_NULL,
+            VT_R4, VT_R8, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT,
+        },
+    },
+    core::Result,
+};
+
+use crate::{Currency, Decimal, OleDate, ToVariant, Variant, decimal::rescale, variant};
+
+/// How strings are compared by [`Variant::compare`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StringComparison {
+    /// Strings are compared by their UTF-16 code units, like `vbBinaryCompare`.
+    #[default]
+    Binary,
+    /// Strings are compared ignoring case, like `vbTextCompare`.
+    IgnoreCase,
+}
+
+/// The type of the result of an operation on numbers, in order of precedence.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Byte,
+    Short,
+    Long,
+    Long64,
+    Single,
+    Double,
+    Currency,
+    Date,
+    Decimal,
+}
+
+impl Kind {
+    fn of(vt: VARENUM) -> Option<Self> {
+        Some(match vt {
+            VT_UI1 => Kind::Byte,
+            VT_EMPTY | VT_BOOL | VT_I1 | VT_I2 => Kind::Short,
+            VT_UI2 | VT_I4 | VT_INT | VT_UI4 | VT_UINT => Kind::Long,
+            VT_I8 | VT_UI8 => Kind::Long64,
+            VT_R4 => Kind::Single,
+            // Strings are read as numbers
+            VT_R8 | VT_BSTR => Kind::Double,
+            VT_CY => Kind::Currency,
+            VT_DATE => Kind::Date,
+            VT_DECIMAL => Kind::Decimal,
+            _ => return None,
+        })
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Kind::Single | Kind::Double | Kind::Date)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Variant {
+    /// Compare two values, as `VarCmp` and Visual Basic's comparison operators do.
+    ///
+    /// - Numbers of any type, including `Boolean`, `Date`, `Currency` and `Decimal`, are
+    ///   compared by value.
+    /// - Strings are compared with each other as chosen by `strings`, and are greater than any
+    ///   number.
+    /// - `Empty` is equal to `0` or `""`.
+    /// - Comparisons with `Null` have no result, so return `None`.
+    ///
+    /// `PartialEq` and `PartialOrd` compare variants in the same way, comparing strings by
+    /// [`StringComparison::Binary`], so `Null` is not equal to itself.
+    ///
+    /// ```rust
+    /// use std::cmp::Ordering::{Equal, Greater, Less};
+    ///
+    /// use com_shim::{StringComparison::{Binary, IgnoreCase}, ToVariant, Variant};
+    ///
+    /// let compare = |left: Variant, right: Variant, how| left.compare(&right, how).unwrap();
+    /// assert_eq!(compare(2_i16.to_variant(), 2.0.to_variant(), Binary), Some(Equal));
+    /// assert_eq!(compare("10".to_variant(), 9.to_variant(), Binary), Some(Greater));
+    /// assert_eq!(compare("a".to_variant(), "B".to_variant(), IgnoreCase), Some(Less));
+    /// assert_eq!(compare(Variant::null(), 1.to_variant(), Binary), None);
+    ///
+    /// assert_eq!(2_u8.to_variant(), 2.0_f32.to_variant());
+    /// assert!("b".to_variant() > "a".to_variant());
+    /// assert_ne!(Variant::null(), Variant::null());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if either value is not a number, string, `Empty` or `Null`.
+    pub fn compare(&self, other: &Variant, strings: StringComparison) -> Result<Option<Ordering>> {
+        compare(self, other, strings)
+    }
+
+    /// Join two values as strings, as `VarCat` and Visual Basic's `&` operator do.
+    ///
+    /// `Null` is joined as an empty string, unless both values are `Null`, and booleans are
+    /// written as `True` or `False`.
+    ///
+    /// ```rust
+    /// use com_shim::{FromVariant, ToVariant, Variant, VariantExt};
+    ///
+    /// let joined = 1.to_variant().concat(&"a".to_variant())?;
+    /// assert_eq!(String::from_variant(&joined)?, "1a");
+    ///
+    /// let joined = true.to_variant().concat(&Variant::null())?;
+    /// assert_eq!(String::from_variant(&joined)?, "True");
+    ///
+    /// assert!(Variant::null().concat(&Variant::null())?.is_nothing());
+    /// # Ok::<_, windows::core::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if either value cannot be written as a string.
+    pub fn concat(&self, other: &Variant) -> Result<Variant> {
+        let left = dereference(self)?;
+        let left = left.as_deref().unwrap_or(self);
+        let right = dereference(other)?;
+        let right = right.as_deref().unwrap_or(other);
+        if vt_of(left) == VT_NULL && vt_of(right) == VT_NULL {
+            return Ok(Variant::null());
+        }
+        let text = |variant: &VARIANT| {
+            if vt_of(variant) == VT_NULL {
+                Ok(String::new())
+            } else {
+                text(variant, VARIANT_ALPHABOOL)
+            }
+        };
+        Ok((text(left)? + &text(right)?).to_variant())
+    }
+}
+
+impl PartialEq for Variant {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Variant {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.compare(other, StringComparison::Binary).ok().flatten()
+    }
+}
+
+macro_rules! operator {
+    ($($(#[$attr:meta])* $trait:ident, $fn:ident => $operation:ident;)*) => {
+        $(
+            $(#[$attr])*
+            impl $trait for &Variant {
+                type Output = Result<Variant>;
+
+                fn $fn(self, other: &Variant) -> Result<Variant> {
+                    arithmetic(self, other, Operation::$operation)
+                }
+            }
+        )*
+    };
+}
+
+operator! {
+    /// Add two values, as `VarAdd` and Visual Basic's `+` operator do.
+    ///
+    /// - The result has the type of the operand with the most precise type, in the order
+    ///   `Byte`, `Integer`, `Long`, `LongLong`, `Single`, `Double`, `Currency`, `Date` and
+    ///   `Decimal`.
+    /// - Integers that overflow that type are widened, to a `Double` at most.
+    /// - Two strings are joined, but a string and a number are added as `Double`s.
+    /// - `Empty` is `0` or an empty string, and `Null` makes the result `Null`.
+    ///
+    /// Subtraction, multiplication and division follow the same rules, except that
+    /// multiplying dates, or subtracting one from another, results in a `Double`, and division
+    /// results in a `Double` unless either operand is a `Decimal`, or both are `Single` or
+    /// smaller.
+    ///
+    /// ```rust
+    /// use com_shim::{ToVariant, Variant};
+    /// use windows::Win32::System::Variant::{VT_I4, VT_NULL, VT_R8};
+    ///
+    /// let sum = (&32767_i16.to_variant() + &1_i16.to_variant())?;
+    /// assert_eq!((sum.vt(), sum), (VT_I4, 32768.to_variant()));
+    ///
+    /// let sum = (&"1".to_variant() + &2.to_variant())?;
+    /// assert_eq!((sum.vt(), sum), (VT_R8, 3.0.to_variant()));
+    ///
+    /// assert_eq!((&Variant::null() + &1.to_variant())?.vt(), VT_NULL);
+    /// # Ok::<_, windows::core::Error>(())
+    /// ```
+    Add, add => Add;
+    /// Subtract one value from another, as `VarSub` and Visual Basic's `-` operator do.
+    ///
+    /// See [`Add`](#impl-Add-for-%26Variant) for the rules followed.
+    Sub, sub => Subtract;
+    /// Multiply two values, as `VarMul` and Visual Basic's `*` operator do.
+    ///
+    /// See [`Add`](#impl-Add-for-%26Variant) for the rules followed.
+    Mul, mul => Multiply;
+    /// Divide one value by another, as `VarDiv` and Visual Basic's `/` operator do.
+    ///
+    /// See [`Add`](#impl-Add-for-%26Variant) for the rules followed.
+    Div, div => Divide;
+}
+
+fn vt_of(variant: &VARIANT) -> VARENUM {
+    // SAFETY: `vt` is valid for every variant.
+    unsafe { variant.Anonymous.Anonymous.vt }
+}
+
+/// Copy the value a variant refers to, if it is a `VT_BYREF` reference.
+fn dereference(variant: &VARIANT) -> Result<Option<Variant>> {
+    let vt = vt_of(variant);
+    if vt.0 & VT_BYREF.0 == 0 {
+        return Ok(None);
+    }
+    variant::change_type(variant, VARENUM(vt.0 & !VT_BYREF.0)).map(Some)
+}
+
+fn text(variant: &VARIANT, flags: VAR_CHANGE_FLAGS) -> Result<String> {
+    let value = variant::change_type_with(variant, VT_BSTR, flags)?;
+    // SAFETY: the variant has just been converted to a string.
+    Ok(unsafe { value.Anonymous.Anonymous.Anonymous.bstrVal.to_string() })
+}
+
+fn float(variant: &VARIANT) -> Result<f64> {
+    let value = variant::change_type(variant, VT_R8)?;
+    // SAFETY: the variant has just been converted to a double.
+    Ok(unsafe { value.Anonymous.Anonymous.Anonymous.dblVal })
+}
+
+fn currency(variant: &VARIANT) -> Result<i128> {
+    let value = variant::change_type(variant, VT_CY)?;
+    // SAFETY: the variant has just been converted to a currency.
+    let value = Currency::from(unsafe { value.Anonymous.Anonymous.Anonymous.cyVal });
+    Ok(value.scaled().into())
+}
+
+/// Read a value as `mantissa / 10^scale`, exactly.
+fn exact(variant: &VARIANT) -> Result<(i128, u32)> {
+    let value = variant::change_type(variant, VT_DECIMAL)?;
+    // SAFETY: the variant has just been converted to a decimal.
+    let value = Decimal::try_from(unsafe { value.Anonymous.decVal })?;
+    Ok((value.mantissa(), value.scale().into()))
+}
+
+fn compare(left: &VARIANT, right: &VARIANT, strings: StringComparison) -> Result<Option<Ordering>> {
+    let left_value = dereference(left)?;
+    let left = left_value.as_deref().unwrap_or(left);
+    let right_value = dereference(right)?;
+    let right = right_value.as_deref().unwrap_or(right);
+
+    Ok(match (vt_of(left), vt_of(right)) {
+        (VT_NULL, _) | (_, VT_NULL) => None,
+        (VT_EMPTY, VT_EMPTY) => Some(Ordering::Equal),
+        (VT_BSTR | VT_EMPTY, VT_BSTR | VT_EMPTY) => {
+            let flags = VAR_CHANGE_FLAGS(0);
+            let (left, right) = (text(left, flags)?, text(right, flags)?);
+            Some(match strings {
+                StringComparison::Binary => left.encode_utf16().cmp(right.encode_utf16()),
+                StringComparison::IgnoreCase => {
+                    let (left, right) = (left.to_lowercase(), right.to_lowercase());
+                    left.encode_utf16().cmp(right.encode_utf16())
+                }
+            })
+        }
+        // Strings are greater than any number
+        (VT_BSTR, vt) if Kind::of(vt).is_some() => Some(Ordering::Greater),
+        (vt, VT_BSTR) if Kind::of(vt).is_some() => Some(Ordering::Less),
+        (left_vt, right_vt) => match (Kind::of(left_vt), Kind::of(right_vt)) {
+            (Some(left_kind), Some(right_kind))
+                if left_kind.is_float() || right_kind.is_float() =>
+            {
+                float(left)?.partial_cmp(&float(right)?)
+            }
+            (Some(_), Some(_)) => {
+                // Compare the whole and fractional parts separately, so neither overflows
+                let split = |(mantissa, scale): (i128, u32)| {
+                    let unit = 10_i128.pow(scale);
+                    let fraction = mantissa.rem_euclid(unit) * 10_i128.pow(28 - scale);
+                    (mantissa.div_euclid(unit), fraction)
+                };
+                Some(split(exact(left)?).cmp(&split(exact(right)?)))
+            }
+            _ => return Err(DISP_E_TYPEMISMATCH.into()),
+        },
+    })
+}
+
+fn arithmetic(left: &VARIANT, right: &VARIANT, operation: Operation) -> Result<Variant> {
+    let left_value = dereference(left)?;
+    let left = left_value.as_deref().unwrap_or(left);
+    let right_value = dereference(right)?;
+    let right = right_value.as_deref().unwrap_or(right);
+
+    let (left_vt, right_vt) = (vt_of(left), vt_of(right));
+    if left_vt == VT_NULL || right_vt == VT_NULL {
+        return Ok(Variant::null());
+    }
+    let texts = [left_vt, right_vt];
+    if operation == Operation::Add
+        && texts.contains(&VT_BSTR)
+        && texts.iter().all(|&vt| vt == VT_BSTR || vt == VT_EMPTY)
+    {
+        let flags = VAR_CHANGE_FLAGS(0);
+        return Ok((text(left, flags)? + &text(right, flags)?).to_variant());
+    }
+    let (Some(left_kind), Some(right_kind)) = (Kind::of(left_vt), Kind::of(right_vt)) else {
+        return Err(DISP_E_TYPEMISMATCH.into());
+    };
+
+    match result_kind(operation, left_kind, right_kind) {
+        kind @ (Kind::Byte | Kind::Short | Kind::Long | Kind::Long64) => {
+            integer(operation, kind, exact(left)?.0, exact(right)?.0)
+        }
+        kind @ (Kind::Single | Kind::Double) => {
+            let value = apply(operation, float(left)?, float(right)?)?;
+            Ok(narrow_float(value, kind))
+        }
+        Kind::Date => {
+            let value = apply(operation, float(left)?, float(right)?)?;
+            Ok(OleDate::try_from(value)?.to_variant())
+        }
+        Kind::Currency => {
+            let (left, right) = (currency(left)?, currency(right)?);
+            let value = match operation {
+                Operation::Add => Some(left + right),
+                Operation::Subtract => Some(left - right),
+                Operation::Multiply => rescale(left * right, 8, 4),
+                Operation::Divide => unreachable!("currencies are divided as doubles"),
+            };
+            let value = value.and_then(|value| i64::try_from(value).ok());
+            Ok(Currency::from_scaled(value.ok_or(DISP_E_OVERFLOW)?).to_variant())
+        }
+        Kind::Decimal => {
+            let (mantissa, scale) = decimal(operation, exact(left)?, exact(right)?)?;
+            Ok(fit(mantissa, scale)?.to_variant())
+        }
+    }
+}
+
+/// The type of the result of an operation on numbers of the given types.
+fn result_kind(operation: Operation, left: Kind, right: Kind) -> Kind {
+    let long =
+        matches!(left, Kind::Long | Kind::Long64) || matches!(right, Kind::Long | Kind::Long64);
+    match (operation, left.max(right)) {
+        (Operation::Divide, Kind::Decimal) => Kind::Decimal,
+        (Operation::Divide, Kind::Single) if !long => Kind::Single,
+        (Operation::Divide, _) | (Operation::Multiply, Kind::Date) => Kind::Double,
+        (Operation::Subtract, Kind::Date) if left == right => Kind::Double,
+        // A `Single` is not precise enough for every `Long`
+        (_, Kind::Single) if long => Kind::Double,
+        (_, kind) => kind,
+    }
+}
+
+fn apply(operation: Operation, left: f64, right: f64) -> Result<f64> {
+    let value = match operation {
+        Operation::Add => left + right,
+        Operation::Subtract => left - right,
+        Operation::Multiply => left * right,
+        Operation::Divide if right == 0.0 => return Err(DISP_E_DIVBYZERO.into()),
+        Operation::Divide => left / right,
+    };
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(DISP_E_OVERFLOW.into())
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)] // range checked
+fn narrow_float(value: f64, kind: Kind) -> Variant {
+    if kind == Kind::Single && value.abs() <= f64::from(f32::MAX) {
+        (value as f32).to_variant()
+    } else {
+        value.to_variant()
+    }
+}
+
+/// Apply an operation to integers, widening the result if it overflows `kind`.
+#[allow(clippy::cast_precision_loss)] // as precise as a `f64` can be
+fn integer(operation: Operation, kind: Kind, left: i128, right: i128) -> Result<Variant> {
+    let value = match operation {
+        Operation::Add => left.checked_add(right),
+        Operation::Subtract => left.checked_sub(right),
+        Operation::Multiply => left.checked_mul(right),
+        Operation::Divide => unreachable!("integers are divided as doubles"),
+    };
+    let widened: &[Kind] = match kind {
+        Kind::Byte => &[Kind::Byte, Kind::Short, Kind::Long],
+        Kind::Short => &[Kind::Short, Kind::Long],
+        _ => &[kind],
+    };
+    let narrowed = value.and_then(|value| {
+        widened.iter().find_map(|kind| match kind {
+            Kind::Byte => u8::try_from(value).ok().map(|value| value.to_variant()),
+            Kind::Short => i16::try_from(value).ok().map(|value| value.to_variant()),
+            Kind::Long => i32::try_from(value).ok().map(|value| value.to_variant()),
+            _ => i64::try_from(value).ok().map(|value| value.to_variant()),
+        })
+    });
+    match narrowed {
+        Some(variant) => Ok(variant),
+        None => Ok(apply(operation, left as f64, right as f64)?.to_variant()),
+    }
+}
+
+/// Apply an operation to values of `mantissa / 10^scale`, exactly where possible.
+fn decimal(
+    operation: Operation,
+    (left, left_scale): (i128, u32),
+    (right, right_scale): (i128, u32),
+) -> Result<(i128, u32)> {
+    match operation {
+        Operation::Add | Operation::Subtract => {
+            let right = if operation == Operation::Subtract {
+                -right
+            } else {
+                right
+            };
+            // Round the more precise value if aligning the two would overflow
+            (left_scale.min(right_scale)..=left_scale.max(right_scale))
+                .rev()
+                .find_map(|scale| {
+                    let left = rescale(left, left_scale, scale)?;
+                    let right = rescale(right, right_scale, scale)?;
+                    Some((left.checked_add(right)?, scale))
+                })
+                .ok_or_else(|| DISP_E_OVERFLOW.into())
+        }
+        Operation::Multiply => {
+            let (mut left, mut left_scale, mut right, mut right_scale) =
+                (left, left_scale, right, right_scale);
+            loop {
+                if let Some(product) = left.checked_mul(right) {
+                    return Ok((product, left_scale + right_scale));
+                }
+                // Round the more precise value until the product fits
+                if left_scale >= right_scale && left_scale > 0 {
+                    left = rescale(left, left_scale, left_scale - 1).ok_or(DISP_E_OVERFLOW)?;
+                    left_scale -= 1;
+                } else if right_scale > 0 {
+                    right = rescale(right, right_scale, right_scale - 1).ok_or(DISP_E_OVERFLOW)?;
+                    right_scale -= 1;
+                } else {
+                    return Err(DISP_E_OVERFLOW.into());
+                }
+            }
+        }
+        Operation::Divide => {
+            if right == 0 {
+                return Err(DISP_E_DIVBYZERO.into());
+            }
+            // Long division, until the quotient is exact or has as many digits as fit
+            let limit = 1_u128 << 96;
+            let (dividend, divisor) = (left.unsigned_abs(), right.unsigned_abs());
+            let (mut quotient, mut remainder) = (dividend / divisor, dividend % divisor);
+            let mut scale = i64::from(left_scale) - i64::from(right_scale);
+            while (scale < 0 || (scale < 28 && remainder != 0)) && quotient < limit / 10 {
+                remainder *= 10;
+                quotient = quotient * 10 + remainder / divisor;
+                remainder %= divisor;
+                scale += 1;
+            }
+            let twice = remainder * 2;
+            if twice > divisor || (twice == divisor && quotient % 2 != 0) {
+                quotient += 1;
+            }
+            let quotient = i128::try_from(quotient).map_err(|_| DISP_E_OVERFLOW)?;
+            let scale = u32::try_from(scale).map_err(|_| DISP_E_OVERFLOW)?;
+            let negative = (left < 0) ^ (right < 0);
+            Ok((if negative { -quotient } else { quotient }, scale))
+        }
+    }
+}
+
+/// Create a decimal, rounding it to fewer decimal places if it has too many digits.
+fn fit(mut mantissa: i128, mut scale: u32) -> Result<Decimal> {
+    loop {
+        if let Some(decimal) = u8::try_from(scale)
+            .ok()
+            .and_then(|scale| Decimal::new(mantissa, scale))
+        {
+            return Ok(decimal);
+        }
+        if scale == 0 {
+            return Err(DISP_E_OVERFLOW.into());
+        }
+        mantissa = rescale(mantissa, scale, scale - 1).ok_or(DISP_E_OVERFLOW)?;
+        scale -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering::{Equal, Greater, Less};
+
+    use windows::{
+        Win32::Foundation::{DISP_E_DIVBYZERO, DISP_E_TYPEMISMATCH},
+        core::HRESULT,
+    };
+
+    use crate::{Currency, Decimal, OleDate, Result, StringComparison, ToVariant, Variant};
+
+    type Table = [(Variant, Variant, std::result::Result<Variant, HRESULT>)];
+
+    fn currency(scaled: i64) -> Variant {
+        Currency::from_scaled(scaled).to_variant()
+    }
+
+    fn decimal(mantissa: i128, scale: u8) -> Variant {
+        Decimal::new(mantissa, scale).unwrap().to_variant()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> Variant {
+        OleDate::from_ymd(year, month, day).unwrap().to_variant()
+    }
+
+    /// Apply `operation` to each pair of values, naming the row whose result is not the expected
+    /// value of the expected type.
+    #[track_caller]
+    fn check(operation: fn(&Variant, &Variant) -> Result<Variant>, table: &Table) {
+        for (row, (left, right, expected)) in table.iter().enumerate() {
+            let result = operation(left, right).map_err(|e| e.code());
+            let result = result.map(|value| (value.vt(), value));
+            let expected = expected.clone().map(|value| (value.vt(), value));
+            assert_eq!(result, expected, "row {row}: {left:?} and {right:?}");
+        }
+    }
+
+    #[test]
+    fn comparisons() {
+        let comparisons = [
+            (1.to_variant(), 2.to_variant(), Ok(Some(Less))),
+            (2_i16.to_variant(), 2.0.to_variant(), Ok(Some(Equal))),
+            (true.to_variant(), (-1).to_variant(), Ok(Some(Equal))),
+            (
+                u64::MAX.to_variant(),
+                (-1_i64).to_variant(),
+                Ok(Some(Greater)),
+            ),
+            (currency(15_000), decimal(150, 2), Ok(Some(Equal))),
+            (decimal(1, 28), 0.to_variant(), Ok(Some(Greater))),
+            (decimal(1, 1), 0.1.to_variant(), Ok(Some(Equal))),
+            (date(1900, 1, 1), 2.to_variant(), Ok(Some(Equal))),
+            (Variant::empty(), 0.to_variant(), Ok(Some(Equal))),
+            (Variant::empty(), "".to_variant(), Ok(Some(Equal))),
+            (Variant::empty(), Variant::empty(), Ok(Some(Equal))),
+            ("abc".to_variant(), "ABC".to_variant(), Ok(Some(Greater))),
+            ("a".to_variant(), "B".to_variant(), Ok(Some(Greater))),
+            ("10".to_variant(), 9.to_variant(), Ok(Some(Greater))),
+            (9.to_variant(), "10".to_variant(), Ok(Some(Less))),
+            (Variant::null(), 1.to_variant(), Ok(None)),
+            (Variant::null(), Variant::null(), Ok(None)),
+            (
+                vec![1].to_variant(),
+                1.to_variant(),
+                Err(DISP_E_TYPEMISMATCH),
+            ),
+        ];
+        for (row, (left, right, expected)) in comparisons.iter().enumerate() {
+            let result = left
+                .compare(right, StringComparison::Binary)
+                .map_err(|e| e.code());
+            assert_eq!(
+                &result, expected,
+                "row {row}: {left:?} compared to {right:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn comparisons_ignoring_case() {
+        let ignore_case = |left: &str, right: &str| {
+            left.to_variant()
+                .compare(&right.to_variant(), StringComparison::IgnoreCase)
+                .unwrap()
+        };
+        assert_eq!(ignore_case("abc", "ABC"), Some(Equal));
+        assert_eq!(ignore_case("a", "B"), Some(Less));
+    }
+
+    #[test]
+    fn sums() {
+        let sums = [
+            (
+                1_i16.to_variant(),
+                2_i16.to_variant(),
+                Ok(3_i16.to_variant()),
+            ),
+            (
+                32767_i16.to_variant(),
+                1_i16.to_variant(),
+                Ok(32768_i32.to_variant()),
+            ),
+            (
+                200_u8.to_variant(),
+                100_u8.to_variant(),
+                Ok(300_i16.to_variant()),
+            ),
+            (
+                i32::MAX.to_variant(),
+                1.to_variant(),
+                Ok(2_147_483_648.0.to_variant()),
+            ),
+            (1.to_variant(), 0.5.to_variant(), Ok(1.5.to_variant())),
+            (
+                1.5_f32.to_variant(),
+                1_i16.to_variant(),
+                Ok(2.5_f32.to_variant()),
+            ),
+            ("1".to_variant(), 2.to_variant(), Ok(3.0.to_variant())),
+            ("a".to_variant(), "b".to_variant(), Ok("ab".to_variant())),
+            ("a".to_variant(), Variant::empty(), Ok("a".to_variant())),
+            (Variant::empty(), Variant::empty(), Ok(0_i16.to_variant())),
+            (
+                true.to_variant(),
+                true.to_variant(),
+                Ok((-2_i16).to_variant()),
+            ),
+            (currency(10_001), 1.to_variant(), Ok(currency(20_001))),
+            (decimal(1, 1), decimal(2, 1), Ok(decimal(3, 1))),
+            (date(2024, 2, 28), 1.to_variant(), Ok(date(2024, 2, 29))),
+            ("x".to_variant(), 1.to_variant(), Err(DISP_E_TYPEMISMATCH)),
+        ];
+        check(|a, b| a + b, &sums);
+    }
+
+    #[test]
+    fn differences() {
+        let differences = [
+            (date(2024, 3, 1), date(2024, 2, 28), Ok(2.0.to_variant())),
+            (
+                decimal(1, 28),
+                1.to_variant(),
+                Ok(decimal(-9_999_999_999_999_999_999_999_999_999, 28)),
+            ),
+        ];
+        check(|a, b| a - b, &differences);
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)] // the product overflows to a `Double`
+    fn products() {
+        let products = [
+            (2.to_variant(), 3.to_variant(), Ok(6.to_variant())),
+            (currency(15_000), currency(20_000), Ok(currency(30_000))),
+            (
+                i64::MAX.to_variant(),
+                2_i64.to_variant(),
+                Ok((i64::MAX as f64 * 2.0).to_variant()),
+            ),
+        ];
+        check(|a, b| a * b, &products);
+    }
+
+    #[test]
+    fn quotients() {
+        let quotients = [
+            (1.to_variant(), 4.to_variant(), Ok(0.25.to_variant())),
+            (
+                1.5_f32.to_variant(),
+                2_i16.to_variant(),
+                Ok(0.75_f32.to_variant()),
+            ),
+            (
+                decimal(1, 0),
+                decimal(3, 0),
+                Ok(decimal(3_333_333_333_333_333_333_333_333_333, 28)),
+            ),
+            (1.to_variant(), 0.to_variant(), Err(DISP_E_DIVBYZERO)),
+        ];
+        check(|a, b| a / b, &quotients);
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    cell::{Cell, RefCell},
+    sync::{PoisonError, RwLock},
+    time::Duration,
+};
+
+use windows::{
+    Win32::Foundation::{RPC_E_CALL_REJECTED, RPC_E_SERVERCALL_RETRYLATER},
+    core::{HRESULT, Result},
+};
+
+use crate::{Member, utils, wait};
+
+/// How calls that a busy server rejects are retried.
+///
+/// Out-of-process servers reject calls with `RPC_E_CALL_REJECTED` or `RPC_E_SERVERCALL_RETRYLATER`
+/// while they are busy, without making them. Every call made through
+/// [`IDispatchExt`](crate::IDispatchExt), and so every member of a class declared with
+/// [`com_shim!`](crate::com_shim), is retried after such an error, waiting longer each time, until
+/// it is made or the policy gives up. By default, a call is made up to 5 times, waiting 50ms before
+/// the first retry and doubling up to 1s.
+///
+/// Other errors, after which a call may already have been made, such as a dropped connection, can
+/// be retried with [`RetryPolicy::retry_idempotent_on`], but only for members that are
+/// [idempotent](crate::Effect::Idempotent).
+///
+/// The policy can be chosen for a call with [`RetryPolicy::scope`], or for the whole program with
+/// [`RetryPolicy::set_global`]. Members marked `#[com(no_retry)]` in `com_shim!` are never retried.
+///
+/// ```rust
+/// use std::{cell::Cell, time::Duration};
+///
+/// use com_shim::{ComObject, Result, RetryPolicy, com_object, com_shim};
+/// use windows::{
+///     Win32::Foundation::{E_FAIL, RPC_E_CALL_REJECTED},
+///     core::HRESULT,
+/// };
+///
+/// /// A server that fails calls while it is busy.
+/// #[derive(Default)]
+/// struct Busy {
+///     failures: Cell<u32>,
+///     attempts: Cell<u32>,
+/// }
+///
+/// impl Busy {
+///     /// Returns how many attempts the call took.
+///     fn attempt(&self, code: HRESULT) -> Result<u32> {
+///         self.attempts.set(self.attempts.get() + 1);
+///         if self.failures.get() > 0 {
+///             self.failures.set(self.failures.get() - 1);
+///             return Err(code.into());
+///         }
+///         Ok(self.attempts.replace(0))
+///     }
+/// }
+///
+/// com_object! {
+///     impl Busy {
+///         #[com(set)]
+///         fn set_failures(&self, failures: u32) {
+///             self.failures.set(failures);
+///             self.attempts.set(0);
+///         }
+///
+///         #[com(method)]
+///         fn work(&self) -> Result<u32> {
+///             self.attempt(RPC_E_CALL_REJECTED)
+///         }
+///
+///         #[com(method)]
+///         fn submit(&self) -> Result<u32> {
+///             self.attempt(RPC_E_CALL_REJECTED)
+///         }
+///
+///         #[com(method)]
+///         fn read(&self) -> Result<u32> {
+///             self.attempt(E_FAIL)
+///         }
+///
+///         #[com(method)]
+///         fn write(&self) -> Result<u32> {
+///             self.attempt(E_FAIL)
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     struct BusyClass {
+///         mut Failures: u32,
+///         fn Work() -> u32,
+///         #[com(no_retry)]
+///         fn Submit() -> u32,
+///         #[com(idempotent)]
+///         fn Read() -> u32,
+///         fn Write() -> u32,
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let busy = BusyClass::from(Busy::default().into_idispatch());
+///
+/// // Retry quickly in this example
+/// let policy = RetryPolicy::default().backoff(Duration::from_millis(1), Duration::from_millis(5));
+/// policy.clone().scope(|| -> Result<()> {
+///     busy.set_failures(2)?;
+///     assert_eq!(busy.work()?, 3);
+///
+///     busy.set_failures(10)?;
+///     assert_eq!(busy.work().unwrap_err().code(), RPC_E_CALL_REJECTED);
+///
+///     busy.set_failures(1)?;
+///     assert_eq!(busy.submit().unwrap_err().code(), RPC_E_CALL_REJECTED);
+///     Ok(())
+/// })?;
+///
+/// busy.set_failures(7)?;
+/// assert_eq!(policy.clone().max_attempts(10).scope(|| busy.work())?, 8);
+///
+/// // Only idempotent members are retried after errors that may follow a call being made
+/// policy.retry_idempotent_on([E_FAIL]).scope(|| -> Result<()> {
+///     busy.set_failures(1)?;
+///     assert_eq!(busy.read()?, 2);
+///
+///     busy.set_failures(1)?;
+///     assert_eq!(busy.write().unwrap_err().code(), E_FAIL);
+///     Ok(())
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    codes: Vec<HRESULT>,
+    idempotent_codes: Vec<HRESULT>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            codes: vec![RPC_E_CALL_REJECTED, RPC_E_SERVERCALL_RETRYLATER],
+            idempotent_codes: vec![],
+        }
+    }
+}
+
+/// The global policy, if one has been set.
+static GLOBAL: RwLock<Option<RetryPolicy>> = RwLock::new(None);
+
+thread_local! {
+    /// The policy chosen for the current call.
+    static CALL: RefCell<Option<RetryPolicy>> = const { RefCell::new(None) };
+    /// Whether the current call is to a member that is never retried.
+    static NEVER: Cell<bool> = const { Cell::new(false) };
+}
+
+impl RetryPolicy {
+    /// A policy that makes every call only once.
+    #[must_use]
+    pub fn never() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    /// Make a call up to `max_attempts` times, including the first, and at least once.
+    #[must_use]
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Wait `backoff` before the first retry, doubling after each retry up to `max_backoff`.
+    ///
+    /// While it waits, the thread dispatches window messages, so that a single-threaded apartment
+    /// still receives calls.
+    #[must_use]
+    pub fn backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff.max(backoff);
+        self
+    }
+
+    /// Retry calls that fail with any of `codes`, instead of those rejected by a busy server.
+    #[must_use]
+    pub fn retry_on(mut self, codes: impl IntoIterator<Item = HRESULT>) -> Self {
+        self.codes = codes.into_iter().collect();
+        self
+    }
+
+    /// Also retry calls that fail with any of `codes`, if they are to an idempotent member.
+    ///
+    /// Calls to mutating members, and calls not made through a class declared with
+    /// [`com_shim!`](crate::com_shim), are only retried after the errors given to
+    /// [`RetryPolicy::retry_on`].
+    #[must_use]
+    pub fn retry_idempotent_on(mut self, codes: impl IntoIterator<Item = HRESULT>) -> Self {
+        self.idempotent_codes = codes.into_iter().collect();
+        self
+    }
+
+    /// The policy used when none is chosen for a call.
+    #[must_use]
+    pub fn global() -> Self {
+        GLOBAL
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// Set the policy used when none is chosen for a call.
+    pub fn set_global(self) {
+        *GLOBAL.write().unwrap_or_else(PoisonError::into_inner) = Some(self);
+    }
+
+    /// The policy that applies on this thread now.
+    #[must_use]
+    pub fn current() -> Self {
+        if NEVER.get() {
+            return Self::never();
+        }
+        CALL.with_borrow(Clone::clone).unwrap_or_else(Self::global)
+    }
+
+    /// The policy chosen with [`RetryPolicy::scope`] on this thread, if one is.
+    pub(crate) fn chosen() -> Option<Self> {
+        CALL.with_borrow(Clone::clone)
+    }
+
+    /// Run `f` with this policy.
+    ///
+    /// This also applies to calls `f` makes through a [`Remote`](crate::Remote) handle.
+    pub fn scope<R>(self, f: impl FnOnce() -> R) -> R {
+        utils::scoped(&CALL, Some(self), f)
+    }
+
+    /// Make a call, retrying it by this policy.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the call fails with an error that is not retried, or still fails after the last
+    /// attempt.
+    pub fn run<R>(&self, mut f: impl FnMut() -> Result<R>) -> Result<R> {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(error) if attempt < self.max_attempts && self.retries(error.code()) => {
+                    tracing::debug!(
+                        "Retrying in {backoff:?} after attempt {attempt} failed: {error}"
+                    );
+                    wait::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Whether a call that fails with `code` is retried.
+    fn retries(&self, code: HRESULT) -> bool {
+        self.codes.contains(&code)
+            || (self.idempotent_codes.contains(&code)
+                && Member::current().is_some_and(|member| member.is_idempotent()))
+    }
+}
+
+/// Make a call, retrying it by the current policy if it fails with an error that may be retried.
+pub(crate) fn run<R>(mut f: impl FnMut() -> Result<R>) -> Result<R> {
+    // The policy is only read once a call has failed
+    let result = f();
+    let Err(error) = &result else {
+        return result;
+    };
+    let policy = RetryPolicy::current();
+    if !policy.retries(error.code()) {
+        return result;
+    }
+    let mut first = Some(result);
+    policy.run(|| first.take().unwrap_or_else(&mut f))
+}
+
+/// Run `f` with the global policy, whatever was chosen for the current call.
+pub(crate) fn detached<R>(f: impl FnOnce() -> R) -> R {
+    utils::scoped(&CALL, None, || utils::scoped(&NEVER, false, f))
+}
+
+/// Run `f` without retrying any call it makes.
+pub(crate) fn never<R>(f: impl FnOnce() -> R) -> R {
+    utils::scoped(&NEVER, true, f)
+}
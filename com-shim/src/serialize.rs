@@ -0,0 +1,327 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
+use windows::{
+    Win32::{
+        Foundation::{DISP_E_BADVARTYPE, DISP_E_TYPEMISMATCH},
+        System::{
+            Ole::{SafeArrayGetDim, SafeArrayGetVartype},
+            Variant::{
+                VARENUM, VARIANT, VARIANT_0_0, VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF, VT_CY,
+                VT_DATE, VT_DECIMAL, VT_DISPATCH, VT_EMPTY, VT_ERROR, VT_I1, VT_I2, VT_I4, VT_I8,
+                VT_INT, VT_NULL, VT_R4, VT_R8, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT, VT_UNKNOWN,
+                VT_VARIANT,
+            },
+        },
+    },
+    core::Result,
+};
+
+use crate::{
+    Currency, Decimal, FromVariant, OleDate, ToVariant, Variant, VariantExt, array, convert,
+};
+
+/// The serialized form of a variant, tagged with its type so that it can be read back exactly.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum Repr {
+    Empty,
+    Null,
+    I1(i8),
+    I2(i16),
+    I4(i32),
+    I8(i64),
+    Ui1(u8),
+    Ui2(u16),
+    Ui4(u32),
+    Ui8(u64),
+    Int(i32),
+    Uint(u32),
+    R4(f32),
+    R8(f64),
+    Bool(bool),
+    Bstr(String),
+    Date(DateRepr),
+    Cy(Currency),
+    Decimal(Decimal),
+    Error(i32),
+    /// A null object reference. Live objects cannot be serialized.
+    Dispatch,
+    /// A null object reference. Live objects cannot be serialized.
+    Unknown,
+    Array(ArrayRepr),
+}
+
+/// Dates are written as text where that is exact, and as the underlying number otherwise.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum DateRepr {
+    Text(OleDate),
+    Number(f64),
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArrayRepr {
+    #[serde(rename = "type")]
+    element: ElementType,
+    /// The lower bound and length of each dimension.
+    bounds: Vec<(i32, usize)>,
+    /// The elements in storage order, with the first dimension varying fastest.
+    elements: Vec<Repr>,
+}
+
+/// The element types of a `SAFEARRAY` that can be serialized.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum ElementType {
+    I1,
+    I2,
+    I4,
+    I8,
+    Ui1,
+    Ui2,
+    Ui4,
+    Ui8,
+    Int,
+    Uint,
+    R4,
+    R8,
+    Bool,
+    Bstr,
+    Date,
+    Cy,
+    Decimal,
+    Error,
+    Dispatch,
+    Unknown,
+    Variant,
+}
+
+const ELEMENT_TYPES: [(ElementType, VARENUM); 21] = [
+    (ElementType::I1, VT_I1),
+    (ElementType::I2, VT_I2),
+    (ElementType::I4, VT_I4),
+    (ElementType::I8, VT_I8),
+    (ElementType::Ui1, VT_UI1),
+    (ElementType::Ui2, VT_UI2),
+    (ElementType::Ui4, VT_UI4),
+    (ElementType::Ui8, VT_UI8),
+    (ElementType::Int, VT_INT),
+    (ElementType::Uint, VT_UINT),
+    (ElementType::R4, VT_R4),
+    (ElementType::R8, VT_R8),
+    (ElementType::Bool, VT_BOOL),
+    (ElementType::Bstr, VT_BSTR),
+    (ElementType::Date, VT_DATE),
+    (ElementType::Cy, VT_CY),
+    (ElementType::Decimal, VT_DECIMAL),
+    (ElementType::Error, VT_ERROR),
+    (ElementType::Dispatch, VT_DISPATCH),
+    (ElementType::Unknown, VT_UNKNOWN),
+    (ElementType::Variant, VT_VARIANT),
+];
+
+impl ElementType {
+    fn from_vt(vt: VARENUM) -> Result<Self> {
+        ELEMENT_TYPES
+            .iter()
+            .find(|(_, v)| *v == vt)
+            .map(|(element, _)| *element)
+            .ok_or_else(|| DISP_E_BADVARTYPE.into())
+    }
+
+    fn vt(self) -> VARENUM {
+        ELEMENT_TYPES
+            .iter()
+            .find(|(element, _)| *element == self)
+            .map(|(_, vt)| *vt)
+            .expect("every element type has a vt")
+    }
+}
+
+/// Read a variant into its serialized form.
+fn read(variant: &VARIANT) -> Result<Repr> {
+    // SAFETY: `vt` is valid for every variant, and each field is read only for its type.
+    let (vt, value) = unsafe {
+        let v00 = &variant.Anonymous.Anonymous;
+        (v00.vt, &v00.Anonymous)
+    };
+    Ok(match vt {
+        VT_EMPTY => Repr::Empty,
+        VT_NULL => Repr::Null,
+        VT_I1 => Repr::I1(i8::from_variant(variant)?),
+        VT_I2 => Repr::I2(i16::from_variant(variant)?),
+        VT_I4 => Repr::I4(i32::from_variant(variant)?),
+        VT_I8 => Repr::I8(i64::from_variant(variant)?),
+        VT_UI1 => Repr::Ui1(u8::from_variant(variant)?),
+        VT_UI2 => Repr::Ui2(u16::from_variant(variant)?),
+        VT_UI4 => Repr::Ui4(u32::from_variant(variant)?),
+        VT_UI8 => Repr::Ui8(u64::from_variant(variant)?),
+        VT_INT => Repr::Int(unsafe { value.intVal }),
+        VT_UINT => Repr::Uint(unsafe { value.uintVal }),
+        VT_R4 => Repr::R4(f32::from_variant(variant)?),
+        VT_R8 => Repr::R8(f64::from_variant(variant)?),
+        VT_BOOL => Repr::Bool(bool::from_variant(variant)?),
+        VT_BSTR => Repr::Bstr(String::from_variant(variant)?),
+        VT_DATE => {
+            let number = unsafe { value.date };
+            Repr::Date(match OleDate::try_from(number) {
+                Ok(date) if f64::from(date).to_bits() == number.to_bits() => DateRepr::Text(date),
+                _ => DateRepr::Number(number),
+            })
+        }
+        VT_CY => Repr::Cy(Currency::from_variant(variant)?),
+        VT_DECIMAL => Repr::Decimal(Decimal::from_variant(variant)?),
+        VT_ERROR => Repr::Error(unsafe { value.scode }),
+        VT_DISPATCH | VT_UNKNOWN if !variant.is_nothing() => {
+            return Err(DISP_E_TYPEMISMATCH.into());
+        }
+        VT_DISPATCH => Repr::Dispatch,
+        VT_UNKNOWN => Repr::Unknown,
+        _ if vt.0 & VT_ARRAY.0 != 0 && vt.0 & VT_BYREF.0 == 0 => {
+            let psa = convert::safearray(variant)?;
+            // SAFETY: a `VT_ARRAY` variant holds a valid `SAFEARRAY`.
+            let (element, (elements, bounds)) = unsafe {
+                (
+                    ElementType::from_vt(SafeArrayGetVartype(psa)?)?,
+                    array::read_safearray(psa, SafeArrayGetDim(psa), read)?,
+                )
+            };
+            Repr::Array(ArrayRepr {
+                element,
+                bounds,
+                elements,
+            })
+        }
+        _ => return Err(DISP_E_BADVARTYPE.into()),
+    })
+}
+
+/// Build a variant from its serialized form.
+fn build(repr: Repr) -> Result<Variant> {
+    let raw = |vt: VARENUM, set: &dyn Fn(&mut VARIANT_0_0)| {
+        let mut v00 = VARIANT_0_0 {
+            vt,
+            ..Default::default()
+        };
+        set(&mut v00);
+        Variant::from_v00(v00)
+    };
+    Ok(match repr {
+        Repr::Empty => Variant::empty(),
+        Repr::Null => Variant::null(),
+        Repr::I1(value) => value.to_variant(),
+        Repr::I2(value) => value.to_variant(),
+        Repr::I4(value) => value.to_variant(),
+        Repr::I8(value) => value.to_variant(),
+        Repr::Ui1(value) => value.to_variant(),
+        Repr::Ui2(value) => value.to_variant(),
+        Repr::Ui4(value) => value.to_variant(),
+        Repr::Ui8(value) => value.to_variant(),
+        Repr::Int(value) => raw(VT_INT, &|v00| v00.Anonymous.intVal = value),
+        Repr::Uint(value) => raw(VT_UINT, &|v00| v00.Anonymous.uintVal = value),
+        Repr::R4(value) => value.to_variant(),
+        Repr::R8(value) => value.to_variant(),
+        Repr::Bool(value) => value.to_variant(),
+        Repr::Bstr(value) => value.to_variant(),
+        Repr::Date(DateRepr::Text(date)) => date.to_variant(),
+        Repr::Date(DateRepr::Number(number)) => raw(VT_DATE, &|v00| v00.Anonymous.date = number),
+        Repr::Cy(value) => value.to_variant(),
+        Repr::Decimal(value) => value.to_variant(),
+        Repr::Error(scode) => raw(VT_ERROR, &|v00| v00.Anonymous.scode = scode),
+        Repr::Dispatch => raw(VT_DISPATCH, &|_| ()),
+        Repr::Unknown => raw(VT_UNKNOWN, &|_| ()),
+        Repr::Array(ArrayRepr {
+            element,
+            bounds,
+            elements,
+        }) => {
+            let count = bounds
+                .iter()
+                .try_fold(1_usize, |n, (_, len)| n.checked_mul(*len));
+            if bounds.is_empty() || count != Some(elements.len()) {
+                return Err(DISP_E_TYPEMISMATCH.into());
+            }
+            let elements = elements.into_iter().map(build).collect::<Result<_>>()?;
+            let vt = element.vt();
+            let psa = array::create_typed_safearray(vt, &bounds, elements)?;
+            raw(VARENUM(VT_ARRAY.0 | vt.0), &|v00| {
+                v00.Anonymous.parray = psa;
+            })
+        }
+    })
+}
+
+/// Variants are serialized tagged with their type, so that they are read back exactly as they
+/// were written, including the element type and bounds of arrays. Dates are written as text,
+/// and currency and decimal values as exact decimal strings:
+///
+/// ```rust
+/// use com_shim::{Array2, Currency, FromVariant, OleDate, ToVariant, Variant};
+///
+/// let date = OleDate::from_ymd_hms_milli(2024, 2, 29, 13, 30, 0, 0).unwrap();
+/// let json = serde_json::to_string(&date.to_variant()).unwrap();
+/// assert_eq!(json, r#"{"DATE":"2024-02-29 13:30:00"}"#);
+///
+/// let price = Currency::from_scaled(123_450);
+/// let grid = Array2::from_row_major(2, 2, vec![1, 2, 3, 4]).unwrap().with_lower_bounds(1, 1);
+/// let values = ("Widget", price, vec![Some(0.5), None], grid).to_variant();
+///
+/// let json = serde_json::to_string(&values).unwrap();
+/// let loaded: Variant = serde_json::from_str(&json).unwrap();
+/// assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+///
+/// let (name, price, ratios, grid) =
+///     <(String, Currency, Vec<Option<f64>>, Array2<i32>)>::from_variant(&loaded).unwrap();
+/// assert_eq!(name, "Widget");
+/// assert_eq!(price.to_string(), "12.345");
+/// assert_eq!(ratios, vec![Some(0.5), None]);
+/// assert_eq!((grid[(1, 0)], grid.lower_bounds()), (3, (1, 1)));
+/// ```
+///
+/// Objects cannot be serialized, other than null references, and nor can values held by
+/// reference.
+impl Serialize for Variant {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        read(self)
+            .map_err(ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Variant {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        build(Repr::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// Serialize and deserialize a value as text, in the format written by its
+/// [`Display`](std::fmt::Display) implementation.
+macro_rules! text {
+    ($ty:ty, $expecting:literal) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                <$ty>::parse(&text).ok_or_else(|| {
+                    de::Error::invalid_value(de::Unexpected::Str(&text), &$expecting)
+                })
+            }
+        }
+    };
+}
+
+text!(OleDate, "a date formatted as YYYY-MM-DD HH:MM:SS");
+text!(
+    Currency,
+    "a decimal number with at most four decimal places"
+);
+text!(Decimal, "a decimal number");
@@ -0,0 +1,376 @@
+//! A minimal `IDispatch` implementation, for objects that COM calls into, such as event sinks.
+
+use std::{
+    any::{self, Any},
+    ffi::c_void,
+    mem::ManuallyDrop,
+    panic::{self, AssertUnwindSafe},
+    slice,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use windows::{
+    Win32::{
+        Foundation::{
+            DISP_E_BADINDEX, DISP_E_BADPARAMCOUNT, DISP_E_EXCEPTION, DISP_E_MEMBERNOTFOUND,
+            DISP_E_PARAMNOTFOUND, DISP_E_TYPEMISMATCH, DISP_E_UNKNOWNNAME, E_NOINTERFACE,
+            E_POINTER, E_UNEXPECTED, S_OK,
+        },
+        System::{
+            Com::{DISPATCH_FLAGS, DISPPARAMS, EXCEPINFO, IDispatch, IDispatch_Vtbl},
+            Ole::DISPID_UNKNOWN,
+            Variant::VARIANT,
+        },
+    },
+    core::{BSTR, ComInterface, GUID, HRESULT, IUnknown, IUnknown_Vtbl, Interface, PCWSTR, Result},
+};
+
+use crate::{Variant, wait};
+
+/// An object that can be called through `IDispatch`.
+pub(crate) trait Dispatch: 'static {
+    /// Whether the object also answers to an interface other than `IUnknown` and `IDispatch`,
+    /// which it implements through `IDispatch`, such as an outgoing interface.
+    fn implements(&self, iid: &GUID) -> bool {
+        let _ = iid;
+        false
+    }
+
+    /// Find the `DISPID` of a member, ignoring case.
+    fn dispid(&self, name: &str) -> Option<i32>;
+
+    /// Invoke a member, with the arguments in reverse order as `IDispatch::Invoke` receives them.
+    fn invoke(&self, dispid: i32, flags: DISPATCH_FLAGS, params: &DISPPARAMS) -> Result<Variant>;
+}
+
+/// A Rust object that can be called through `IDispatch`, such as by passing it to a COM method or
+/// a script.
+///
+/// This is implemented by [`com_object!`](crate::com_object) for an `impl` block, exposing its
+/// methods marked `#[com(get)]`, `#[com(set)]` and `#[com(method)]` as properties and methods named
+/// in `UpperCamelCase`, or as given with `name = "..."`. Arguments and return values, which may be
+/// a [`Result`], are converted with [`FromVariant`](crate::FromVariant) and
+/// [`ToVariant`](crate::ToVariant). An error returned by a method, or a panic, which is caught, is
+/// raised as an exception describing it, with the error's code or `E_UNEXPECTED`.
+///
+/// ```rust
+/// use std::cell::Cell;
+///
+/// use com_shim::{ComObject, IDispatch, IDispatchExt, Result, ToVariant, com_object, com_shim};
+/// use windows::Win32::Foundation::{DISP_E_OVERFLOW, E_UNEXPECTED};
+///
+/// #[derive(Default)]
+/// struct Counter {
+///     count: Cell<i32>,
+/// }
+///
+/// com_object! {
+///     impl Counter {
+///         #[com(get)]
+///         fn count(&self) -> i32 {
+///             self.count.get()
+///         }
+///
+///         #[com(set)]
+///         fn set_count(&self, count: i32) {
+///             self.count.set(count);
+///         }
+///
+///         #[com(method)]
+///         fn add(&self, amount: i32) -> Result<i32> {
+///             let count = self.count.get().checked_add(amount).ok_or(DISP_E_OVERFLOW)?;
+///             self.count.set(count);
+///             Ok(count)
+///         }
+///
+///         #[com(method, name = "Reset")]
+///         fn clear(&self) {
+///             panic!("not implemented yet");
+///         }
+///     }
+/// }
+///
+/// // Objects can be used through classes like any other
+/// com_shim! {
+///     struct CounterClass {
+///         mut Count: i32,
+///         fn Add(i32) -> i32,
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let counter: IDispatch = Counter::default().into_idispatch();
+/// counter.set("Count", 40.to_variant())?;
+/// assert_eq!(counter.call("add", vec![1.to_variant()])?, 41.to_variant());
+///
+/// let class = CounterClass::from(counter.clone());
+/// assert_eq!(class.add(1)?, 42);
+/// assert_eq!(class.add(i32::MAX).unwrap_err().code(), DISP_E_OVERFLOW);
+///
+/// assert_eq!(counter.call("Reset", vec![]).unwrap_err().code(), E_UNEXPECTED);
+/// assert_eq!(class.count()?, 42);
+/// # Ok(())
+/// # }
+/// ```
+pub trait ComObject: 'static {
+    /// Find the `DISPID` of a member, ignoring case.
+    fn dispid(&self, name: &str) -> Option<i32>;
+
+    /// Invoke a member, with the arguments in reverse order as `IDispatch::Invoke` receives them.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no such member, if the arguments cannot be converted, or if the member
+    /// fails.
+    fn invoke(&self, dispid: i32, flags: DISPATCH_FLAGS, params: &DISPPARAMS) -> Result<Variant>;
+
+    /// Move this into a new COM object, which is dropped once every reference to it is released.
+    fn into_idispatch(self) -> IDispatch
+    where
+        Self: Sized,
+    {
+        into_idispatch(self)
+    }
+}
+
+impl<T: ComObject> Dispatch for T {
+    fn dispid(&self, name: &str) -> Option<i32> {
+        ComObject::dispid(self, name)
+    }
+
+    fn invoke(&self, dispid: i32, flags: DISPATCH_FLAGS, params: &DISPPARAMS) -> Result<Variant> {
+        ComObject::invoke(self, dispid, flags, params)
+    }
+}
+
+/// The layout of an object, which begins with its vtable.
+#[repr(C)]
+struct Object<T> {
+    vtable: &'static IDispatch_Vtbl,
+    references: AtomicU32,
+    value: T,
+}
+
+/// Wrap a value in a new COM object.
+pub(crate) fn into_idispatch<T: Dispatch>(value: T) -> IDispatch {
+    let object = Box::new(Object {
+        vtable: &Object::<T>::VTABLE,
+        references: AtomicU32::new(1),
+        value,
+    });
+    // SAFETY: the object begins with an `IDispatch` vtable, and starts with one reference.
+    unsafe { IDispatch::from_raw(Box::into_raw(object).cast()) }
+}
+
+impl<T: Dispatch> Object<T> {
+    const VTABLE: IDispatch_Vtbl = IDispatch_Vtbl {
+        base__: IUnknown_Vtbl {
+            QueryInterface: Self::query_interface,
+            AddRef: Self::add_ref,
+            Release: Self::release,
+        },
+        GetTypeInfoCount: Self::get_type_info_count,
+        GetTypeInfo: Self::get_type_info,
+        GetIDsOfNames: Self::get_ids_of_names,
+        Invoke: Self::invoke,
+    };
+
+    /// Borrow the object behind an interface pointer.
+    unsafe fn from_this<'a>(this: *mut c_void) -> &'a Self {
+        unsafe { &*this.cast::<Self>() }
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut c_void,
+        iid: *const GUID,
+        interface: *mut *mut c_void,
+    ) -> HRESULT {
+        if iid.is_null() || interface.is_null() {
+            return E_POINTER;
+        }
+        unsafe {
+            let object = Self::from_this(this);
+            let iid = &*iid;
+            if *iid == IUnknown::IID || *iid == IDispatch::IID || object.value.implements(iid) {
+                object.references.fetch_add(1, Ordering::Relaxed);
+                interface.write(this);
+                S_OK
+            } else {
+                interface.write(std::ptr::null_mut());
+                E_NOINTERFACE
+            }
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut c_void) -> u32 {
+        unsafe { Self::from_this(this) }
+            .references
+            .fetch_add(1, Ordering::Relaxed)
+            + 1
+    }
+
+    unsafe extern "system" fn release(this: *mut c_void) -> u32 {
+        let remaining = unsafe { Self::from_this(this) }
+            .references
+            .fetch_sub(1, Ordering::Release)
+            - 1;
+        if remaining == 0 {
+            std::sync::atomic::fence(Ordering::Acquire);
+            // SAFETY: the last reference has been released, so the object is no longer used.
+            drop(unsafe { Box::from_raw(this.cast::<Self>()) });
+        }
+        remaining
+    }
+
+    unsafe extern "system" fn get_type_info_count(_this: *mut c_void, count: *mut u32) -> HRESULT {
+        if count.is_null() {
+            return E_POINTER;
+        }
+        unsafe { count.write(0) };
+        S_OK
+    }
+
+    unsafe extern "system" fn get_type_info(
+        _this: *mut c_void,
+        _index: u32,
+        _lcid: u32,
+        type_info: *mut *mut c_void,
+    ) -> HRESULT {
+        if !type_info.is_null() {
+            unsafe { type_info.write(std::ptr::null_mut()) };
+        }
+        DISP_E_BADINDEX
+    }
+
+    unsafe extern "system" fn get_ids_of_names(
+        this: *mut c_void,
+        _iid: *const GUID,
+        names: *const PCWSTR,
+        count: u32,
+        _lcid: u32,
+        dispids: *mut i32,
+    ) -> HRESULT {
+        if names.is_null() || dispids.is_null() {
+            return E_POINTER;
+        }
+        let object = unsafe { Self::from_this(this) };
+        let count = count as usize;
+        // SAFETY: the caller provides `count` names, and room for as many `DISPID`s.
+        let (names, dispids) = unsafe {
+            (
+                slice::from_raw_parts(names, count),
+                slice::from_raw_parts_mut(dispids, count),
+            )
+        };
+        dispids.fill(DISPID_UNKNOWN);
+        let Some(name) = names.first() else {
+            return S_OK;
+        };
+        // Named arguments are not supported
+        match catch(|| object.value.dispid(&unsafe { wide_to_string(*name) })) {
+            Ok(Some(dispid)) if count == 1 => {
+                dispids[0] = dispid;
+                S_OK
+            }
+            Ok(Some(dispid)) => {
+                dispids[0] = dispid;
+                DISP_E_UNKNOWNNAME
+            }
+            Ok(None) => DISP_E_UNKNOWNNAME,
+            Err(_) => E_UNEXPECTED,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "system" fn invoke(
+        this: *mut c_void,
+        dispid: i32,
+        _iid: *const GUID,
+        _lcid: u32,
+        flags: DISPATCH_FLAGS,
+        params: *const DISPPARAMS,
+        result: *mut VARIANT,
+        exception: *mut EXCEPINFO,
+        _argument_error: *mut u32,
+    ) -> HRESULT {
+        let object = unsafe { Self::from_this(this) };
+        let no_params = DISPPARAMS::default();
+        // SAFETY: the caller provides valid parameters, if any.
+        let params = unsafe { params.as_ref() }.unwrap_or(&no_params);
+        // The member, event handler or callback may be called from within a call this thread is
+        // making, such as one that raised an event, but is not part of it
+        match catch(|| wait::detached(|| object.value.invoke(dispid, flags, params))) {
+            Ok(Ok(value)) => {
+                if !result.is_null() {
+                    // SAFETY: the caller provides an empty variant for the result.
+                    unsafe { result.write(value.into_raw()) };
+                }
+                S_OK
+            }
+            // Errors in making the call are returned, and errors from the member raised
+            Ok(Err(error)) if INVOKE_ERRORS.contains(&error.code()) => error.code(),
+            Ok(Err(error)) => unsafe {
+                Self::raise(exception, &error.message().to_string(), error.code())
+            },
+            Err(message) => {
+                tracing::error!(
+                    "{} panicked handling DISPID {dispid}: {message}",
+                    any::type_name::<T>()
+                );
+                unsafe { Self::raise(exception, &message, E_UNEXPECTED) }
+            }
+        }
+    }
+
+    /// Raise an exception from the object, filling in `exception` if the caller provided one.
+    unsafe fn raise(exception: *mut EXCEPINFO, description: &str, code: HRESULT) -> HRESULT {
+        if !exception.is_null() {
+            // SAFETY: the caller provides an empty `EXCEPINFO` to fill in.
+            unsafe {
+                exception.write(EXCEPINFO {
+                    bstrSource: ManuallyDrop::new(BSTR::from(any::type_name::<T>())),
+                    bstrDescription: ManuallyDrop::new(BSTR::from(description)),
+                    scode: code.0,
+                    ..Default::default()
+                });
+            }
+        }
+        DISP_E_EXCEPTION
+    }
+}
+
+/// The errors `IDispatch::Invoke` returns itself when a call cannot be made, rather than raising
+/// them as an exception.
+const INVOKE_ERRORS: [HRESULT; 4] = [
+    DISP_E_BADPARAMCOUNT,
+    DISP_E_MEMBERNOTFOUND,
+    DISP_E_PARAMNOTFOUND,
+    DISP_E_TYPEMISMATCH,
+];
+
+/// Run `f`, catching a panic as its message, so that it does not unwind into the caller.
+pub(crate) fn catch<R>(f: impl FnOnce() -> R) -> std::result::Result<R, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload: Box<dyn Any + Send>| {
+        payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panicked".to_string())
+    })
+}
+
+/// Read a NUL-terminated UTF-16 string.
+///
+/// `PCWSTR::to_string` relies on the C `wcslen`, which is not UTF-16 off Windows.
+unsafe fn wide_to_string(string: PCWSTR) -> String {
+    if string.is_null() {
+        return String::new();
+    }
+    unsafe {
+        let mut len = 0;
+        while *string.0.add(len) != 0 {
+            len += 1;
+        }
+        String::from_utf16_lossy(slice::from_raw_parts(string.0, len))
+    }
+}
@@ -0,0 +1,141 @@
+//! Fake objects for com-shim's own tests.
+
+use std::{cell::RefCell, rc::Rc};
+
+use windows::{
+    Win32::{
+        Foundation::E_NOTIMPL,
+        System::{
+            Com::{
+                DISPATCH_FLAGS, DISPPARAMS, EXCEPINFO, IConnectionPoint, IConnectionPoint_Impl,
+                IConnectionPointContainer, IConnectionPointContainer_Impl, IDispatch_Impl,
+                IEnumConnectionPoints, IEnumConnections, ITypeInfo,
+            },
+            Ole::CONNECT_E_NOCONNECTION,
+        },
+    },
+    core::{ComInterface, GUID, IUnknown, Interface, PCWSTR, Result, implement},
+};
+
+use crate::{IDispatch, IDispatchExt, VARIANT, Variant};
+
+/// The sinks connected to an [`EventSource`], which are `None` once disconnected.
+pub(crate) type Sinks = Rc<RefCell<Vec<Option<IDispatch>>>>;
+
+/// An object raising events through one outgoing interface.
+#[implement(IDispatch, IConnectionPointContainer)]
+pub(crate) struct EventSource {
+    iid: GUID,
+    sinks: Sinks,
+}
+
+impl EventSource {
+    /// Create an object raising events through the interface `iid`, and the sinks connected to it.
+    pub(crate) fn create(iid: GUID) -> (IDispatch, Sinks) {
+        let sinks = Sinks::default();
+        let source = Self {
+            iid,
+            sinks: sinks.clone(),
+        };
+        (source.into(), sinks)
+    }
+}
+
+impl IDispatch_Impl for EventSource {
+    fn GetTypeInfoCount(&self) -> Result<u32> {
+        Ok(0)
+    }
+
+    fn GetTypeInfo(&self, _: u32, _: u32) -> Result<ITypeInfo> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn GetIDsOfNames(
+        &self,
+        _: *const GUID,
+        _: *const PCWSTR,
+        _: u32,
+        _: u32,
+        _: *mut i32,
+    ) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn Invoke(
+        &self,
+        _: i32,
+        _: *const GUID,
+        _: u32,
+        _: DISPATCH_FLAGS,
+        _: *const DISPPARAMS,
+        _: *mut VARIANT,
+        _: *mut EXCEPINFO,
+        _: *mut u32,
+    ) -> Result<()> {
+        Err(E_NOTIMPL.into())
+    }
+}
+
+impl IConnectionPointContainer_Impl for EventSource {
+    fn EnumConnectionPoints(&self) -> Result<IEnumConnectionPoints> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn FindConnectionPoint(&self, iid: *const GUID) -> Result<IConnectionPoint> {
+        // SAFETY: the caller provides a valid IID.
+        if unsafe { *iid } != self.iid {
+            return Err(CONNECT_E_NOCONNECTION.into());
+        }
+        Ok(Point {
+            iid: self.iid,
+            sinks: self.sinks.clone(),
+        }
+        .into())
+    }
+}
+
+/// The connection point of an [`EventSource`].
+#[implement(IConnectionPoint)]
+struct Point {
+    iid: GUID,
+    sinks: Sinks,
+}
+
+impl IConnectionPoint_Impl for Point {
+    fn GetConnectionInterface(&self) -> Result<GUID> {
+        Ok(self.iid)
+    }
+
+    fn GetConnectionPointContainer(&self) -> Result<IConnectionPointContainer> {
+        Err(E_NOTIMPL.into())
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // tests connect few sinks
+    fn Advise(&self, sink: Option<&IUnknown>) -> Result<u32> {
+        // Sources ask the sink for the outgoing interface, which it implements as `IDispatch`
+        let sink = sink.ok_or(E_NOTIMPL)?;
+        let mut sink_ptr = std::ptr::null_mut();
+        // SAFETY: a reference to the interface is written to `sink_ptr` if it succeeds.
+        unsafe { sink.query(&raw const self.iid, &raw mut sink_ptr) }.ok()?;
+        let sink = unsafe { IDispatch::from_raw(sink_ptr) };
+        self.sinks.borrow_mut().push(Some(sink));
+        Ok(self.sinks.borrow().len() as u32)
+    }
+
+    fn Unadvise(&self, cookie: u32) -> Result<()> {
+        self.sinks.borrow_mut()[cookie as usize - 1] = None;
+        Ok(())
+    }
+
+    fn EnumConnections(&self) -> Result<IEnumConnections> {
+        Err(E_NOTIMPL.into())
+    }
+}
+
+/// Raise an event on every connected sink.
+pub(crate) fn raise(sinks: &Sinks, name: &str, args: &[Variant]) -> Result<()> {
+    for sink in sinks.borrow().iter().flatten() {
+        sink.call(name, args.to_vec())?;
+    }
+    Ok(())
+}
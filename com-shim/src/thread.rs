@@ -0,0 +1,799 @@
+use std::{
+    any::{self, Any},
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak, mpsc},
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, JoinHandle, ThreadId},
+    time::Duration,
+};
+
+use windows::{
+    Win32::{
+        Foundation::{
+            CO_E_NOTINITIALIZED, E_ABORT, E_UNEXPECTED, RPC_E_DISCONNECTED, RPC_E_WRONG_THREAD,
+        },
+        System::Com::IDispatch,
+    },
+    core::{Error, Result},
+};
+
+use crate::{
+    Callback, CancellationToken, Conversion, Currency, Decimal, OleDate, RetryPolicy,
+    deadline::{self, Fail, TimeoutError},
+    dry_run, server,
+    wait::Signal,
+};
+
+/// A thread in a single-threaded apartment, which owns COM objects and makes every call on them.
+///
+/// COM objects, such as an [`IDispatch`], cannot be sent between threads. Objects created on a
+/// `ComThread` stay there, and are used through a [`Remote`] handle, which can be shared between
+/// threads and sends each call to the `ComThread` to run. A call waits for its result, or returns
+/// a [`Pending`] result to wait for or `.await`.
+///
+/// Classes declared with `#[com(remote)]` in [`com_shim!`](crate::com_shim) have their members
+/// implemented for a `Remote` handle by a trait named after the class, such as `SessionRemote`,
+/// with an `_async` variant of each. Their arguments and results are passed with [`Marshal`], so
+/// that an object returned by a member is also left on the `ComThread`, and returned as a
+/// `Remote`.
+///
+/// Between calls, the thread dispatches window messages, as a single-threaded apartment must, so
+/// that calls from other apartments, callbacks and connection point events reach its objects.
+///
+/// Calls wait for as long as they take, unless a deadline is set with [`Remote::with_timeout`], or
+/// a [`CancellationToken`] is given with [`Remote::with_cancellation`]. When a `ComThread` is
+/// dropped, it releases the objects it owns and stops, after which calls through its handles fail
+/// with `RPC_E_DISCONNECTED`.
+///
+/// A [`Conversion`], [`RetryPolicy`] or [`DryRun`](crate::DryRun) chosen for the calling thread
+/// with `scope` also applies to the calls it makes through a handle.
+///
+/// ```rust
+/// use std::cell::Cell;
+///
+/// use com_shim::{ComObject, ComThread, IDispatch, Result, com_object, com_shim};
+/// use windows::Win32::Foundation::RPC_E_DISCONNECTED;
+///
+/// /// A counter, which cannot leave the thread it is created on.
+/// #[derive(Default)]
+/// struct Counter {
+///     count: Cell<i32>,
+/// }
+///
+/// com_object! {
+///     impl Counter {
+///         #[com(get)]
+///         fn count(&self) -> i32 {
+///             self.count.get()
+///         }
+///
+///         #[com(method)]
+///         fn add(&self, amount: i32) -> i32 {
+///             self.count.set(self.count.get() + amount);
+///             self.count.get()
+///         }
+///
+///         #[com(method)]
+///         fn copy(&self) -> IDispatch {
+///             Counter { count: self.count.clone() }.into_idispatch()
+///         }
+///     }
+/// }
+///
+/// com_shim! {
+///     #[com(remote)]
+///     struct CounterClass {
+///         Count: i32,
+///         fn Add(i32) -> i32,
+///         fn Copy() -> CounterClass,
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let thread = ComThread::spawn()?;
+/// let counter = thread.create(|| Ok(CounterClass::from(Counter::default().into_idispatch())))?;
+///
+/// // The handle can be shared between threads
+/// std::thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| {
+///             for _ in 0..10 {
+///                 counter.add(1).unwrap();
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(counter.count()?, 40);
+///
+/// // Returned objects are left on the thread
+/// let copy = counter.copy()?;
+/// copy.add(2)?;
+/// assert_eq!(futures::executor::block_on(copy.count_async())?, 42);
+/// assert_eq!(counter.count()?, 40);
+///
+/// drop(thread);
+/// assert_eq!(counter.count().unwrap_err().code(), RPC_E_DISCONNECTED);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ComThread {
+    channel: Arc<Channel>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ComThread {
+    /// Start a new thread, and initialize COM on it in a single-threaded apartment.
+    ///
+    /// # Errors
+    ///
+    /// Fails if COM cannot be initialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread cannot be started.
+    pub fn spawn() -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let (started, start) = mpsc::sync_channel(1);
+        let handle = thread::Builder::new()
+            .name("com-shim".to_string())
+            .spawn(move || {
+                let signal = match Signal::new() {
+                    Ok(signal) => signal,
+                    Err(error) => {
+                        let _ = started.send(Err(error));
+                        return;
+                    }
+                };
+                let channel = Arc::new(Channel {
+                    sender,
+                    signal,
+                    thread: thread::current().id(),
+                });
+                if let Err(error) = initialize() {
+                    let _ = started.send(Err(error));
+                    return;
+                }
+                APARTMENT.with_borrow_mut(|apartment| {
+                    *apartment = Some(Apartment {
+                        channel: channel.clone(),
+                        objects: HashMap::new(),
+                        next_id: 0,
+                    });
+                });
+                let _ = started.send(Ok(channel.clone()));
+                serve(&receiver, &channel.signal);
+
+                // Release everything while COM is still initialized
+                drop(receiver);
+                drop(APARTMENT.with_borrow_mut(Option::take));
+                uninitialize();
+            })
+            .expect("failed to spawn a COM thread");
+        let channel = start.recv().unwrap_or(Err(E_UNEXPECTED.into()))?;
+        Ok(Self {
+            channel,
+            handle: Some(handle),
+        })
+    }
+
+    /// Run a function on the thread, waiting for its result.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the function fails or panics, or the thread has stopped.
+    pub fn run<R: Send + 'static>(
+        &self,
+        f: impl FnOnce() -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.run_async(f).wait()
+    }
+
+    /// Run a function on the thread, without waiting for its result.
+    pub fn run_async<R: Send + 'static>(
+        &self,
+        f: impl FnOnce() -> Result<R> + Send + 'static,
+    ) -> Pending<R> {
+        self.channel.submit("run", &CallOptions::default(), f)
+    }
+
+    /// Run a function on the thread, waiting for its result until `timeout` passes, dispatching
+    /// window messages meanwhile. The error for the timeout names `member`.
+    pub(crate) fn run_with_timeout<R: Send + 'static>(
+        &self,
+        member: impl Into<Cow<'static, str>>,
+        timeout: Duration,
+        f: impl FnOnce() -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        let options = CallOptions {
+            timeout: Some(timeout),
+            cancellation: None,
+        };
+        self.channel.submit(member, &options, f).wait_dispatching()
+    }
+
+    /// Create an object on the thread, which is then used through the returned handle.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the object cannot be created, or the thread has stopped.
+    pub fn create<T: 'static>(
+        &self,
+        f: impl FnOnce() -> Result<T> + Send + 'static,
+    ) -> Result<Remote<T>> {
+        self.run(move || Remote::new(f()?))
+    }
+}
+
+impl Drop for ComThread {
+    fn drop(&mut self) {
+        self.channel.send(Message::Stop);
+        if let Some(handle) = self.handle.take() {
+            // A thread cannot wait for itself to stop
+            if handle.thread().id() != thread::current().id() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// Initialize COM on the current thread, in a single-threaded apartment.
+#[cfg(windows)]
+fn initialize() -> Result<()> {
+    use windows::Win32::System::Com::{COINIT_APARTMENTTHREADED, CoInitializeEx};
+
+    // SAFETY: COM is uninitialized when the thread stops.
+    unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }
+}
+
+#[cfg(windows)]
+fn uninitialize() {
+    // SAFETY: COM was initialized when the thread started, and nothing on it is used after this.
+    unsafe { windows::Win32::System::Com::CoUninitialize() };
+}
+
+/// Off Windows, objects are in-process and need no apartment.
+#[cfg(not(windows))]
+#[allow(clippy::unnecessary_wraps)]
+fn initialize() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn uninitialize() {}
+
+/// Run the messages sent to a thread, until it is stopped.
+///
+/// Between messages, the thread waits with [`Signal::wait`], which dispatches the window messages
+/// that calls from other apartments, callbacks and events are delivered by.
+fn serve(receiver: &mpsc::Receiver<Message>, signal: &Signal) {
+    loop {
+        let message = match receiver.try_recv() {
+            Ok(message) => message,
+            Err(mpsc::TryRecvError::Empty) => {
+                signal.wait();
+                continue;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => return,
+        };
+        match message {
+            Message::Run(job) => {
+                if let Err(message) = server::catch(job) {
+                    tracing::error!("A call on a COM thread panicked: {message}");
+                }
+            }
+            Message::Release(id) => {
+                // The object is dropped after the apartment is released, in case it reenters it
+                let object =
+                    APARTMENT.with_borrow_mut(|apartment| apartment.as_mut()?.objects.remove(&id));
+                drop(object);
+            }
+            Message::Stop => return,
+        }
+    }
+}
+
+/// A function to run on a COM thread.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A message sent to a COM thread.
+enum Message {
+    Run(Job),
+    Release(u64),
+    Stop,
+}
+
+/// The channel to a COM thread, shared by its handles.
+struct Channel {
+    sender: mpsc::Sender<Message>,
+    /// Wakes the thread when a message is sent.
+    signal: Signal,
+    thread: ThreadId,
+}
+
+impl Channel {
+    /// Send a message to the thread. If it has stopped, the message is dropped.
+    fn send(&self, message: Message) {
+        if self.sender.send(message).is_ok() {
+            self.signal.set();
+        }
+    }
+
+    /// Run a function on the thread, or immediately if this is the thread.
+    fn submit<R: Send + 'static>(
+        &self,
+        member: impl Into<Cow<'static, str>>,
+        options: &CallOptions,
+        f: impl FnOnce() -> Result<R> + Send + 'static,
+    ) -> Pending<R> {
+        let reply = Arc::new(Reply {
+            state: Mutex::new(ReplyState {
+                value: None,
+                completed: false,
+                waker: None,
+            }),
+            ready: Condvar::new(),
+        });
+        let replier = Replier(Some(reply.clone()));
+        let local = thread::current().id() == self.thread;
+        // The conversion, retry policy and dry run chosen on this thread apply to the call. A dry
+        // run already applies to a call made on this thread.
+        let conversion = Conversion::chosen();
+        let policy = RetryPolicy::chosen();
+        let dry_run = if local {
+            None
+        } else {
+            dry_run::Carried::current()
+        };
+        let cancellation = options.cancellation.clone();
+        let job = move || {
+            if cancellation
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                replier.send(Err(E_ABORT.into()));
+                return;
+            }
+            let f = || match dry_run {
+                Some(dry_run) => dry_run.scope(f),
+                None => f(),
+            };
+            let f = || match policy {
+                Some(policy) => policy.scope(f),
+                None => f(),
+            };
+            replier.send(match conversion {
+                Some(conversion) => conversion.scope(f),
+                None => f(),
+            });
+        };
+        if local {
+            job();
+        } else {
+            // If the thread has stopped, the job is dropped, which replies with an error
+            self.send(Message::Run(Box::new(job)));
+            let call: Weak<dyn Fail> = Arc::downgrade(&reply) as Weak<Reply<R>>;
+            if let Some(timeout) = options.timeout {
+                deadline::expire(call.clone(), TimeoutError::new(member, timeout));
+            }
+            if let Some(token) = &options.cancellation {
+                token.register(call);
+            }
+        }
+        Pending { reply }
+    }
+}
+
+/// How long calls through a handle are waited for, and whether they can be cancelled.
+#[derive(Clone, Default)]
+struct CallOptions {
+    timeout: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+}
+
+/// The objects owned by a COM thread.
+struct Apartment {
+    channel: Arc<Channel>,
+    objects: HashMap<u64, Rc<dyn Any>>,
+    next_id: u64,
+}
+
+thread_local! {
+    /// The apartment of the current thread, if it is a COM thread.
+    static APARTMENT: RefCell<Option<Apartment>> = const { RefCell::new(None) };
+}
+
+/// A handle to an object owned by a [`ComThread`], which can be sent and shared between threads.
+///
+/// The object is released once every clone of its handle is dropped.
+pub struct Remote<T> {
+    entry: Arc<Entry>,
+    options: CallOptions,
+    object: PhantomData<fn() -> T>,
+}
+
+/// An object in an apartment, which is released when dropped.
+struct Entry {
+    id: u64,
+    channel: Arc<Channel>,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        self.channel.send(Message::Release(self.id));
+    }
+}
+
+impl<T: 'static> Remote<T> {
+    /// Move an object into the apartment of the current thread, which must be a [`ComThread`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with `CO_E_NOTINITIALIZED` if this is not a `ComThread`.
+    pub fn new(object: T) -> Result<Self> {
+        APARTMENT.with_borrow_mut(|apartment| {
+            let apartment = apartment.as_mut().ok_or(CO_E_NOTINITIALIZED)?;
+            let id = apartment.next_id;
+            apartment.next_id += 1;
+            apartment.objects.insert(id, Rc::new(object));
+            Ok(Self {
+                entry: Arc::new(Entry {
+                    id,
+                    channel: apartment.channel.clone(),
+                }),
+                options: CallOptions::default(),
+                object: PhantomData,
+            })
+        })
+    }
+
+    /// Call a function with the object on its thread, waiting for its result.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the function fails or panics, or the thread has stopped.
+    pub fn call<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&T) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.call_async(f).wait()
+    }
+
+    /// Call a function with the object on its thread, without waiting for its result.
+    pub fn call_async<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&T) -> Result<R> + Send + 'static,
+    ) -> Pending<R> {
+        self.call_member_async(any::type_name::<T>(), f)
+    }
+
+    /// Call a function with the object on its thread, naming the member it calls if it times
+    /// out, and wait for its result.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the function fails or panics, the deadline passes, or the thread has stopped.
+    pub fn call_member<R: Send + 'static>(
+        &self,
+        member: &'static str,
+        f: impl FnOnce(&T) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.call_member_async(member, f).wait()
+    }
+
+    /// Call a function with the object on its thread, naming the member it calls if it times
+    /// out, without waiting for its result.
+    pub fn call_member_async<R: Send + 'static>(
+        &self,
+        member: &'static str,
+        f: impl FnOnce(&T) -> Result<R> + Send + 'static,
+    ) -> Pending<R> {
+        let id = self.entry.id;
+        self.entry
+            .channel
+            .submit(member, &self.options, move || f(&*local::<T>(id)?))
+    }
+
+    /// A handle to the same object, which stops waiting for each call after `timeout`, failing
+    /// with `RPC_E_TIMEOUT` and a message naming the member, which is read back with
+    /// [`TimeoutError::from_error`](crate::TimeoutError::from_error).
+    #[must_use]
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        let mut remote = self.clone();
+        remote.options.timeout = Some(timeout);
+        remote
+    }
+
+    /// A handle to the same object, whose calls fail with `E_ABORT` once `token` is cancelled.
+    #[must_use]
+    pub fn with_cancellation(&self, token: CancellationToken) -> Self {
+        let mut remote = self.clone();
+        remote.options.cancellation = Some(token);
+        remote
+    }
+
+    /// Call a function with the object, from the thread that owns it.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `RPC_E_WRONG_THREAD` if this is not the thread that owns the object.
+    pub fn with_local<R>(&self, f: impl FnOnce(&T) -> R) -> Result<R> {
+        if thread::current().id() != self.entry.channel.thread {
+            return Err(RPC_E_WRONG_THREAD.into());
+        }
+        Ok(f(&*local::<T>(self.entry.id)?))
+    }
+}
+
+impl<T> Clone for Remote<T> {
+    fn clone(&self) -> Self {
+        Self {
+            entry: self.entry.clone(),
+            options: self.options.clone(),
+            object: PhantomData,
+        }
+    }
+}
+
+/// Find an object in the apartment of the current thread.
+fn local<T: 'static>(id: u64) -> Result<Rc<T>> {
+    let object = APARTMENT.with_borrow(|apartment| {
+        let apartment = apartment.as_ref().ok_or(RPC_E_WRONG_THREAD)?;
+        apartment
+            .objects
+            .get(&id)
+            .cloned()
+            .ok_or(RPC_E_DISCONNECTED)
+    })?;
+    object.downcast().map_err(|_| E_UNEXPECTED.into())
+}
+
+/// The result of a call made on a [`ComThread`], which can be waited for or awaited.
+#[must_use = "the call is made even if its result is not used"]
+pub struct Pending<R> {
+    reply: Arc<Reply<R>>,
+}
+
+struct Reply<R> {
+    state: Mutex<ReplyState<R>>,
+    ready: Condvar,
+}
+
+struct ReplyState<R> {
+    value: Option<Result<R>>,
+    completed: bool,
+    waker: Option<Waker>,
+}
+
+impl<R> Pending<R> {
+    /// Block until the call is complete.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the call fails or panics, its deadline passes, it is cancelled, or the thread has
+    /// stopped.
+    pub fn wait(self) -> Result<R> {
+        let mut state = lock(&self.reply.state);
+        loop {
+            if let Some(value) = state.value.take() {
+                return value;
+            }
+            state = self
+                .reply
+                .ready
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Block until the call is complete, dispatching window messages meanwhile, as a thread in a
+    /// single-threaded apartment must.
+    fn wait_dispatching(mut self) -> Result<R> {
+        let signal = Arc::new(Wakeup(Signal::new()?));
+        let waker = Waker::from(signal.clone());
+        let mut context = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(value) = Pin::new(&mut self).poll(&mut context) {
+                return value;
+            }
+            signal.0.wait();
+        }
+    }
+}
+
+/// Wakes a thread waiting for a [`Pending`] call with [`Pending::wait_dispatching`].
+struct Wakeup(Signal);
+
+impl Wake for Wakeup {
+    fn wake(self: Arc<Self>) {
+        self.0.set();
+    }
+}
+
+impl<R> Future for Pending<R> {
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.reply.state);
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(value);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Completes a [`Pending`] call, with an error if it is dropped without a result.
+struct Replier<R>(Option<Arc<Reply<R>>>);
+
+impl<R> Replier<R> {
+    fn send(mut self, value: Result<R>) {
+        if let Some(reply) = self.0.take() {
+            reply.complete(value);
+        }
+    }
+}
+
+impl<R> Drop for Replier<R> {
+    fn drop(&mut self) {
+        if let Some(reply) = self.0.take() {
+            let error = if thread::panicking() {
+                E_UNEXPECTED
+            } else {
+                RPC_E_DISCONNECTED
+            };
+            reply.complete(Err(error.into()));
+        }
+    }
+}
+
+impl<R> Reply<R> {
+    /// Complete the call, unless it has already failed.
+    fn complete(&self, value: Result<R>) {
+        let mut state = lock(&self.state);
+        if state.completed {
+            return;
+        }
+        state.completed = true;
+        state.value = Some(value);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.ready.notify_all();
+    }
+}
+
+impl<R: Send> Fail for Reply<R> {
+    fn fail(&self, error: Error) {
+        self.complete(Err(error));
+    }
+}
+
+fn lock<R>(state: &Mutex<ReplyState<R>>) -> MutexGuard<'_, ReplyState<R>> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A value passed to or returned from an object on a [`ComThread`], as a value that can be sent
+/// between threads.
+///
+/// Values that can already be sent are passed as they are, and objects are left on the thread and
+/// passed as a [`Remote`] handle. This is implemented for classes declared with
+/// [`com_shim!`](crate::com_shim), and can be implemented for other types with
+/// `type Remote = Self` if they can be sent.
+pub trait Marshal: Sized {
+    /// The value that is sent between threads.
+    type Remote: Send + 'static;
+
+    /// Convert a value on a `ComThread` into one that can be sent to another thread.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the value must be left on a `ComThread`, and this is not one.
+    fn marshal(self) -> Result<Self::Remote>;
+
+    /// Convert a value sent from another thread back on the `ComThread`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the value refers to an object owned by another thread.
+    fn unmarshal(remote: Self::Remote) -> Result<Self>;
+}
+
+/// Implement [`Marshal`] for types that are passed as they are.
+macro_rules! marshal_by_value {
+    ($($(#[$attribute:meta])* $ty:ty;)*) => {
+        $(
+            $(#[$attribute])*
+            impl Marshal for $ty {
+                type Remote = Self;
+
+                fn marshal(self) -> Result<Self> {
+                    Ok(self)
+                }
+
+                fn unmarshal(remote: Self) -> Result<Self> {
+                    Ok(remote)
+                }
+            }
+        )*
+    };
+}
+
+marshal_by_value! {
+    ();
+    bool;
+    i8;
+    i16;
+    i32;
+    i64;
+    isize;
+    u8;
+    u16;
+    u32;
+    u64;
+    usize;
+    f32;
+    f64;
+    String;
+    OleDate;
+    Currency;
+    Decimal;
+    #[cfg(feature = "chrono")]
+    chrono::NaiveDateTime;
+    #[cfg(feature = "time")]
+    time::PrimitiveDateTime;
+    #[cfg(feature = "rust_decimal")]
+    rust_decimal::Decimal;
+    #[cfg(feature = "serde_json")]
+    serde_json::Value;
+}
+
+/// Implement [`Marshal`] for objects, which are left on the thread.
+macro_rules! marshal_by_reference {
+    ($($ty:ty),*) => {
+        $(
+            impl Marshal for $ty {
+                type Remote = Remote<Self>;
+
+                fn marshal(self) -> Result<Remote<Self>> {
+                    Remote::new(self)
+                }
+
+                fn unmarshal(remote: Remote<Self>) -> Result<Self> {
+                    remote.with_local(Clone::clone)
+                }
+            }
+        )*
+    };
+}
+
+marshal_by_reference!(IDispatch, Callback);
+
+impl<T: Marshal> Marshal for Option<T> {
+    type Remote = Option<T::Remote>;
+
+    fn marshal(self) -> Result<Self::Remote> {
+        self.map(T::marshal).transpose()
+    }
+
+    fn unmarshal(remote: Self::Remote) -> Result<Self> {
+        remote.map(T::unmarshal).transpose()
+    }
+}
+
+impl<T: Marshal> Marshal for Vec<T> {
+    type Remote = Vec<T::Remote>;
+
+    fn marshal(self) -> Result<Self::Remote> {
+        self.into_iter().map(T::marshal).collect()
+    }
+
+    fn unmarshal(remote: Self::Remote) -> Result<Self> {
+        remote.into_iter().map(T::unmarshal).collect()
+    }
+}
@@ -1,8 +1,22 @@
+use std::{
+    cell::{Cell, RefCell},
+    mem::ManuallyDrop,
+    thread::LocalKey,
+};
+
 use windows::{
-    Win32::System::{Com::*, Ole::*, Variant::*},
-    core::*,
+    Win32::{
+        Foundation::DISP_E_EXCEPTION,
+        System::{
+            Com::{DISPATCH_FLAGS, DISPPARAMS, EXCEPINFO, IDispatch},
+            Ole::DISPID_PROPERTYPUT,
+        },
+    },
+    core::{Error, GUID, HRESULT, HSTRING, PCWSTR, Result},
 };
 
+use crate::{Variant, deadline, retry};
+
 pub(crate) fn get_method_dispid<S>(disp: &IDispatch, name: S) -> Result<i32>
 where
     S: AsRef<str>,
@@ -15,15 +29,95 @@ where
         let lcid = 0x09; // en
         let mut dispidmember = 0;
 
-        disp.GetIDsOfNames(&riid, &rgsznames, cnames, lcid, &mut dispidmember)?;
+        disp.GetIDsOfNames(
+            &raw const riid,
+            &raw const rgsznames,
+            cnames,
+            lcid,
+            &raw mut dispidmember,
+        )?;
         Ok(dispidmember)
     }
 }
 
-pub(crate) fn assemble_dispparams_get(args: &mut Vec<VARIANT>) -> DISPPARAMS {
+/// Find a member by name and invoke it with `args`, retrying it by the current policy. If a
+/// timeout was chosen for the object the call is made on, each attempt is made on a worker thread.
+pub(crate) fn dispatch(
+    disp: &IDispatch,
+    name: &str,
+    flags: DISPATCH_FLAGS,
+    mut args: Vec<Variant>,
+    assemble: fn(&mut Vec<Variant>) -> DISPPARAMS,
+) -> Result<Variant> {
+    if let Some(timeout) = deadline::current() {
+        return retry::run(|| deadline::invoke(disp, name, flags, &args, assemble, timeout));
+    }
+    let params = assemble(&mut args);
+    retry::run(|| {
+        let dispid = get_method_dispid(disp, name)?;
+        invoke(disp, dispid, flags, &params)
+    })
+}
+
+/// Invoke a member, reading the description of any exception it raises into the error.
+pub(crate) fn invoke(
+    disp: &IDispatch,
+    dispid: i32,
+    flags: DISPATCH_FLAGS,
+    params: &DISPPARAMS,
+) -> Result<Variant> {
+    let iid_null = GUID::zeroed();
+    let mut result = Variant::null();
+    let mut exception = EXCEPINFO::default();
+    let outcome = unsafe {
+        disp.Invoke(
+            dispid,
+            &raw const iid_null,
+            0,
+            flags,
+            params,
+            Some(result.as_raw_mut()),
+            Some(&raw mut exception),
+            None,
+        )
+    };
+    match outcome {
+        Ok(()) => Ok(result),
+        Err(e) if e.code() == DISP_E_EXCEPTION => Err(exception_error(exception)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Convert an exception raised by a member into an error with its code, if it has one, freeing
+/// its strings.
+fn exception_error(mut exception: EXCEPINFO) -> Error {
+    if let Some(fill) = exception.pfnDeferredFillIn {
+        // SAFETY: the member provided this function to fill in the exception.
+        let _ = unsafe { fill(&raw mut exception) };
+    }
+    let source = ManuallyDrop::into_inner(exception.bstrSource);
+    let description = ManuallyDrop::into_inner(exception.bstrDescription);
+    drop(ManuallyDrop::into_inner(exception.bstrHelpFile));
+    tracing::debug!("Exception raised by {source}: {description}");
+    let code = HRESULT(exception.scode);
+    let code = if code.is_err() {
+        code
+    } else {
+        DISP_E_EXCEPTION
+    };
+    if description.is_empty() {
+        code.into()
+    } else {
+        Error::new(code, HSTRING::from(description.to_string()))
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)] // argument lists are never this long
+pub(crate) fn assemble_dispparams_get(args: &mut Vec<Variant>) -> DISPPARAMS {
     args.reverse(); // https://stackoverflow.com/a/65255739
     DISPPARAMS {
-        rgvarg: args.as_mut_ptr(),
+        // `Variant` is `#[repr(transparent)]` over `VARIANT`
+        rgvarg: args.as_mut_ptr().cast(),
         cArgs: args.len() as u32,
         ..Default::default()
     }
@@ -31,11 +125,49 @@ pub(crate) fn assemble_dispparams_get(args: &mut Vec<VARIANT>) -> DISPPARAMS {
 
 static PUT_NAMED_ARGS: [i32; 1] = [DISPID_PROPERTYPUT];
 
-pub(crate) fn assemble_dispparams_put(args: &mut Vec<VARIANT>) -> DISPPARAMS {
+#[allow(clippy::cast_possible_truncation)] // argument lists are never this long
+pub(crate) fn assemble_dispparams_put(args: &mut Vec<Variant>) -> DISPPARAMS {
     DISPPARAMS {
-        rgvarg: args.as_mut_ptr(),
+        // `Variant` is `#[repr(transparent)]` over `VARIANT`
+        rgvarg: args.as_mut_ptr().cast(),
         cArgs: args.len() as u32,
         cNamedArgs: PUT_NAMED_ARGS.len() as u32,
         rgdispidNamedArgs: PUT_NAMED_ARGS.as_ptr().cast_mut(),
     }
 }
+
+/// A thread-local slot whose value can be swapped.
+pub(crate) trait Slot<T> {
+    fn swap(&self, value: T) -> T;
+}
+
+impl<T> Slot<T> for Cell<T> {
+    fn swap(&self, value: T) -> T {
+        self.replace(value)
+    }
+}
+
+impl<T> Slot<T> for RefCell<T> {
+    fn swap(&self, value: T) -> T {
+        self.replace(value)
+    }
+}
+
+/// Run `f` with the thread-local `key` set to `value`, restoring it afterwards even if `f` panics.
+pub(crate) fn scoped<S: Slot<T>, T, R>(
+    key: &'static LocalKey<S>,
+    value: T,
+    f: impl FnOnce() -> R,
+) -> R {
+    struct Restore<S: Slot<T> + 'static, T>(&'static LocalKey<S>, Option<T>);
+    impl<S: Slot<T>, T> Drop for Restore<S, T> {
+        fn drop(&mut self) {
+            if let Some(value) = self.1.take() {
+                self.0.with(|slot| slot.swap(value));
+            }
+        }
+    }
+
+    let _restore = Restore(key, Some(key.with(|slot| slot.swap(value))));
+    f()
+}
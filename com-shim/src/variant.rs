@@ -0,0 +1,202 @@
+use std::{fmt, mem::ManuallyDrop, ops::Deref};
+
+use windows::{
+    Win32::System::Variant::{
+        VAR_CHANGE_FLAGS, VARENUM, VARIANT, VARIANT_0_0, VT_EMPTY, VT_ERROR, VT_NULL, VariantClear,
+        VariantCopy,
+    },
+    core::{Error, Result},
+};
+
+/// An owned [`VARIANT`], which is cleared when dropped, releasing any string, array or object
+/// it holds.
+///
+/// This is the type passed to and returned from [`IDispatchExt`](crate::IDispatchExt), and it
+/// dereferences to the underlying [`VARIANT`] for reading.
+///
+/// ```rust
+/// # extern crate com_shim_fake_win32;
+/// use com_shim::{ToVariant, Variant, VariantExt};
+///
+/// let text = "Hello".to_variant();
+/// let copy = text.clone();
+/// drop(text);
+/// assert!(!copy.is_nothing());
+///
+/// assert!(Variant::null().is_nothing());
+/// assert!(Variant::default().is_nothing());
+/// ```
+///
+/// Arguments are cleared once a call has been made, and results when they are dropped, so no
+/// strings or object references are leaked however many calls are made.
+///
+/// Cloning a variant copies any string or array it holds, and adds a reference to any object. If
+/// the copy fails, because memory runs out or the type is not one a variant can hold, the clone
+/// holds the error as a `VT_ERROR` instead of panicking. [`Variant::copy`] reports the error.
+#[repr(transparent)]
+pub struct Variant(VARIANT);
+
+impl Variant {
+    /// Create a `VT_NULL` variant.
+    #[must_use]
+    pub fn null() -> Self {
+        Self::from_v00(VARIANT_0_0 {
+            vt: VT_NULL,
+            ..Default::default()
+        })
+    }
+
+    /// Create a `VT_EMPTY` variant.
+    #[must_use]
+    pub fn empty() -> Self {
+        Self::from_v00(VARIANT_0_0 {
+            vt: VT_EMPTY,
+            ..Default::default()
+        })
+    }
+
+    /// Take ownership of a raw [`VARIANT`], clearing it when this is dropped.
+    ///
+    /// # Safety
+    ///
+    /// The [`VARIANT`] must be valid, and must own any value it references, which must not be
+    /// freed elsewhere.
+    #[must_use]
+    pub unsafe fn from_raw(variant: VARIANT) -> Self {
+        Self(variant)
+    }
+
+    /// Release ownership of the underlying [`VARIANT`], which must then be cleared by the caller.
+    #[must_use]
+    pub fn into_raw(self) -> VARIANT {
+        let this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so the value is moved out exactly once.
+        unsafe { std::ptr::read(&raw const this.0) }
+    }
+
+    /// Borrow the underlying [`VARIANT`].
+    #[must_use]
+    pub fn as_raw(&self) -> &VARIANT {
+        &self.0
+    }
+
+    /// Mutably borrow the underlying [`VARIANT`].
+    ///
+    /// # Safety
+    ///
+    /// The [`VARIANT`] must be left valid and owning any value it references.
+    pub unsafe fn as_raw_mut(&mut self) -> &mut VARIANT {
+        &mut self.0
+    }
+
+    /// The type of the value held.
+    #[must_use]
+    pub fn vt(&self) -> VARENUM {
+        // SAFETY: `vt` is valid for every variant.
+        unsafe { self.0.Anonymous.Anonymous.vt }
+    }
+
+    /// Copy a borrowed [`VARIANT`], including any string, array or object it references.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the value cannot be copied.
+    pub fn copy(variant: &VARIANT) -> Result<Self> {
+        let mut new = Variant::empty();
+        // SAFETY: both variants are valid.
+        unsafe { VariantCopy(&raw mut new.0, variant) }?;
+        Ok(new)
+    }
+
+    /// Create a variant from a value and its type. The value must be owned by the variant.
+    pub(crate) fn from_v00(v00: VARIANT_0_0) -> Self {
+        let mut variant = VARIANT::default();
+        variant.Anonymous.Anonymous = ManuallyDrop::new(v00);
+        Self(variant)
+    }
+
+    /// Create a `VT_ERROR` variant holding the code of `error`, for conversions that cannot fail.
+    pub(crate) fn from_error(error: &Error) -> Self {
+        let mut v00 = VARIANT_0_0 {
+            vt: VT_ERROR,
+            ..Default::default()
+        };
+        v00.Anonymous.scode = error.code().0;
+        Self::from_v00(v00)
+    }
+}
+
+/// Convert a [`VARIANT`] into a new variant of type `vt`, following OLE Automation coercion rules.
+pub(crate) fn change_type(variant: &VARIANT, vt: VARENUM) -> Result<Variant> {
+    change_type_with(variant, vt, VAR_CHANGE_FLAGS(0))
+}
+
+/// Convert a [`VARIANT`] into a new variant of type `vt`, with `VariantChangeType` flags.
+#[cfg(windows)]
+pub(crate) fn change_type_with(
+    variant: &VARIANT,
+    vt: VARENUM,
+    flags: VAR_CHANGE_FLAGS,
+) -> Result<Variant> {
+    use windows::Win32::System::Variant::VariantChangeType;
+
+    tracing::debug!("Own type: {:?}", unsafe { variant.Anonymous.Anonymous.vt });
+    let mut new = Variant::empty();
+    unsafe {
+        VariantChangeType(&raw mut new.0, variant, flags, vt)?;
+    }
+    Ok(new)
+}
+
+/// Convert a [`VARIANT`] into a new variant of type `vt`, with `VariantChangeType` flags.
+///
+/// There is no `oleaut32` off Windows, so the coercion rules are followed in Rust.
+#[cfg(not(windows))]
+pub(crate) fn change_type_with(
+    variant: &VARIANT,
+    vt: VARENUM,
+    flags: VAR_CHANGE_FLAGS,
+) -> Result<Variant> {
+    tracing::debug!("Own type: {:?}", unsafe { variant.Anonymous.Anonymous.vt });
+    crate::coerce::change_type(variant, vt, flags)
+}
+
+impl Drop for Variant {
+    fn drop(&mut self) {
+        // SAFETY: the variant is valid and owns its value.
+        if let Err(e) = unsafe { VariantClear(&raw mut self.0) } {
+            tracing::warn!("Failed to clear variant: {e:?}");
+        }
+    }
+}
+
+impl Clone for Variant {
+    fn clone(&self) -> Self {
+        Variant::copy(&self.0).unwrap_or_else(|error| {
+            tracing::error!("Failed to copy variant: {error}");
+            Self::from_error(&error)
+        })
+    }
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Deref for Variant {
+    type Target = VARIANT;
+
+    fn deref(&self) -> &VARIANT {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Variant")
+            .field("vt", &self.vt())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,238 @@
+//! Waiting on a thread that may be in a single-threaded apartment.
+//!
+//! A single-threaded apartment receives calls from other apartments, callbacks and events as
+//! window messages, so a thread in one must keep dispatching them while it waits, or they are not
+//! delivered until it stops waiting.
+//!
+//! The messages are dispatched outside the call the thread is waiting in, so that the calls they
+//! deliver are not taken for part of it: they are not cached, recorded by a dry run or retried by
+//! its policy. The same goes for calls an object implemented in Rust receives, such as an event
+//! handler called while the thread is making the call that raised the event.
+
+use std::time::Duration;
+
+pub(crate) use imp::Signal;
+
+use crate::{cache, conversion, deadline, dry_run, member, retry};
+
+/// Wait for `duration`, dispatching window messages meanwhile.
+pub(crate) fn sleep(duration: Duration) {
+    detached(|| imp::sleep(duration));
+}
+
+/// Run `f` outside any call being made on this thread, with none of the scopes chosen for it.
+pub(crate) fn detached<R>(f: impl FnOnce() -> R) -> R {
+    member::detached(|| {
+        dry_run::detached(|| {
+            cache::detached(|| retry::detached(|| conversion::detached(|| deadline::detached(f))))
+        })
+    })
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::time::{Duration, Instant};
+
+    use windows::{
+        Win32::{
+            Foundation::{CloseHandle, HANDLE, HWND, WAIT_FAILED, WAIT_OBJECT_0},
+            System::Threading::{CreateEventW, INFINITE, SetEvent},
+            UI::WindowsAndMessaging::{
+                DispatchMessageW, MSG, MWMO_INPUTAVAILABLE, MsgWaitForMultipleObjectsEx, PM_REMOVE,
+                PeekMessageW, QS_ALLINPUT, TranslateMessage,
+            },
+        },
+        core::{PCWSTR, Result},
+    };
+
+    /// Wakes a thread waiting with [`Signal::wait`].
+    pub(crate) struct Signal(HANDLE);
+
+    impl Signal {
+        pub(crate) fn new() -> Result<Self> {
+            // SAFETY: an unnamed auto-reset event, which is closed when dropped.
+            unsafe { CreateEventW(None, false, false, PCWSTR::null()) }.map(Self)
+        }
+
+        /// Wake the waiting thread, or the next thread to wait if none is.
+        pub(crate) fn set(&self) {
+            // SAFETY: the event is open until this is dropped.
+            if let Err(error) = unsafe { SetEvent(self.0) } {
+                tracing::error!("Failed to wake a COM thread: {error}");
+            }
+        }
+
+        /// Wait until the signal is set, dispatching window messages meanwhile.
+        pub(crate) fn wait(&self) {
+            super::detached(|| wait(Some(self.0), None));
+        }
+    }
+
+    impl Drop for Signal {
+        fn drop(&mut self) {
+            // SAFETY: the event is not used after this.
+            let _ = unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    /// Wait for `duration`, dispatching window messages meanwhile.
+    pub(crate) fn sleep(duration: Duration) {
+        wait(None, Some(duration));
+    }
+
+    /// Wait until `handle` is signalled, or `timeout` passes, dispatching window messages
+    /// meanwhile.
+    fn wait(handle: Option<HANDLE>, timeout: Option<Duration>) {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let handles = handle.as_slice();
+        loop {
+            let milliseconds = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return;
+                    }
+                    // Waits are rounded up, so that they are never shorter than asked for
+                    u32::try_from(remaining.as_millis() + 1).unwrap_or(INFINITE - 1)
+                }
+                None => INFINITE,
+            };
+            // SAFETY: the handles are open for the duration of the call.
+            let result = unsafe {
+                MsgWaitForMultipleObjectsEx(
+                    Some(handles),
+                    milliseconds,
+                    QS_ALLINPUT,
+                    MWMO_INPUTAVAILABLE,
+                )
+            };
+            // With no handles, `WAIT_OBJECT_0` means that messages are waiting
+            if !handles.is_empty() && result == WAIT_OBJECT_0 {
+                return;
+            }
+            if result == WAIT_FAILED {
+                tracing::error!(
+                    "Failed to wait for messages: {}",
+                    windows::core::Error::from_win32()
+                );
+                if let Some(deadline) = deadline {
+                    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                }
+                return;
+            }
+            dispatch_messages();
+        }
+    }
+
+    /// Dispatch every window message waiting for this thread.
+    fn dispatch_messages() {
+        let mut message = MSG::default();
+        // SAFETY: `message` is written by `PeekMessageW` before it is dispatched.
+        unsafe {
+            while PeekMessageW(&raw mut message, HWND::default(), 0, 0, PM_REMOVE).as_bool() {
+                let _ = TranslateMessage(&raw const message);
+                DispatchMessageW(&raw const message);
+            }
+        }
+    }
+}
+
+/// Off Windows, objects are in-process and no messages are sent to a thread, so it waits without
+/// dispatching anything.
+#[cfg(not(windows))]
+mod imp {
+    use std::sync::{Condvar, Mutex, PoisonError};
+    pub(crate) use std::thread::sleep;
+
+    use windows::core::Result;
+
+    /// Wakes a thread waiting with [`Signal::wait`].
+    #[derive(Default)]
+    pub(crate) struct Signal {
+        set: Mutex<bool>,
+        changed: Condvar,
+    }
+
+    impl Signal {
+        #[allow(clippy::unnecessary_wraps)] // creating an event can fail on Windows
+        pub(crate) fn new() -> Result<Self> {
+            Ok(Self::default())
+        }
+
+        /// Wake the waiting thread, or the next thread to wait if none is.
+        pub(crate) fn set(&self) {
+            *self.set.lock().unwrap_or_else(PoisonError::into_inner) = true;
+            self.changed.notify_one();
+        }
+
+        /// Wait until the signal is set.
+        pub(crate) fn wait(&self) {
+            let set = self.set.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut set = self
+                .changed
+                .wait_while(set, |set| !*set)
+                .unwrap_or_else(PoisonError::into_inner);
+            *set = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use windows::core::GUID;
+
+    use super::detached;
+    use crate::{
+        CallCache, Conversion, DryRun, Effect, Member, MemberKind, RetryPolicy, ToVariant, cache,
+        conversion, deadline, dry_run, retry, test_support::EventSource,
+    };
+
+    #[test]
+    fn messages_are_dispatched_outside_the_call_being_waited_in() {
+        let (object, _) = EventSource::create(GUID::zeroed());
+        let member = Member::new("Count", MemberKind::Get, Effect::Idempotent);
+        let cache = CallCache::new();
+        let dry_run = DryRun::new();
+
+        let check = || {
+            assert_eq!(Member::current(), Some(member));
+            assert!(dry_run::Carried::current().is_some());
+            assert_eq!(RetryPolicy::current(), RetryPolicy::never());
+            assert_eq!(Conversion::current(), Conversion::Strict);
+            assert!(deadline::current().is_some());
+
+            // A call dispatched while waiting sees none of the scopes of the call
+            detached(|| {
+                assert_eq!(Member::current(), None);
+                assert!(dry_run::Carried::current().is_none());
+                assert_eq!(RetryPolicy::current(), RetryPolicy::global());
+                assert_eq!(Conversion::current(), Conversion::global());
+                assert_eq!(deadline::current(), None);
+                let result = member.scope(|| cache::call(&object, vec![], |_| Ok(1.to_variant())));
+                assert!(result.is_ok());
+            });
+            assert!(cache.is_empty());
+            assert_eq!(Member::current(), Some(member));
+            assert!(dry_run::Carried::current().is_some());
+        };
+        cache.scope(|| {
+            dry_run.scope(|| {
+                dry_run::object_scope(Some(&dry_run), || {
+                    conversion::object_scope(Some(Conversion::Widening), || {
+                        Conversion::Strict.scope(|| {
+                            RetryPolicy::default().scope(|| {
+                                retry::never(|| {
+                                    deadline::object_scope(Some(Duration::from_secs(1)), || {
+                                        member.scope(check);
+                                    });
+                                });
+                            });
+                        });
+                    });
+                });
+            });
+        });
+    }
+}
@@ -0,0 +1,121 @@
+//! What applies to calls made on an object with a timeout.
+
+// Off Windows, the Win32 functions that com-shim calls are faked
+extern crate com_shim_fake_win32;
+
+use std::{cell::Cell, thread, time::Duration};
+
+use com_shim::{
+    ComObject, DryRun, HasIDispatch, IDispatch, IDispatchExt, Result, RetryPolicy, TimeoutError,
+    ToVariant, WithDeadline, WithDryRun, com_object, com_shim,
+};
+use windows::Win32::Foundation::{E_FAIL, RPC_E_TIMEOUT};
+
+/// A desk whose `Wait` takes as long as it is asked to, and whose `Save` fails until it has been
+/// called `failures` times.
+#[derive(Default)]
+struct Desk {
+    failures: Cell<u32>,
+    saves: Cell<u32>,
+}
+
+com_object! {
+    impl Desk {
+        #[com(method)]
+        fn wait(&self, milliseconds: u32) -> u32 {
+            thread::sleep(Duration::from_millis(milliseconds.into()));
+            milliseconds
+        }
+
+        #[com(method)]
+        fn save(&self) -> Result<u32> {
+            self.saves.set(self.saves.get() + 1);
+            if self.saves.get() <= self.failures.get() {
+                return Err(E_FAIL.into());
+            }
+            Ok(self.saves.get())
+        }
+
+        #[com(method)]
+        fn echo(&self, object: IDispatch) -> IDispatch {
+            object
+        }
+    }
+}
+
+com_shim! {
+    struct DeskClass {
+        fn Wait(u32) -> u32,
+        #[com(idempotent)]
+        fn Save() -> u32,
+        fn Echo(IDispatch) -> IDispatch,
+    }
+}
+
+fn desk(failures: u32, timeout: Duration) -> WithDeadline<DeskClass> {
+    let desk = Desk {
+        failures: Cell::new(failures),
+        ..Desk::default()
+    };
+    WithDeadline::new(DeskClass::from(desk.into_idispatch()), timeout)
+}
+
+#[test]
+fn slow_calls_time_out_naming_the_member() -> Result<()> {
+    let desk = desk(0, Duration::from_millis(100));
+    assert_eq!(desk.wait(0)?, 0);
+
+    let error = desk.wait(400).unwrap_err();
+    let timeout = TimeoutError::from_error(&error).unwrap();
+    assert_eq!(timeout.member(), "Wait");
+    assert_eq!(timeout.timeout(), Duration::from_millis(100));
+
+    // Calls by name also have the timeout
+    let error = desk.call("Save", vec![]).unwrap_err();
+    assert_eq!(error.code(), RPC_E_TIMEOUT);
+    assert_eq!(TimeoutError::from_error(&error).unwrap().member(), "Save");
+
+    // Once the slow call returns, calls are made again
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(
+        desk.call("Wait", vec![5_u32.to_variant()])?,
+        5_u32.to_variant()
+    );
+    Ok(())
+}
+
+#[test]
+fn calls_with_a_timeout_are_retried() -> Result<()> {
+    let desk = desk(2, Duration::from_secs(5));
+    // `Save` is idempotent, which the retry sees though the attempts are made on a worker
+    let policy = RetryPolicy::default()
+        .backoff(Duration::ZERO, Duration::ZERO)
+        .retry_idempotent_on([E_FAIL]);
+    assert_eq!(policy.scope(|| desk.save())?, 3);
+    Ok(())
+}
+
+#[test]
+fn objects_are_passed_to_and_from_calls_with_a_timeout() -> Result<()> {
+    let desk = desk(0, Duration::from_secs(5));
+    let other = Desk::default().into_idispatch();
+    let echoed = desk.echo(other.clone())?;
+    assert_eq!(echoed, other);
+    assert_eq!(DeskClass::from(echoed).wait(1)?, 1);
+    Ok(())
+}
+
+#[test]
+fn dry_runs_record_calls_with_a_timeout() -> Result<()> {
+    let dry_run = DryRun::new();
+    let desk = desk(0, Duration::from_millis(100));
+
+    // The call is recorded instead of being made, so does not time out
+    dry_run.scope(|| desk.wait(400))?;
+    assert_eq!(dry_run.changes().len(), 1);
+
+    // An object in a dry run keeps its timeout
+    let desk = WithDryRun::new(desk, dry_run);
+    assert_eq!(desk.deadline(), Some(Duration::from_millis(100)));
+    Ok(())
+}
@@ -0,0 +1,155 @@
+//! What applies to calls made on a `ComThread` through a `Remote` handle.
+
+// Off Windows, the Win32 functions that com-shim calls are faked
+extern crate com_shim_fake_win32;
+
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
+
+use com_shim::{
+    ComObject, ComThread, Conversion, DryRun, FromVariant, IDispatch, Result, RetryPolicy,
+    VariantExt, com_object, com_shim,
+};
+use windows::Win32::Foundation::{DISP_E_TYPEMISMATCH, E_FAIL};
+
+/// A form whose `Text` starts as "42", whose `Ticket` counts how often it has been read, and whose
+/// `Save` fails until it has been called `failures` times.
+#[derive(Default)]
+struct Form {
+    text: RefCell<String>,
+    tickets: Cell<u32>,
+    failures: Cell<u32>,
+    saves: Cell<u32>,
+}
+
+com_object! {
+    impl Form {
+        #[com(get)]
+        fn text(&self) -> String {
+            self.text.borrow().clone()
+        }
+
+        #[com(set)]
+        fn set_text(&self, text: String) {
+            *self.text.borrow_mut() = text;
+        }
+
+        #[com(get)]
+        fn ticket(&self) -> u32 {
+            self.tickets.set(self.tickets.get() + 1);
+            self.tickets.get()
+        }
+
+        #[com(method)]
+        fn fill(&self, rows: Vec<i32>) -> usize {
+            rows.len()
+        }
+
+        #[com(method)]
+        fn attach(&self, objects: Vec<IDispatch>) -> usize {
+            objects.len()
+        }
+
+        #[com(method)]
+        fn save(&self) -> Result<u32> {
+            self.saves.set(self.saves.get() + 1);
+            if self.saves.get() <= self.failures.get() {
+                return Err(E_FAIL.into());
+            }
+            Ok(self.saves.get())
+        }
+    }
+}
+
+com_shim! {
+    #[com(remote)]
+    struct FormClass {
+        mut Text: i32,
+        #[com(mutating)]
+        Ticket: u32,
+        #[com(idempotent)]
+        fn Save() -> u32,
+        fn Fill(Vec<i32>) -> usize,
+        fn Attach(Vec<IDispatch>) -> usize,
+    }
+}
+
+fn form(failures: u32) -> Result<(ComThread, com_shim::Remote<FormClass>)> {
+    let thread = ComThread::spawn()?;
+    let form = thread.create(move || {
+        let form = Form {
+            text: RefCell::new("42".to_string()),
+            failures: Cell::new(failures),
+            ..Form::default()
+        };
+        Ok(FormClass::from(form.into_idispatch()))
+    })?;
+    Ok((thread, form))
+}
+
+#[test]
+fn dry_runs_apply_to_remote_calls() -> Result<()> {
+    let (_thread, form) = form(0)?;
+    let dry_run = DryRun::new();
+    dry_run.scope(|| -> Result<()> {
+        form.set_text(7)?;
+        assert_eq!(form.text()?, 42);
+        // Reading `Ticket` changes it, so it is recorded instead
+        assert_eq!(form.ticket()?, 0);
+        Ok(())
+    })?;
+    let report = dry_run
+        .changes()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(report, ["`Text` = 7", "`Ticket`()"]);
+
+    // The recorded calls were not made
+    assert_eq!(form.text()?, 42);
+    assert_eq!(form.ticket()?, 1);
+    Ok(())
+}
+
+#[test]
+fn arrays_are_sent_back_from_remote_dry_runs() -> Result<()> {
+    let (thread, form) = form(0)?;
+    let other = thread.create(|| Ok(Form::default().into_idispatch()))?;
+    let dry_run = DryRun::new();
+    dry_run.scope(|| -> Result<()> {
+        form.fill(vec![1, 2, 3])?;
+        form.attach(vec![other])?;
+        Ok(())
+    })?;
+
+    // An array of values is sent back, and one holding objects is left on the thread
+    let changes = dry_run.changes();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(
+        Vec::<i32>::from_variant(&changes[0].arguments()[0])?,
+        [1, 2, 3]
+    );
+    assert!(changes[1].arguments()[0].is_nothing());
+    Ok(())
+}
+
+#[test]
+fn conversions_apply_to_remote_calls() -> Result<()> {
+    let (_thread, form) = form(0)?;
+    assert_eq!(form.text()?, 42);
+    let error = Conversion::Strict.scope(|| form.text()).unwrap_err();
+    assert_eq!(error.code(), DISP_E_TYPEMISMATCH);
+    Ok(())
+}
+
+#[test]
+fn retry_policies_apply_to_remote_calls() -> Result<()> {
+    let (_thread, form) = form(2)?;
+    let policy = RetryPolicy::default()
+        .backoff(Duration::from_millis(1), Duration::from_millis(5))
+        .retry_idempotent_on([E_FAIL]);
+    assert_eq!(policy.scope(|| form.save())?, 3);
+    Ok(())
+}
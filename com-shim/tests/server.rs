@@ -0,0 +1,95 @@
+//! How objects exposed with `com_object!` report errors to their callers.
+
+// Off Windows, the Win32 functions that com-shim calls are faked
+extern crate com_shim_fake_win32;
+
+use std::mem::ManuallyDrop;
+
+use com_shim::{ComObject, IDispatch, IDispatchExt, Result, ToVariant, com_object};
+use windows::{
+    Win32::{
+        Foundation::{DISP_E_BADPARAMCOUNT, DISP_E_EXCEPTION, E_FAIL, E_UNEXPECTED},
+        System::Com::{DISPATCH_METHOD, DISPPARAMS, EXCEPINFO},
+    },
+    core::{Error, GUID, HSTRING, PCWSTR},
+};
+
+/// An object whose methods fail.
+struct Broken;
+
+com_object! {
+    impl Broken {
+        #[com(method)]
+        fn fail(&self) -> Result<()> {
+            Err(Error::new(E_FAIL, "broken".into()))
+        }
+
+        #[com(method)]
+        fn panic(&self) {
+            panic!("broken");
+        }
+    }
+}
+
+/// Invoke a method without arguments, returning the exception it raises.
+fn invoke(object: &IDispatch, name: &str) -> (Result<()>, EXCEPINFO) {
+    let dispid = dispid(object, name);
+    let mut exception = EXCEPINFO::default();
+    // SAFETY: every pointer is valid for the call.
+    let result = unsafe {
+        object.Invoke(
+            dispid,
+            &GUID::zeroed(),
+            0,
+            DISPATCH_METHOD,
+            &DISPPARAMS::default(),
+            None,
+            Some(&raw mut exception),
+            None,
+        )
+    };
+    (result, exception)
+}
+
+/// Find the `DISPID` of a member.
+fn dispid(object: &IDispatch, name: &str) -> i32 {
+    let name = HSTRING::from(name);
+    let names = [PCWSTR(name.as_ptr())];
+    let mut dispid = 0;
+    // SAFETY: one name is passed, with room for one `DISPID`.
+    unsafe { object.GetIDsOfNames(&GUID::zeroed(), names.as_ptr(), 1, 0, &raw mut dispid) }
+        .expect("the member exists");
+    dispid
+}
+
+#[test]
+fn errors_are_raised_as_exceptions() {
+    let object = Broken.into_idispatch();
+    let (result, exception) = invoke(&object, "Fail");
+    assert_eq!(result.unwrap_err().code(), DISP_E_EXCEPTION);
+    assert_eq!(exception.scode, E_FAIL.0);
+    let source = ManuallyDrop::into_inner(exception.bstrSource);
+    assert!(source.to_string().ends_with("Broken"), "{source}");
+    drop(ManuallyDrop::into_inner(exception.bstrDescription));
+
+    // Callers read the code of the exception
+    assert_eq!(object.call("Fail", vec![]).unwrap_err().code(), E_FAIL);
+}
+
+#[test]
+fn panics_are_raised_as_exceptions() {
+    let object = Broken.into_idispatch();
+    let (result, exception) = invoke(&object, "Panic");
+    assert_eq!(result.unwrap_err().code(), DISP_E_EXCEPTION);
+    assert_eq!(exception.scode, E_UNEXPECTED.0);
+    let description = ManuallyDrop::into_inner(exception.bstrDescription);
+    assert_eq!(description, "broken");
+    drop(ManuallyDrop::into_inner(exception.bstrSource));
+}
+
+#[test]
+fn calls_that_cannot_be_made_are_not_exceptions() {
+    let object = Broken.into_idispatch();
+    let error = object.call("Fail", vec![1.to_variant()]).unwrap_err();
+    assert_eq!(error.code(), DISP_E_BADPARAMCOUNT);
+}
@@ -0,0 +1,85 @@
+//! Variants passed to and returned from calls are freed.
+
+// Off Windows, the Win32 functions that com-shim calls are faked
+extern crate com_shim_fake_win32;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use com_shim::{
+    ComObject, FromVariant, IDispatch, IDispatchExt, Result, ToVariant, Variant, com_object,
+};
+
+/// Counts the allocations that have not been freed, on each thread, so that the test harness
+/// allocating on another thread does not change the count.
+struct Counting;
+
+thread_local! {
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+}
+
+// SAFETY: allocations are made by the system allocator.
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.with(|live| live.set(live.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.with(|live| live.set(live.get() - 1));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+/// Returns its argument from `Echo`, and a new string from `Name`.
+struct Fake;
+
+impl Drop for Fake {
+    fn drop(&mut self) {
+        DROPPED.store(true, Ordering::SeqCst);
+    }
+}
+
+com_object! {
+    impl Fake {
+        #[com(method)]
+        fn echo(&self, value: Variant) -> Variant {
+            value
+        }
+
+        #[com(get)]
+        fn name(&self) -> String {
+            "Fake".to_string()
+        }
+    }
+}
+
+#[test]
+fn calls_do_not_leak() -> Result<()> {
+    let object: IDispatch = Fake.into_idispatch();
+    // Warm up anything allocated once, such as by logging
+    let _ = object.call("Echo", vec![object.get("Name")?])?;
+
+    let live = LIVE.get();
+    for _ in 0..1_000 {
+        let name = object.get("Name")?;
+        let echoed = object.call("Echo", vec![name.clone()])?;
+        assert_eq!(String::from_variant(&echoed)?, "Fake");
+
+        let echoed = object.call("Echo", vec![object.to_variant()])?;
+        let _ = IDispatch::from_variant(&echoed)?;
+    }
+    assert_eq!(LIVE.get(), live);
+
+    drop(object);
+    assert!(DROPPED.load(Ordering::SeqCst));
+    Ok(())
+}
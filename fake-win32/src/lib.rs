@@ -0,0 +1,696 @@
+//! Pure-Rust implementations of the Win32 functions used by com-shim and `windows-core`.
+//!
+//! On platforms other than Windows these symbols do not exist, so this crate provides them,
+//! allowing com-shim to be tested against in-process fake objects. They belong to the operating
+//! system, so this crate is only a dev-dependency of com-shim, and is never published. Strings,
+//! arrays and error information are allocated with the Rust global allocator.
+
+#![cfg(not(windows))]
+#![warn(clippy::pedantic)]
+#![allow(non_snake_case, clippy::missing_safety_doc, clippy::missing_panics_doc)]
+// Every allocation here is aligned for the values stored in it
+#![allow(clippy::cast_ptr_alignment)]
+
+use std::{
+    alloc::{Layout, alloc, alloc_zeroed, dealloc},
+    cell::RefCell,
+    ffi::{CStr, c_void},
+    mem::{self, ManuallyDrop},
+    ptr,
+};
+
+use windows::{
+    Win32::{
+        Foundation::{
+            DISP_E_ARRAYISLOCKED, DISP_E_BADINDEX, DISP_E_BADVARTYPE, E_INVALIDARG, E_OUTOFMEMORY,
+            E_UNEXPECTED, S_FALSE, S_OK,
+        },
+        System::{
+            Com::{FADF_HAVEVARTYPE, IErrorInfo, IErrorInfo_Impl, SAFEARRAY, SAFEARRAYBOUND},
+            Variant::{
+                VARENUM, VARIANT, VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF, VT_CY, VT_DATE, VT_DECIMAL,
+                VT_DISPATCH, VT_ERROR, VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_R4, VT_R8,
+                VT_TYPEMASK, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UINT, VT_UNKNOWN, VT_VARIANT,
+            },
+            WinRT::{IRestrictedErrorInfo, IRestrictedErrorInfo_Impl},
+        },
+    },
+    core::{BSTR, GUID, HRESULT, HSTRING, Interface, Result, implement},
+};
+
+// Strings
+
+/// The byte length of a `BSTR` is stored in the four bytes before it.
+const BSTR_PREFIX: usize = mem::size_of::<u32>();
+
+fn bstr_layout(bytes: usize) -> Layout {
+    // Strings are followed by a two byte terminator
+    Layout::from_size_align(BSTR_PREFIX + bytes + 2, mem::align_of::<u32>())
+        .expect("BSTR layout is valid")
+}
+
+unsafe fn alloc_bstr(data: *const u8, bytes: usize) -> *mut u16 {
+    let Ok(len) = u32::try_from(bytes) else {
+        return ptr::null_mut();
+    };
+    unsafe {
+        let base = alloc(bstr_layout(bytes));
+        if base.is_null() {
+            return ptr::null_mut();
+        }
+        base.cast::<u32>().write(len);
+        let string = base.add(BSTR_PREFIX);
+        if data.is_null() {
+            ptr::write_bytes(string, 0, bytes);
+        } else {
+            ptr::copy_nonoverlapping(data, string, bytes);
+        }
+        string.add(bytes).cast::<[u8; 2]>().write_unaligned([0, 0]);
+        string.cast()
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SysAllocStringLen(strin: *const u16, ui: u32) -> *mut u16 {
+    unsafe { alloc_bstr(strin.cast(), ui as usize * 2) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SysAllocStringByteLen(psz: *const u8, len: u32) -> *mut u16 {
+    unsafe { alloc_bstr(psz, len as usize) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SysFreeString(bstrstring: *mut u16) {
+    if bstrstring.is_null() {
+        return;
+    }
+    unsafe {
+        let bytes = SysStringByteLen(bstrstring) as usize;
+        dealloc(bstrstring.cast::<u8>().sub(BSTR_PREFIX), bstr_layout(bytes));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SysStringByteLen(bstr: *const u16) -> u32 {
+    if bstr.is_null() {
+        return 0;
+    }
+    unsafe { bstr.cast::<u8>().sub(BSTR_PREFIX).cast::<u32>().read() }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SysStringLen(pbstr: *const u16) -> u32 {
+    unsafe { SysStringByteLen(pbstr) / 2 }
+}
+
+// Heap allocations
+
+/// The size of heap allocations is stored before them, so that they can be freed.
+const HEAP_PREFIX: usize = 16;
+const HEAP_ZERO_MEMORY: u32 = 8;
+
+fn heap_layout(bytes: usize) -> Layout {
+    Layout::from_size_align(HEAP_PREFIX + bytes, HEAP_PREFIX).expect("heap layout is valid")
+}
+
+unsafe fn heap_alloc(bytes: usize, zeroed: bool) -> *mut c_void {
+    unsafe {
+        let layout = heap_layout(bytes);
+        let base = if zeroed {
+            alloc_zeroed(layout)
+        } else {
+            alloc(layout)
+        };
+        if base.is_null() {
+            return ptr::null_mut();
+        }
+        base.cast::<usize>().write(bytes);
+        base.add(HEAP_PREFIX).cast()
+    }
+}
+
+unsafe fn heap_free(mem: *const c_void) {
+    if mem.is_null() {
+        return;
+    }
+    unsafe {
+        let base = mem.cast::<u8>().sub(HEAP_PREFIX).cast_mut();
+        dealloc(base, heap_layout(base.cast::<usize>().read()));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn GetProcessHeap() -> isize {
+    1
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn HeapAlloc(
+    _hheap: isize,
+    dwflags: u32,
+    dwbytes: usize,
+) -> *mut c_void {
+    unsafe { heap_alloc(dwbytes, dwflags & HEAP_ZERO_MEMORY != 0) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn HeapFree(_hheap: isize, _dwflags: u32, lpmem: *const c_void) -> i32 {
+    unsafe { heap_free(lpmem) };
+    1
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn CoTaskMemAlloc(cb: usize) -> *mut c_void {
+    unsafe { heap_alloc(cb, false) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn CoTaskMemFree(pv: *const c_void) {
+    unsafe { heap_free(pv) };
+}
+
+// Errors and libraries
+
+/// Error information describing the most recent failure on a thread, as `RoOriginateError`
+/// creates it.
+#[implement(IErrorInfo, IRestrictedErrorInfo)]
+struct ErrorInfo {
+    code: HRESULT,
+    description: BSTR,
+}
+
+impl IErrorInfo_Impl for ErrorInfo {
+    fn GetGUID(&self) -> Result<GUID> {
+        Ok(GUID::zeroed())
+    }
+
+    fn GetSource(&self) -> Result<BSTR> {
+        Ok(BSTR::new())
+    }
+
+    fn GetDescription(&self) -> Result<BSTR> {
+        Ok(self.description.clone())
+    }
+
+    fn GetHelpFile(&self) -> Result<BSTR> {
+        Ok(BSTR::new())
+    }
+
+    fn GetHelpContext(&self) -> Result<u32> {
+        Ok(0)
+    }
+}
+
+impl IRestrictedErrorInfo_Impl for ErrorInfo {
+    fn GetErrorDetails(
+        &self,
+        description: *mut BSTR,
+        error: *mut HRESULT,
+        restricteddescription: *mut BSTR,
+        capabilitysid: *mut BSTR,
+    ) -> Result<()> {
+        unsafe {
+            description.write(self.description.clone());
+            error.write(self.code);
+            restricteddescription.write(self.description.clone());
+            capabilitysid.write(BSTR::new());
+        }
+        Ok(())
+    }
+
+    fn GetReference(&self) -> Result<BSTR> {
+        Ok(BSTR::new())
+    }
+}
+
+thread_local! {
+    /// The error information set on this thread, which `GetErrorInfo` takes.
+    static ERROR_INFO: RefCell<Option<IErrorInfo>> = const { RefCell::new(None) };
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn GetErrorInfo(
+    _dwreserved: u32,
+    pperrinfo: *mut *mut c_void,
+) -> HRESULT {
+    let info = ERROR_INFO.take();
+    let found = info.is_some();
+    unsafe { pperrinfo.write(info.map_or(ptr::null_mut(), IErrorInfo::into_raw)) };
+    if found { S_OK } else { S_FALSE }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SetErrorInfo(_dwreserved: u32, perrinfo: *mut c_void) -> HRESULT {
+    let info = unsafe { IErrorInfo::from_raw_borrowed(&perrinfo) }.cloned();
+    ERROR_INFO.set(info);
+    S_OK
+}
+
+/// Set error information describing `error` on this thread, as `Error::new` expects.
+extern "system" fn RoOriginateError(error: HRESULT, message: *mut c_void) -> i32 {
+    // SAFETY: the message is a borrowed `HSTRING`, which is a pointer to its header.
+    let message = unsafe { ManuallyDrop::new(mem::transmute::<*mut c_void, HSTRING>(message)) };
+    let info: IErrorInfo = ErrorInfo {
+        code: error,
+        description: BSTR::from_wide(message.as_wide()).unwrap_or_default(),
+    }
+    .into();
+    ERROR_INFO.set(Some(info));
+    1
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn GetLastError() -> u32 {
+    0
+}
+
+const FORMAT_MESSAGE_ALLOCATE_BUFFER: u32 = 0x100;
+
+/// No messages are available, so this always returns an empty message.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn FormatMessageW(
+    dwflags: u32,
+    _lpsource: *const c_void,
+    _dwmessageid: u32,
+    _dwlanguageid: u32,
+    lpbuffer: *mut u16,
+    _nsize: u32,
+    _arguments: *const *const i8,
+) -> u32 {
+    unsafe {
+        if dwflags & FORMAT_MESSAGE_ALLOCATE_BUFFER != 0 {
+            // Callers read the message from the buffer even when it is empty
+            lpbuffer
+                .cast::<*mut c_void>()
+                .write(heap_alloc(mem::size_of::<u16>(), true));
+        } else if !lpbuffer.is_null() {
+            lpbuffer.write(0);
+        }
+    }
+    0
+}
+
+/// A handle to the only library functions are loaded from, `combase.dll`.
+const COMBASE: isize = 1;
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn LoadLibraryExA(
+    lplibfilename: *const u8,
+    _hfile: isize,
+    _dwflags: u32,
+) -> isize {
+    let name = unsafe { CStr::from_ptr(lplibfilename.cast()) };
+    if name.to_bytes().eq_ignore_ascii_case(b"combase.dll") {
+        COMBASE
+    } else {
+        0
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn GetProcAddress(hmodule: isize, lpprocname: *const u8) -> *mut c_void {
+    let name = unsafe { CStr::from_ptr(lpprocname.cast()) };
+    if hmodule == COMBASE && name.to_bytes() == b"RoOriginateError" {
+        RoOriginateError as *mut c_void
+    } else {
+        ptr::null_mut()
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn FreeLibrary(_hlibmodule: isize) -> i32 {
+    1
+}
+
+// Variants
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn VariantInit(pvarg: *mut VARIANT) {
+    unsafe { pvarg.write(VARIANT::default()) };
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn VariantClear(pvarg: *mut VARIANT) -> HRESULT {
+    unsafe {
+        let v00 = &mut *(*pvarg).Anonymous.Anonymous;
+        let vt = v00.vt;
+        if vt.0 & VT_BYREF.0 == 0 {
+            if vt.0 & VT_ARRAY.0 != 0 {
+                let hr = SafeArrayDestroy(v00.Anonymous.parray);
+                if hr.is_err() {
+                    return hr;
+                }
+            } else {
+                match vt {
+                    VT_BSTR => SysFreeString(mem::transmute_copy(&v00.Anonymous.bstrVal)),
+                    VT_DISPATCH => ManuallyDrop::drop(&mut v00.Anonymous.pdispVal),
+                    VT_UNKNOWN => ManuallyDrop::drop(&mut v00.Anonymous.punkVal),
+                    _ => (),
+                }
+            }
+        }
+        pvarg.write(VARIANT::default());
+    }
+    S_OK
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn VariantCopy(
+    pvargdest: *mut VARIANT,
+    pvargsrc: *const VARIANT,
+) -> HRESULT {
+    unsafe {
+        if ptr::eq(pvargdest, pvargsrc) {
+            return S_OK;
+        }
+        let hr = VariantClear(pvargdest);
+        if hr.is_err() {
+            return hr;
+        }
+        let mut copy: VARIANT = ptr::read(pvargsrc);
+        let v00 = &mut *copy.Anonymous.Anonymous;
+        let vt = v00.vt;
+        if vt.0 & VT_BYREF.0 == 0 {
+            if vt.0 & VT_ARRAY.0 != 0 {
+                let mut parray = ptr::null_mut();
+                let hr = SafeArrayCopy(v00.Anonymous.parray, &raw mut parray);
+                if hr.is_err() {
+                    return hr;
+                }
+                v00.Anonymous.parray = parray;
+            } else {
+                match vt {
+                    VT_BSTR => {
+                        let source: *const u16 = mem::transmute_copy(&v00.Anonymous.bstrVal);
+                        let bstr = copy_bstr(source);
+                        if bstr.is_null() && !source.is_null() {
+                            return E_OUTOFMEMORY;
+                        }
+                        v00.Anonymous.bstrVal =
+                            mem::transmute::<*mut u16, ManuallyDrop<BSTR>>(bstr);
+                    }
+                    VT_DISPATCH => {
+                        v00.Anonymous.pdispVal =
+                            ManuallyDrop::new((*v00.Anonymous.pdispVal).clone());
+                    }
+                    VT_UNKNOWN => {
+                        v00.Anonymous.punkVal = ManuallyDrop::new((*v00.Anonymous.punkVal).clone());
+                    }
+                    _ => (),
+                }
+            }
+        }
+        pvargdest.write(copy);
+    }
+    S_OK
+}
+
+unsafe fn copy_bstr(bstr: *const u16) -> *mut u16 {
+    if bstr.is_null() {
+        return ptr::null_mut();
+    }
+    unsafe { alloc_bstr(bstr.cast(), SysStringByteLen(bstr) as usize) }
+}
+
+// Arrays
+
+/// The `VARTYPE` of an array is stored in the 16 bytes before it, as with `FADF_HAVEVARTYPE`.
+const ARRAY_PREFIX: usize = 16;
+
+fn element_size(vt: VARENUM) -> Option<usize> {
+    Some(match vt {
+        VT_I1 | VT_UI1 => 1,
+        VT_I2 | VT_UI2 | VT_BOOL => 2,
+        VT_I4 | VT_UI4 | VT_INT | VT_UINT | VT_R4 | VT_ERROR => 4,
+        VT_I8 | VT_UI8 | VT_R8 | VT_CY | VT_DATE => 8,
+        VT_DECIMAL => 16,
+        VT_BSTR | VT_DISPATCH | VT_UNKNOWN => mem::size_of::<*mut c_void>(),
+        VT_VARIANT => mem::size_of::<VARIANT>(),
+        _ => return None,
+    })
+}
+
+fn header_layout(dims: usize) -> Layout {
+    let size = ARRAY_PREFIX
+        + mem::size_of::<SAFEARRAY>()
+        + dims.saturating_sub(1) * mem::size_of::<SAFEARRAYBOUND>();
+    Layout::from_size_align(size, ARRAY_PREFIX).expect("SAFEARRAY layout is valid")
+}
+
+fn data_layout(size: usize, count: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_mul(count)?.max(1), 8).ok()
+}
+
+/// The bounds of a `SAFEARRAY`, which are stored in reverse order.
+unsafe fn bounds<'a>(psa: *const SAFEARRAY) -> &'a [SAFEARRAYBOUND] {
+    unsafe {
+        std::slice::from_raw_parts(
+            (&raw const (*psa).rgsabound).cast::<SAFEARRAYBOUND>(),
+            usize::from((*psa).cDims),
+        )
+    }
+}
+
+unsafe fn element_count(psa: *const SAFEARRAY) -> usize {
+    unsafe { bounds(psa).iter().map(|b| b.cElements as usize).product() }
+}
+
+unsafe fn vartype(psa: *const SAFEARRAY) -> VARENUM {
+    unsafe { psa.cast::<u8>().sub(ARRAY_PREFIX).cast::<VARENUM>().read() }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayCreate(
+    vt: VARENUM,
+    cdims: u32,
+    rgsabound: *const SAFEARRAYBOUND,
+) -> *mut SAFEARRAY {
+    let (Some(size), Ok(dims)) = (element_size(vt), u16::try_from(cdims)) else {
+        return ptr::null_mut();
+    };
+    if dims == 0 {
+        return ptr::null_mut();
+    }
+    unsafe {
+        let requested = std::slice::from_raw_parts(rgsabound, usize::from(dims));
+        let count = requested
+            .iter()
+            .try_fold(1_usize, |acc, b| acc.checked_mul(b.cElements as usize));
+        let Some(layout) = count.and_then(|count| data_layout(size, count)) else {
+            return ptr::null_mut();
+        };
+
+        let base = alloc_zeroed(header_layout(usize::from(dims)));
+        if base.is_null() {
+            return ptr::null_mut();
+        }
+        let data = alloc_zeroed(layout);
+        if data.is_null() {
+            dealloc(base, header_layout(usize::from(dims)));
+            return ptr::null_mut();
+        }
+        base.cast::<VARENUM>().write(vt);
+
+        let psa = base.add(ARRAY_PREFIX).cast::<SAFEARRAY>();
+        (*psa).cDims = dims;
+        (*psa).fFeatures = FADF_HAVEVARTYPE;
+        (*psa).cbElements = u32::try_from(size).expect("element sizes are small");
+        (*psa).cLocks = 0;
+        (*psa).pvData = data.cast();
+        let stored = (&raw mut (*psa).rgsabound).cast::<SAFEARRAYBOUND>();
+        for (i, bound) in requested.iter().rev().enumerate() {
+            stored.add(i).write(*bound);
+        }
+        psa
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayDestroy(psa: *const SAFEARRAY) -> HRESULT {
+    if psa.is_null() {
+        return S_OK;
+    }
+    unsafe {
+        if (*psa).cLocks > 0 {
+            return DISP_E_ARRAYISLOCKED;
+        }
+        let vt = vartype(psa);
+        let size = (*psa).cbElements as usize;
+        let count = element_count(psa);
+        let data = (*psa).pvData.cast::<u8>();
+        for i in 0..count {
+            let element = data.add(i * size);
+            match vt {
+                VT_VARIANT => {
+                    let _ = VariantClear(element.cast());
+                }
+                VT_BSTR => SysFreeString(element.cast::<*mut u16>().read()),
+                VT_DISPATCH | VT_UNKNOWN => {
+                    drop(element.cast::<Option<windows::core::IUnknown>>().read());
+                }
+                _ => (),
+            }
+        }
+        dealloc(
+            data,
+            data_layout(size, count).expect("layout was valid when allocated"),
+        );
+        let dims = usize::from((*psa).cDims);
+        dealloc(
+            psa.cast::<u8>().sub(ARRAY_PREFIX).cast_mut(),
+            header_layout(dims),
+        );
+    }
+    S_OK
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayCopy(
+    psa: *const SAFEARRAY,
+    ppsaout: *mut *mut SAFEARRAY,
+) -> HRESULT {
+    unsafe {
+        ppsaout.write(ptr::null_mut());
+        if psa.is_null() {
+            return S_OK;
+        }
+        let vt = vartype(psa);
+        let requested: Vec<SAFEARRAYBOUND> = bounds(psa).iter().rev().copied().collect();
+        let copy = SafeArrayCreate(vt, u32::from((*psa).cDims), requested.as_ptr());
+        if copy.is_null() {
+            return E_OUTOFMEMORY;
+        }
+        let size = (*psa).cbElements as usize;
+        let source = (*psa).pvData.cast::<u8>();
+        let dest = (*copy).pvData.cast::<u8>();
+        for i in 0..element_count(psa) {
+            let (from, to) = (source.add(i * size), dest.add(i * size));
+            match vt {
+                VT_VARIANT => {
+                    let hr = VariantCopy(to.cast(), from.cast());
+                    if hr.is_err() {
+                        let _ = SafeArrayDestroy(copy);
+                        return hr;
+                    }
+                }
+                VT_BSTR => to
+                    .cast::<*mut u16>()
+                    .write(copy_bstr(from.cast::<*const u16>().read())),
+                VT_DISPATCH | VT_UNKNOWN => to
+                    .cast::<Option<windows::core::IUnknown>>()
+                    .write((*from.cast::<Option<windows::core::IUnknown>>()).clone()),
+                _ => ptr::copy_nonoverlapping(from, to, size),
+            }
+        }
+        ppsaout.write(copy);
+    }
+    S_OK
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayAccessData(
+    psa: *const SAFEARRAY,
+    ppvdata: *mut *mut c_void,
+) -> HRESULT {
+    if psa.is_null() {
+        return E_INVALIDARG;
+    }
+    unsafe {
+        let psa = psa.cast_mut();
+        (*psa).cLocks += 1;
+        ppvdata.write((*psa).pvData);
+    }
+    S_OK
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayUnaccessData(psa: *const SAFEARRAY) -> HRESULT {
+    if psa.is_null() {
+        return E_INVALIDARG;
+    }
+    unsafe {
+        let psa = psa.cast_mut();
+        if (*psa).cLocks == 0 {
+            return E_UNEXPECTED;
+        }
+        (*psa).cLocks -= 1;
+    }
+    S_OK
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayGetDim(psa: *const SAFEARRAY) -> u32 {
+    if psa.is_null() {
+        return 0;
+    }
+    unsafe { u32::from((*psa).cDims) }
+}
+
+unsafe fn bound(psa: *const SAFEARRAY, ndim: u32) -> Option<SAFEARRAYBOUND> {
+    unsafe {
+        let bounds = bounds(psa);
+        let ndim = usize::try_from(ndim).ok()?;
+        (1..=bounds.len())
+            .contains(&ndim)
+            .then(|| bounds[bounds.len() - ndim])
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayGetLBound(
+    psa: *const SAFEARRAY,
+    ndim: u32,
+    pllbound: *mut i32,
+) -> HRESULT {
+    unsafe {
+        match bound(psa, ndim) {
+            Some(bound) => {
+                pllbound.write(bound.lLbound);
+                S_OK
+            }
+            None => DISP_E_BADINDEX,
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(clippy::cast_possible_wrap)] // matches the wrapping behaviour of oleaut32
+pub unsafe extern "system" fn SafeArrayGetUBound(
+    psa: *const SAFEARRAY,
+    ndim: u32,
+    plubound: *mut i32,
+) -> HRESULT {
+    unsafe {
+        match bound(psa, ndim) {
+            Some(bound) => {
+                plubound.write(
+                    bound
+                        .lLbound
+                        .wrapping_add(bound.cElements as i32)
+                        .wrapping_sub(1),
+                );
+                S_OK
+            }
+            None => DISP_E_BADINDEX,
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn SafeArrayGetVartype(
+    psa: *const SAFEARRAY,
+    pvt: *mut VARENUM,
+) -> HRESULT {
+    if psa.is_null() {
+        return E_INVALIDARG;
+    }
+    unsafe {
+        let vt = vartype(psa);
+        if vt.0 & !VT_TYPEMASK.0 != 0 {
+            return DISP_E_BADVARTYPE;
+        }
+        pvt.write(vt);
+    }
+    S_OK
+}